@@ -1,6 +1,6 @@
 use anyhow::Error;
 use futures::prelude::*;
-use rskafka::{Consumer, ConsumerConfig};
+use rskafka::{consumer::assignor, Consumer, ConsumerConfig};
 use tokio::signal;
 
 #[tokio::main]
@@ -9,11 +9,12 @@ async fn main() -> Result<(), Error> {
         .parse_filters("rskafka::consumer=trace,rskafka::fetch=trace,info") //
         .init();
 
-    let config = ConsumerConfig {
-        client_id: "rskafka-example".into(),
-        group_id: "rskafka-example".into(),
-        topics: vec!["rskafka-test".into()],
-    };
+    let config = ConsumerConfig::builder()
+        .client_id("rskafka-example".into())
+        .group_id("rskafka-example".into())
+        .topics(vec!["rskafka-test".into()])
+        .assignors(assignor::default_assignors())
+        .build()?;
 
     let consumer = Consumer::bootstrap("localhost:9092", config).await?;
     let (killswitch, assignment_stream) = consumer.split();
@@ -0,0 +1,56 @@
+use anyhow::Error;
+use futures::prelude::*;
+use rskafka::{KafkaPartition, StandaloneConsumer, StandaloneConsumerConfig, StartPosition};
+use std::time::Duration;
+use tokio::signal;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    env_logger::Builder::new()
+        .parse_filters("rskafka::consumer=trace,info")
+        .init();
+
+    let config = StandaloneConsumerConfig {
+        client_id: "rskafka-example".into(),
+        partitions: vec![(
+            KafkaPartition {
+                topic_name: "rskafka-test".into(),
+                partition_index: 0,
+            },
+            StartPosition::Earliest,
+        )],
+        group_id: None,
+        fetch: Default::default(),
+        retry_backoff: Duration::from_millis(100),
+        retry_backoff_max: Duration::from_secs(5),
+    };
+
+    let consumer = StandaloneConsumer::bootstrap("localhost:9092", config).await?;
+    let (killswitch, assignment_stream) = consumer.split();
+
+    // Register graceful shutdown procedure
+    tokio::spawn(async {
+        signal::ctrl_c().await?;
+        killswitch.shutdown().await;
+
+        Ok::<(), anyhow::Error>(())
+    });
+
+    let result = assignment_stream
+        .try_for_each(|assignment| async move {
+            assignment
+                .into_message_stream()
+                .for_each(|msg| async move { println!("{:#?}", msg) })
+                .await;
+            Ok(())
+        })
+        .await
+        .map_err(|e| e.0);
+
+    match result {
+        Ok(_) => println!("Finished"),
+        Err(e) => eprintln!("{:?}", e),
+    }
+
+    Ok(())
+}
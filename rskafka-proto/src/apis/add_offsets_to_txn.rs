@@ -0,0 +1,63 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct AddOffsetsToTxnRequestV1<'a> {
+    pub transactional_id: Cow<'a, str>,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    /// Consumer group whose offsets are committed with the transaction
+    pub group_id: Cow<'a, str>,
+}
+
+impl<'a> KafkaRequest for AddOffsetsToTxnRequestV1<'a> {
+    const API_KEY: ApiKey = ApiKey::AddOffsetsToTxn;
+    const API_VERSION: i16 = 1;
+    type Response = AddOffsetsToTxnResponseV1;
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct AddOffsetsToTxnResponseV1 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+}
+
+impl KafkaResponse for AddOffsetsToTxnResponseV1 {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn add_offsets_to_txn_request_v1() {
+        let request = AddOffsetsToTxnRequestV1 {
+            transactional_id: "tx".into(),
+            producer_id: 7,
+            producer_epoch: 3,
+            group_id: "g".into(),
+        };
+        let expected = hex_bytes("0002747800000000000000070003000167");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn add_offsets_to_txn_response_v1() {
+        let bytes = hex_bytes("000000000030");
+        let expected = AddOffsetsToTxnResponseV1 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::InvalidTxnState,
+        };
+
+        assert_eq!(
+            AddOffsetsToTxnResponseV1::from_wire_bytes(&bytes),
+            Ok(expected)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct AddPartitionsToTxnRequestV1<'a> {
+    pub transactional_id: Cow<'a, str>,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub topics: Vec<AddPartitionsToTxnTopic<'a>>,
+}
+
+impl<'a> KafkaRequest for AddPartitionsToTxnRequestV1<'a> {
+    const API_KEY: ApiKey = ApiKey::AddPartitionsToTxn;
+    const API_VERSION: i16 = 1;
+    type Response = AddPartitionsToTxnResponseV1;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct AddPartitionsToTxnTopic<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct AddPartitionsToTxnResponseV1 {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<AddPartitionsToTxnTopicResult>,
+}
+
+impl KafkaResponse for AddPartitionsToTxnResponseV1 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct AddPartitionsToTxnTopicResult {
+    pub name: String,
+    pub partitions: Vec<AddPartitionsToTxnPartitionResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct AddPartitionsToTxnPartitionResult {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn add_partitions_to_txn_request_v1() {
+        let request = AddPartitionsToTxnRequestV1 {
+            transactional_id: "tx".into(),
+            producer_id: 7,
+            producer_epoch: 3,
+            topics: vec![AddPartitionsToTxnTopic {
+                name: "t".into(),
+                partitions: vec![0, 2],
+            }],
+        };
+        let expected =
+            hex_bytes("000274780000000000000007000300000001000174000000020000000000000002");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn add_partitions_to_txn_response_v1() {
+        let bytes = hex_bytes("000000000000000100017400000001000000020033");
+        let expected = AddPartitionsToTxnResponseV1 {
+            throttle_time_ms: 0,
+            topics: vec![AddPartitionsToTxnTopicResult {
+                name: "t".into(),
+                partitions: vec![AddPartitionsToTxnPartitionResult {
+                    partition_index: 2,
+                    error_code: ErrorCode::ConcurrentTransactions,
+                }],
+            }],
+        };
+
+        assert_eq!(
+            AddPartitionsToTxnResponseV1::from_wire_bytes(&bytes),
+            Ok(expected)
+        );
+    }
+}
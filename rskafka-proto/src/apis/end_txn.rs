@@ -0,0 +1,60 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct EndTxnRequestV1<'a> {
+    pub transactional_id: Cow<'a, str>,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    /// True to commit transaction, false to abort it
+    pub committed: bool,
+}
+
+impl<'a> KafkaRequest for EndTxnRequestV1<'a> {
+    const API_KEY: ApiKey = ApiKey::EndTxn;
+    const API_VERSION: i16 = 1;
+    type Response = EndTxnResponseV1;
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct EndTxnResponseV1 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+}
+
+impl KafkaResponse for EndTxnResponseV1 {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn end_txn_request_v1() {
+        let request = EndTxnRequestV1 {
+            transactional_id: "tx".into(),
+            producer_id: 7,
+            producer_epoch: 3,
+            committed: true,
+        };
+        let expected = hex_bytes("000274780000000000000007000301");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn end_txn_response_v1() {
+        let bytes = hex_bytes("00000000002f");
+        let expected = EndTxnResponseV1 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::InvalidProducerEpoch,
+        };
+
+        assert_eq!(EndTxnResponseV1::from_wire_bytes(&bytes), Ok(expected));
+    }
+}
@@ -112,3 +112,340 @@ pub struct AbortedTransaction {
     pub producer_id: i64,
     pub first_offset: i64,
 }
+
+/// Sentinel session id/epoch values (KIP-227)
+pub const INVALID_SESSION_ID: i32 = 0;
+pub const INITIAL_SESSION_EPOCH: i32 = 0;
+pub const FINAL_SESSION_EPOCH: i32 = -1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct FetchRequestV7<'a> {
+    pub replica_id: i32,
+    pub max_wait_time: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    pub isolation_level: IsolationLevel,
+    pub session_id: i32,
+    pub session_epoch: i32,
+    pub topics: Vec<TopicFetchV7<'a>>,
+    pub forgotten_topics: Vec<ForgottenTopic<'a>>,
+}
+
+impl<'a> KafkaRequest for FetchRequestV7<'a> {
+    const API_KEY: ApiKey = ApiKey::Fetch;
+    const API_VERSION: i16 = 7;
+    type Response = FetchResponseV7;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct TopicFetchV7<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<PartitionFetchV7>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct PartitionFetchV7 {
+    pub index: i32,
+    pub fetch_offset: i64,
+    pub log_start_offset: i64,
+    pub partition_max_bytes: i32,
+}
+
+/// Partitions removed from incremental fetch session
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ForgottenTopic<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponseV7 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub session_id: i32,
+    pub topics: Vec<FetchResponseTopicV7>,
+}
+
+impl KafkaResponse for FetchResponseV7 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponseTopicV7 {
+    pub name: String,
+    pub partitions: Vec<FetchResponsePartitionV7>,
+}
+
+#[derive(Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponsePartitionV7 {
+    pub index: i32,
+    pub error_code: ErrorCode,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    pub aborted_transactions: Vec<AbortedTransaction>,
+    pub record_set: Vec<u8>,
+}
+
+impl std::fmt::Debug for FetchResponsePartitionV7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FetchResponsePartitionV7")
+            .field("index", &self.index)
+            .field("error_code", &self.error_code)
+            .field("high_watermark", &self.high_watermark)
+            .field("log_start_offset", &self.log_start_offset)
+            .field("aborted_transactions", &self.aborted_transactions)
+            .field("record_set", &DebugLen(&self.record_set))
+            .finish()
+    }
+}
+
+impl From<FetchResponsePartitionV7> for FetchResponsePartition {
+    fn from(v: FetchResponsePartitionV7) -> Self {
+        FetchResponsePartition {
+            index: v.index,
+            error_code: v.error_code,
+            high_watermark: v.high_watermark,
+            last_stable_offset: v.last_stable_offset,
+            aborted_transactions: v.aborted_transactions,
+            record_set: v.record_set,
+        }
+    }
+}
+
+impl From<FetchResponseTopicV7> for FetchResponseTopic {
+    fn from(v: FetchResponseTopicV7) -> Self {
+        FetchResponseTopic {
+            name: v.name,
+            partitions: v.partitions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Fetch with rack information (KIP-392). Brokers may point consumer to a closer replica with
+/// `preferred_read_replica`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct FetchRequestV11<'a> {
+    pub replica_id: i32,
+    pub max_wait_time: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    pub isolation_level: IsolationLevel,
+    pub session_id: i32,
+    pub session_epoch: i32,
+    pub topics: Vec<TopicFetchV11<'a>>,
+    pub forgotten_topics: Vec<ForgottenTopic<'a>>,
+    pub rack_id: Cow<'a, str>,
+}
+
+impl<'a> KafkaRequest for FetchRequestV11<'a> {
+    const API_KEY: ApiKey = ApiKey::Fetch;
+    const API_VERSION: i16 = 11;
+    type Response = FetchResponseV11;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct TopicFetchV11<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<PartitionFetchV11>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct PartitionFetchV11 {
+    pub index: i32,
+    pub current_leader_epoch: i32,
+    pub fetch_offset: i64,
+    pub log_start_offset: i64,
+    pub partition_max_bytes: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponseV11 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub session_id: i32,
+    pub topics: Vec<FetchResponseTopicV11>,
+}
+
+impl KafkaResponse for FetchResponseV11 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponseTopicV11 {
+    pub name: String,
+    pub partitions: Vec<FetchResponsePartitionV11>,
+}
+
+#[derive(Clone, PartialEq, WireFormatParse)]
+pub struct FetchResponsePartitionV11 {
+    pub index: i32,
+    pub error_code: ErrorCode,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    pub aborted_transactions: Vec<AbortedTransaction>,
+    /// Broker consumer should fetch this partition from, -1 if not set
+    pub preferred_read_replica: i32,
+    pub record_set: Vec<u8>,
+}
+
+impl std::fmt::Debug for FetchResponsePartitionV11 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FetchResponsePartitionV11")
+            .field("index", &self.index)
+            .field("error_code", &self.error_code)
+            .field("high_watermark", &self.high_watermark)
+            .field("log_start_offset", &self.log_start_offset)
+            .field("aborted_transactions", &self.aborted_transactions)
+            .field("preferred_read_replica", &self.preferred_read_replica)
+            .field("record_set", &DebugLen(&self.record_set))
+            .finish()
+    }
+}
+
+impl From<FetchResponsePartitionV11> for FetchResponsePartition {
+    fn from(v: FetchResponsePartitionV11) -> Self {
+        FetchResponsePartition {
+            index: v.index,
+            error_code: v.error_code,
+            high_watermark: v.high_watermark,
+            last_stable_offset: v.last_stable_offset,
+            aborted_transactions: v.aborted_transactions,
+            record_set: v.record_set,
+        }
+    }
+}
+
+impl From<FetchResponseTopicV11> for FetchResponseTopic {
+    fn from(v: FetchResponseTopicV11) -> Self {
+        FetchResponseTopic {
+            name: v.name,
+            partitions: v.partitions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+
+    #[test]
+    fn fetch_request_v7() {
+        let request = FetchRequestV7 {
+            replica_id: -1,
+            max_wait_time: 500,
+            min_bytes: 1,
+            max_bytes: 1024,
+            isolation_level: IsolationLevel::ReadCommitted,
+            session_id: 7,
+            session_epoch: 2,
+            topics: vec![TopicFetchV7 {
+                name: "abc".into(),
+                partitions: vec![PartitionFetchV7 {
+                    index: 1,
+                    fetch_offset: 42,
+                    log_start_offset: -1,
+                    partition_max_bytes: 256,
+                }],
+            }],
+            forgotten_topics: vec![ForgottenTopic {
+                name: "de".into(),
+                partitions: vec![3],
+            }],
+        };
+        let expected = hex_bytes(concat!(
+            "ffffffff000001f400000001000004000100000007000000020000000100036162630000",
+            "000100000001000000000000002affffffffffffffff0000010000000001000264650000",
+            "000100000003",
+        ));
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn fetch_response_v7() {
+        let bytes = hex_bytes(concat!(
+            "000000000046000000070000000100036162630000000100000001000000000000000000",
+            "2a0000000000000028000000000000000a0000000000000003010203",
+        ));
+        let expected = FetchResponseV7 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::FetchSessionIdNotFound,
+            session_id: 7,
+            topics: vec![FetchResponseTopicV7 {
+                name: "abc".into(),
+                partitions: vec![FetchResponsePartitionV7 {
+                    index: 1,
+                    error_code: ErrorCode::None,
+                    high_watermark: 42,
+                    last_stable_offset: 40,
+                    log_start_offset: 10,
+                    aborted_transactions: vec![],
+                    record_set: vec![1, 2, 3],
+                }],
+            }],
+        };
+
+        assert_eq!(FetchResponseV7::from_wire_bytes(&bytes), Ok(expected));
+    }
+
+    #[test]
+    fn fetch_request_v11() {
+        let request = FetchRequestV11 {
+            replica_id: -1,
+            max_wait_time: 500,
+            min_bytes: 1,
+            max_bytes: 1024,
+            isolation_level: IsolationLevel::ReadCommitted,
+            session_id: 7,
+            session_epoch: 2,
+            topics: vec![TopicFetchV11 {
+                name: "abc".into(),
+                partitions: vec![PartitionFetchV11 {
+                    index: 1,
+                    current_leader_epoch: -1,
+                    fetch_offset: 42,
+                    log_start_offset: -1,
+                    partition_max_bytes: 256,
+                }],
+            }],
+            forgotten_topics: vec![],
+            rack_id: "az-1".into(),
+        };
+        let expected = hex_bytes(concat!(
+            "ffffffff000001f400000001000004000100000007000000020000000100036162630000",
+            "000100000001ffffffff000000000000002affffffffffffffff00000100000000000004",
+            "617a2d31",
+        ));
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn fetch_response_v11() {
+        let bytes = hex_bytes(concat!(
+            "000000000000000000070000000100036162630000000100000001000000000000000000",
+            "2a0000000000000028000000000000000a000000000000000200000003010203",
+        ));
+        let expected = FetchResponseV11 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::None,
+            session_id: 7,
+            topics: vec![FetchResponseTopicV11 {
+                name: "abc".into(),
+                partitions: vec![FetchResponsePartitionV11 {
+                    index: 1,
+                    error_code: ErrorCode::None,
+                    high_watermark: 42,
+                    last_stable_offset: 40,
+                    log_start_offset: 10,
+                    aborted_transactions: vec![],
+                    preferred_read_replica: 2,
+                    record_set: vec![1, 2, 3],
+                }],
+            }],
+        };
+
+        assert_eq!(FetchResponseV11::from_wire_bytes(&bytes), Ok(expected));
+    }
+}
@@ -32,6 +32,7 @@ impl KafkaResponse for FindCoordinatorResponseV2 {}
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyType {
     Group = 0,
+    Transaction = 1,
 }
 
 impl WireFormatWrite for KeyType {
@@ -0,0 +1,57 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct HeartbeatRequestV1<'a> {
+    pub group_id: Cow<'a, str>,
+    pub generation_id: i32,
+    pub member_id: Cow<'a, str>,
+}
+
+impl<'a> KafkaRequest for HeartbeatRequestV1<'a> {
+    const API_KEY: ApiKey = ApiKey::Heartbeat;
+    const API_VERSION: i16 = 1;
+    type Response = HeartbeatResponseV1;
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct HeartbeatResponseV1 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+}
+
+impl KafkaResponse for HeartbeatResponseV1 {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn heartbeat_request_v1() {
+        let request = HeartbeatRequestV1 {
+            group_id: "g".into(),
+            generation_id: 1,
+            member_id: "m".into(),
+        };
+        let expected = hex_bytes("0001670000000100016d");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn heartbeat_response_v1() {
+        let bytes = hex_bytes("00000000001b");
+        let expected = HeartbeatResponseV1 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::RebalanceInProgress,
+        };
+
+        assert_eq!(HeartbeatResponseV1::from_wire_bytes(&bytes), Ok(expected));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use rskafka_wire_format::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct InitProducerIdRequestV1<'a> {
+    /// Null for idempotent producer without transactions
+    pub transactional_id: NullableString<'a>,
+    pub transaction_timeout_ms: i32,
+}
+
+impl<'a> KafkaRequest for InitProducerIdRequestV1<'a> {
+    const API_KEY: ApiKey = ApiKey::InitProducerId;
+    const API_VERSION: i16 = 1;
+    type Response = InitProducerIdResponseV1;
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct InitProducerIdResponseV1 {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+impl KafkaResponse for InitProducerIdResponseV1 {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+
+    #[test]
+    fn init_producer_id_request_v1() {
+        let request = InitProducerIdRequestV1 {
+            transactional_id: "tx".into(),
+            transaction_timeout_ms: 60000,
+        };
+        let expected = hex_bytes("000274780000ea60");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn init_producer_id_response_v1() {
+        let bytes = hex_bytes("00000000000000000000000000070003");
+        let expected = InitProducerIdResponseV1 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::None,
+            producer_id: 7,
+            producer_epoch: 3,
+        };
+
+        assert_eq!(
+            InitProducerIdResponseV1::from_wire_bytes(&bytes),
+            Ok(expected)
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    apis::fetch::IsolationLevel,
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use std::borrow::Cow;
+
+/// Special timestamp value used to query for the latest offset
+pub const LATEST_TIMESTAMP: i64 = -1;
+/// Special timestamp value used to query for the earliest offset
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ListOffsetsRequestV2<'a> {
+    pub replica_id: i32,
+    pub isolation_level: IsolationLevel,
+    pub topics: Vec<ListOffsetsTopic<'a>>,
+}
+
+impl<'a> KafkaRequest for ListOffsetsRequestV2<'a> {
+    const API_KEY: ApiKey = ApiKey::ListOffsets;
+    const API_VERSION: i16 = 2;
+    type Response = ListOffsetsResponseV2;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ListOffsetsTopic<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<ListOffsetsPartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ListOffsetsPartition {
+    pub partition_index: i32,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ListOffsetsResponseV2 {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<ListOffsetsTopicResponse>,
+}
+
+impl KafkaResponse for ListOffsetsResponseV2 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ListOffsetsTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ListOffsetsPartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ListOffsetsPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+    pub timestamp: i64,
+    pub offset: i64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn list_offsets_request_v2() {
+        let request = ListOffsetsRequestV2 {
+            replica_id: -1,
+            isolation_level: IsolationLevel::ReadCommitted,
+            topics: vec![ListOffsetsTopic {
+                name: "abc".into(),
+                partitions: vec![ListOffsetsPartition {
+                    partition_index: 2,
+                    timestamp: EARLIEST_TIMESTAMP,
+                }],
+            }],
+        };
+        let expected = hex_bytes("ffffffff010000000100036162630000000100000002fffffffffffffffe");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn list_offsets_response_v2() {
+        let bytes = hex_bytes(
+            "0000000000000001000361626300000001000000020000ffffffffffffffff000000000000002a",
+        );
+        let expected = ListOffsetsResponseV2 {
+            throttle_time_ms: 0,
+            topics: vec![ListOffsetsTopicResponse {
+                name: "abc".into(),
+                partitions: vec![ListOffsetsPartitionResponse {
+                    partition_index: 2,
+                    error_code: ErrorCode::None,
+                    timestamp: -1,
+                    offset: 42,
+                }],
+            }],
+        };
+
+        assert_eq!(ListOffsetsResponseV2::from_wire_bytes(&bytes), Ok(expected));
+    }
+}
@@ -6,7 +6,8 @@ use rskafka_wire_format::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, WireFormatWrite)]
 pub struct MetadataRequestV2 {
-    pub topics: Vec<String>,
+    /// Null requests metadata of all topics, empty array - of no topics
+    pub topics: NullableArray<String>,
 }
 
 impl KafkaRequest for MetadataRequestV2 {
@@ -66,11 +67,20 @@ mod test {
         let expected_bytes = vec![0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x61, 0x75, 0x74, 0x6f];
 
         let request = MetadataRequestV2 {
-            topics: vec![String::from("auto")],
+            topics: vec![String::from("auto")].into(),
         };
 
         let bytes = request.to_wire_bytes();
 
         assert_eq!(bytes, expected_bytes);
     }
+
+    #[test]
+    fn metadata_request_v2_all_topics_write() {
+        let request = MetadataRequestV2 {
+            topics: NullableArray::with_null(),
+        };
+
+        assert_eq!(request.to_wire_bytes(), vec![0xff, 0xff, 0xff, 0xff]);
+    }
 }
@@ -0,0 +1,102 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use rskafka_wire_format::prelude::*;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct OffsetCommitRequestV2<'a> {
+    pub group_id: Cow<'a, str>,
+    pub generation_id: i32,
+    pub member_id: Cow<'a, str>,
+    pub retention_time_ms: i64,
+    pub topics: Vec<OffsetCommitTopic<'a>>,
+}
+
+impl<'a> KafkaRequest for OffsetCommitRequestV2<'a> {
+    const API_KEY: ApiKey = ApiKey::OffsetCommit;
+    const API_VERSION: i16 = 2;
+    type Response = OffsetCommitResponseV2;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct OffsetCommitTopic<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<OffsetCommitPartition<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct OffsetCommitPartition<'a> {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_metadata: NullableString<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct OffsetCommitResponseV2 {
+    pub topics: Vec<OffsetCommitTopicResponse>,
+}
+
+impl KafkaResponse for OffsetCommitResponseV2 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct OffsetCommitTopicResponse {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitPartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct OffsetCommitPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+
+    #[test]
+    fn offset_commit_request_v2() {
+        let request = OffsetCommitRequestV2 {
+            group_id: "g".into(),
+            generation_id: -1,
+            member_id: "".into(),
+            retention_time_ms: -1,
+            topics: vec![OffsetCommitTopic {
+                name: "t".into(),
+                partitions: vec![OffsetCommitPartition {
+                    partition_index: 1,
+                    committed_offset: 5,
+                    committed_metadata: NullableString::with_null(),
+                }],
+            }],
+        };
+        let expected = hex_bytes(
+            "000167ffffffff0000ffffffffffffffff0000000100017400000001000000010000000000000005ffff",
+        );
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn offset_commit_response_v2() {
+        let bytes = hex_bytes("000000010001740000000100000001001b");
+        let expected = OffsetCommitResponseV2 {
+            topics: vec![OffsetCommitTopicResponse {
+                name: "t".into(),
+                partitions: vec![OffsetCommitPartitionResponse {
+                    partition_index: 1,
+                    error_code: ErrorCode::RebalanceInProgress,
+                }],
+            }],
+        };
+
+        assert_eq!(
+            OffsetCommitResponseV2::from_wire_bytes(&bytes),
+            Ok(expected)
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use rskafka_wire_format::prelude::*;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ProduceRequestV3<'a> {
+    /// Set if records are part of a transaction
+    pub transactional_id: NullableString<'a>,
+    /// Number of replicas which have to acknowledge write before broker responds (-1 for all
+    /// in-sync replicas)
+    pub acks: i16,
+    pub timeout_ms: i32,
+    pub topics: Vec<ProduceTopic<'a>>,
+}
+
+impl<'a> KafkaRequest for ProduceRequestV3<'a> {
+    const API_KEY: ApiKey = ApiKey::Produce;
+    const API_VERSION: i16 = 3;
+    type Response = ProduceResponseV3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ProduceTopic<'a> {
+    pub name: Cow<'a, str>,
+    pub partitions: Vec<ProducePartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct ProducePartition {
+    pub index: i32,
+    /// Record batch in wire format
+    pub records: NullableBytes,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ProduceResponseV3 {
+    pub topics: Vec<ProduceTopicResponse>,
+    pub throttle_time_ms: i32,
+}
+
+impl KafkaResponse for ProduceResponseV3 {}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ProduceTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ProducePartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct ProducePartitionResponse {
+    pub index: i32,
+    pub error_code: ErrorCode,
+    /// Offset of the first appended record
+    pub base_offset: i64,
+    /// Timestamp set by broker if topic uses log append time, -1 otherwise
+    pub log_append_time_ms: i64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+
+    #[test]
+    fn produce_request_v3() {
+        let request = ProduceRequestV3 {
+            transactional_id: "tx".into(),
+            acks: -1,
+            timeout_ms: 1000,
+            topics: vec![ProduceTopic {
+                name: "t".into(),
+                partitions: vec![ProducePartition {
+                    index: 1,
+                    records: NullableBytes::with_data(&[0xab, 0xcd]),
+                }],
+            }],
+        };
+        let expected = hex_bytes("00027478ffff000003e800000001000174000000010000000100000002abcd");
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn produce_response_v3() {
+        let bytes =
+            hex_bytes("0000000100017400000001000000010000000000000000002affffffffffffffff00000000");
+        let expected = ProduceResponseV3 {
+            topics: vec![ProduceTopicResponse {
+                name: "t".into(),
+                partitions: vec![ProducePartitionResponse {
+                    index: 1,
+                    error_code: ErrorCode::None,
+                    base_offset: 42,
+                    log_append_time_ms: -1,
+                }],
+            }],
+            throttle_time_ms: 0,
+        };
+
+        assert_eq!(ProduceResponseV3::from_wire_bytes(&bytes), Ok(expected));
+    }
+}
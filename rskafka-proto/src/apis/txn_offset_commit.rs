@@ -0,0 +1,131 @@
+use crate::{
+    data::{api_key::ApiKey, error::ErrorCode},
+    KafkaRequest, KafkaResponse,
+};
+use rskafka_wire_format::{CompactArray, CompactNullableString, CompactString, TaggedFields};
+
+/// Commits consumer group offsets as part of a transaction. Offsets become visible when
+/// transaction is committed.
+///
+/// Since v3 (KIP-447) request carries group generation and member, so that broker rejects offsets
+/// of a member which was removed from the group.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct TxnOffsetCommitRequestV3<'a> {
+    pub transactional_id: CompactString<'a>,
+    pub group_id: CompactString<'a>,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub generation_id: i32,
+    pub member_id: CompactString<'a>,
+    pub group_instance_id: CompactNullableString<'a>,
+    pub topics: CompactArray<TxnOffsetCommitTopic<'a>>,
+    pub tagged_fields: TaggedFields,
+}
+
+impl<'a> KafkaRequest for TxnOffsetCommitRequestV3<'a> {
+    const API_KEY: ApiKey = ApiKey::TxnOffsetCommit;
+    const API_VERSION: i16 = 3;
+    const FLEXIBLE: bool = true;
+    type Response = TxnOffsetCommitResponseV3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct TxnOffsetCommitTopic<'a> {
+    pub name: CompactString<'a>,
+    pub partitions: CompactArray<TxnOffsetCommitPartition<'a>>,
+    pub tagged_fields: TaggedFields,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, WireFormatWrite)]
+pub struct TxnOffsetCommitPartition<'a> {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub committed_metadata: CompactNullableString<'a>,
+    pub tagged_fields: TaggedFields,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct TxnOffsetCommitResponseV3 {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<TxnOffsetCommitTopicResponse>,
+    pub tagged_fields: TaggedFields,
+}
+
+impl KafkaResponse for TxnOffsetCommitResponseV3 {
+    const FLEXIBLE: bool = true;
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct TxnOffsetCommitTopicResponse {
+    pub name: CompactString<'static>,
+    pub partitions: CompactArray<TxnOffsetCommitPartitionResponse>,
+    pub tagged_fields: TaggedFields,
+}
+
+#[derive(Debug, Clone, PartialEq, WireFormatParse)]
+pub struct TxnOffsetCommitPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+    pub tagged_fields: TaggedFields,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::hex_bytes;
+    use rskafka_wire_format::prelude::*;
+
+    #[test]
+    fn txn_offset_commit_request_v3() {
+        let request = TxnOffsetCommitRequestV3 {
+            transactional_id: "tx".into(),
+            group_id: "g".into(),
+            producer_id: 7,
+            producer_epoch: 3,
+            generation_id: 5,
+            member_id: "m".into(),
+            group_instance_id: CompactNullableString::with_null(),
+            topics: CompactArray(vec![TxnOffsetCommitTopic {
+                name: "t".into(),
+                partitions: CompactArray(vec![TxnOffsetCommitPartition {
+                    partition_index: 1,
+                    committed_offset: 5,
+                    committed_leader_epoch: -1,
+                    committed_metadata: CompactNullableString::with_null(),
+                    tagged_fields: TaggedFields,
+                }]),
+                tagged_fields: TaggedFields,
+            }]),
+            tagged_fields: TaggedFields,
+        };
+        let expected = hex_bytes(
+            "03747802670000000000000007000300000005026d000202740200000001000000000000000\
+             5ffffffff00000000",
+        );
+
+        assert_eq!(request.wire_size(), expected.len());
+        assert_eq!(request.to_wire_bytes(), expected);
+    }
+
+    #[test]
+    fn txn_offset_commit_response_v3() {
+        // Response header tagged fields followed by response
+        let bytes = hex_bytes("00000000000202740200000001001e000000");
+        let expected = TxnOffsetCommitResponseV3 {
+            throttle_time_ms: 0,
+            topics: CompactArray(vec![TxnOffsetCommitTopicResponse {
+                name: "t".into(),
+                partitions: CompactArray(vec![TxnOffsetCommitPartitionResponse {
+                    partition_index: 1,
+                    error_code: ErrorCode::GroupAuthorizationFailed,
+                    tagged_fields: TaggedFields,
+                }]),
+                tagged_fields: TaggedFields,
+            }]),
+            tagged_fields: TaggedFields,
+        };
+
+        assert_eq!(TxnOffsetCommitResponseV3::from_bytes(&bytes), Ok(expected));
+    }
+}
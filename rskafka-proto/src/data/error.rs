@@ -93,6 +93,8 @@ pub enum ErrorCode {
     GroupSubscribedToTopic,
     InvalidRecord,
     UnstableOffsetCommit,
+    ThrottlingQuotaExceeded,
+    ProducerFenced,
     Unknown(i16),
 }
 
@@ -189,6 +191,8 @@ impl ErrorCode {
             ErrorCode::GroupSubscribedToTopic => 86,
             ErrorCode::InvalidRecord => 87,
             ErrorCode::UnstableOffsetCommit => 88,
+            ErrorCode::ThrottlingQuotaExceeded => 89,
+            ErrorCode::ProducerFenced => 90,
             ErrorCode::Unknown(code) => *code,
         }
     }
@@ -285,9 +289,33 @@ impl ErrorCode {
             86 => ErrorCode::GroupSubscribedToTopic,
             87 => ErrorCode::InvalidRecord,
             88 => ErrorCode::UnstableOffsetCommit,
+            89 => ErrorCode::ThrottlingQuotaExceeded,
+            90 => ErrorCode::ProducerFenced,
             other => ErrorCode::Unknown(other),
         }
     }
+
+    /// Coordinator moved to another broker or is temporarily unavailable. Request should be
+    /// retried after finding the coordinator again.
+    pub fn is_coordinator_error(&self) -> bool {
+        match self {
+            ErrorCode::NotCoordinator
+            | ErrorCode::CoordinatorNotAvailable
+            | ErrorCode::CoordinatorLoadInProgress => true,
+            _ => false,
+        }
+    }
+
+    /// Producer was replaced by another instance using the same transactional id (or its epoch
+    /// expired). Producer can't continue and has to be closed.
+    pub fn is_producer_fenced(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::ProducerFenced
+                | ErrorCode::InvalidProducerEpoch
+                | ErrorCode::TransactionCoordinatorFenced
+        )
+    }
 }
 
 impl std::fmt::Display for ErrorCode {
@@ -403,6 +431,8 @@ impl std::fmt::Display for ErrorCode {
             ErrorCode::GroupSubscribedToTopic => Cow::Borrowed("GROUP_SUBSCRIBED_TO_TOPIC"),
             ErrorCode::InvalidRecord => Cow::Borrowed("INVALID_RECORD"),
             ErrorCode::UnstableOffsetCommit => Cow::Borrowed("UNSTABLE_OFFSET_COMMIT"),
+            ErrorCode::ThrottlingQuotaExceeded => Cow::Borrowed("THROTTLING_QUOTA_EXCEEDED"),
+            ErrorCode::ProducerFenced => Cow::Borrowed("PRODUCER_FENCED"),
             ErrorCode::Unknown(code) => Cow::Owned(format!("{}", code)),
         };
 
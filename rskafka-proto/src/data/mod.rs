@@ -1,4 +1,5 @@
 pub mod api_key;
+mod crc32c;
 pub mod error;
 pub mod header;
 pub mod record;
@@ -16,6 +17,12 @@ impl WireFormatParse for BrokerId {
     }
 }
 
+impl From<i32> for BrokerId {
+    fn from(v: i32) -> Self {
+        BrokerId(v)
+    }
+}
+
 impl std::fmt::Display for BrokerId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.fmt(f)
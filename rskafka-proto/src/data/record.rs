@@ -1,3 +1,4 @@
+use super::crc32c::crc32c;
 use nom::{
     bytes::complete::take,
     combinator::{map, map_res},
@@ -5,7 +6,7 @@ use nom::{
     IResult,
 };
 use rskafka_wire_format::{error::ParseError, parse_helpers, prelude::*, VarInt};
-use std::borrow::Cow;
+use std::{borrow::Cow, convert::TryFrom, io};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct RecordBatch<'a> {
@@ -24,7 +25,45 @@ pub struct RecordBatch<'a> {
     pub records: Cow<'a, [Record<'a>]>,
 }
 
+/// Batch attribute bits
+const TIMESTAMP_TYPE_FLAG: i16 = 0x08;
+const TRANSACTIONAL_FLAG: i16 = 0x10;
+const CONTROL_FLAG: i16 = 0x20;
+
+/// Size of batch fields preceding records array, which are covered by `batch_length`
+const BATCH_HEADER_SIZE: usize = 45;
+/// Size of `base_offset` and `batch_length` fields
+const BATCH_PREFIX_SIZE: usize = 12;
+
 impl RecordBatch<'_> {
+    /// Record timestamps were set by broker on append (`max_timestamp` holds append time)
+    /// rather than by producer
+    pub fn is_log_append_time(&self) -> bool {
+        self.attributes & TIMESTAMP_TYPE_FLAG != 0
+    }
+
+    /// Batch is part of a transaction
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_FLAG != 0
+    }
+
+    pub fn set_transactional(&mut self, transactional: bool) {
+        if transactional {
+            self.attributes |= TRANSACTIONAL_FLAG;
+        } else {
+            self.attributes &= !TRANSACTIONAL_FLAG;
+        }
+    }
+
+    /// Batch holds a control record (e.g. transaction marker) instead of user data
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_FLAG != 0
+    }
+
+    pub fn last_offset(&self) -> i64 {
+        self.base_offset + self.last_offset_delta as i64
+    }
+
     pub fn detach(self) -> RecordBatch<'static> {
         RecordBatch {
             base_offset: self.base_offset,
@@ -50,6 +89,37 @@ impl RecordBatch<'_> {
     }
 }
 
+/// Writes batch with `batch_length`, `crc` and record lengths computed from data, values stored
+/// in these fields are ignored
+impl WireFormatWrite for RecordBatch<'_> {
+    fn wire_size(&self) -> usize {
+        BATCH_PREFIX_SIZE + BATCH_HEADER_SIZE + self.records.wire_size()
+    }
+
+    fn write_into<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        // Checksum covers data following `crc` field
+        let mut data = Vec::with_capacity(self.wire_size());
+        self.attributes.write_into(&mut data)?;
+        self.last_offset_delta.write_into(&mut data)?;
+        self.first_timestamp.write_into(&mut data)?;
+        self.max_timestamp.write_into(&mut data)?;
+        self.producer_id.write_into(&mut data)?;
+        self.producer_epoch.write_into(&mut data)?;
+        self.base_sequence.write_into(&mut data)?;
+        self.records.write_into(&mut data)?;
+
+        // Length of fields following `batch_length`
+        let batch_length = i32::try_from(self.wire_size() - BATCH_PREFIX_SIZE)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.base_offset.write_into(writer)?;
+        batch_length.write_into(writer)?;
+        self.partition_leader_epoch.write_into(writer)?;
+        self.magic.write_into(writer)?;
+        crc32c(&data).write_into(writer)?;
+        writer.write_all(&data)
+    }
+}
+
 impl<'a> WireFormatBorrowParse<'a> for RecordBatch<'a> {
     fn borrow_parse(input: &'a [u8]) -> IResult<&'a [u8], Self, ParseError> {
         let (input, base_offset) = i64::parse(input)?;
@@ -117,6 +187,65 @@ impl Record<'_> {
     }
 }
 
+impl Record<'_> {
+    /// Size of record fields following `length`
+    fn body_size(&self) -> usize {
+        self.attributes.wire_size()
+            + self.timestamp_delta.wire_size()
+            + self.offset_delta.wire_size()
+            + bytes_size(self.key.as_deref())
+            + bytes_size(self.value.as_deref())
+            + VarInt(self.headers.len() as i32).wire_size()
+            + self.headers.iter().map(Header::wire_size).sum::<usize>()
+    }
+}
+
+/// Writes record with `length` computed from data, value stored in the field is ignored
+impl WireFormatWrite for Record<'_> {
+    fn wire_size(&self) -> usize {
+        let body_size = self.body_size();
+        VarInt(body_size as i32).wire_size() + body_size
+    }
+
+    fn write_into<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        varint_len(self.body_size())?.write_into(writer)?;
+        self.attributes.write_into(writer)?;
+        self.timestamp_delta.write_into(writer)?;
+        self.offset_delta.write_into(writer)?;
+        write_bytes(self.key.as_deref(), writer)?;
+        write_bytes(self.value.as_deref(), writer)?;
+        varint_len(self.headers.len())?.write_into(writer)?;
+        for header in self.headers.iter() {
+            header.write_into(writer)?;
+        }
+        Ok(())
+    }
+}
+
+fn varint_len(len: usize) -> io::Result<VarInt> {
+    i32::try_from(len)
+        .map(VarInt)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Size of bytes prefixed with varint length (-1 for null)
+fn bytes_size(bytes: Option<&[u8]>) -> usize {
+    match bytes {
+        Some(bytes) => VarInt(bytes.len() as i32).wire_size() + bytes.len(),
+        None => VarInt(-1).wire_size(),
+    }
+}
+
+fn write_bytes<W: io::Write>(bytes: Option<&[u8]>, writer: &mut W) -> io::Result<()> {
+    match bytes {
+        Some(bytes) => {
+            varint_len(bytes.len())?.write_into(writer)?;
+            writer.write_all(bytes)
+        }
+        None => VarInt(-1).write_into(writer),
+    }
+}
+
 impl<'a> WireFormatBorrowParse<'a> for Record<'a> {
     fn borrow_parse(input: &'a [u8]) -> IResult<&'a [u8], Self, ParseError> {
         let (input, length) = VarInt::parse(input)?;
@@ -169,6 +298,17 @@ impl<'a> Header<'a> {
     }
 }
 
+impl WireFormatWrite for Header<'_> {
+    fn wire_size(&self) -> usize {
+        bytes_size(Some(self.key.as_bytes())) + bytes_size(Some(&self.value))
+    }
+
+    fn write_into<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_bytes(Some(self.key.as_bytes()), writer)?;
+        write_bytes(Some(&self.value), writer)
+    }
+}
+
 impl<'a> WireFormatBorrowParse<'a> for Header<'a> {
     fn borrow_parse(input: &'a [u8]) -> IResult<&'a [u8], Self, ParseError> {
         let (input, key_len) = map_res(VarInt::parse, parse_helpers::int_as_usize)(input)?;
@@ -186,6 +326,12 @@ impl<'a> WireFormatBorrowParse<'a> for Header<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_utils::hex_bytes;
+
+    const RECORD_BATCH_HEX: &str = "\
+        0000000000000001000000650000000002a25f84b100000000000000000171ebdfc70500000171eb\
+        dfc705ffffffffffffffffffffffffffff000000016600000010647570612d6b657918647570612d\
+        7061796c6f6164040c68616465723108313233340e686561646572320861626364";
 
     #[test]
     fn header_over_bytes() {
@@ -249,6 +395,28 @@ mod test {
         assert_eq!(record, expected);
     }
 
+    #[test]
+    fn record_batch_write_matches_parsed_bytes() {
+        let bytes = hex_bytes(RECORD_BATCH_HEX);
+        let mut batch = RecordBatch::over_wire_bytes(&bytes).unwrap();
+        assert_eq!(batch.wire_size(), bytes.len());
+        assert_eq!(batch.to_wire_bytes(), bytes);
+
+        // Lengths and checksum are computed from data
+        batch.batch_length = 0;
+        batch.crc = 0;
+        batch.records.to_mut()[0].length = VarInt(0);
+        assert_eq!(batch.to_wire_bytes(), bytes);
+    }
+
+    #[test]
+    fn record_with_null_key_write() {
+        let bytes = vec![20, 0, 0, 0, 1, 8, 100, 117, 112, 97, 0];
+        let record = Record::over_wire_bytes(&bytes).unwrap();
+        assert_eq!(record.wire_size(), bytes.len());
+        assert_eq!(record.to_wire_bytes(), bytes);
+    }
+
     #[test]
     fn record_batch_over_bytes() {
         let expected = RecordBatch {
@@ -10,7 +10,7 @@ mod response;
 pub use data::{
     api_key::ApiKey,
     error::ErrorCode,
-    record::{Record, RecordBatch},
+    record::{Header, Record, RecordBatch},
     BrokerId,
 };
 pub use request::KafkaRequest;
@@ -1,12 +1,14 @@
 use crate::{data::header::RequestHeader, ApiKey, KafkaResponse};
 use log::{log_enabled, trace};
-use rskafka_wire_format::prelude::*;
+use rskafka_wire_format::{prelude::*, TaggedFields};
 
 /// Represents a concrete request in Kafka Protocol wire format.
 /// Has specific Api Key, Api Version and can be written in wire format.
 pub trait KafkaRequest: WireFormatWrite {
     const API_KEY: ApiKey;
     const API_VERSION: i16;
+    /// Flexible versions (KIP-482) use request header v2, ending with tagged fields
+    const FLEXIBLE: bool = false;
     type Response: KafkaResponse;
 
     fn write_bytes<W: std::io::Write>(
@@ -21,7 +23,14 @@ pub trait KafkaRequest: WireFormatWrite {
             correlation_id,
             client_id: client_id.into(),
         };
-        let size = header.wire_size() + self.wire_size();
+        let header_tagged_fields = if Self::FLEXIBLE {
+            Some(TaggedFields)
+        } else {
+            None
+        };
+        let size = header.wire_size()
+            + header_tagged_fields.map_or(0, |t| t.wire_size())
+            + self.wire_size();
 
         trace!("write: size={:?}", size);
         trace!("write: header={:?}", header);
@@ -30,6 +39,9 @@ pub trait KafkaRequest: WireFormatWrite {
             let mut buffer = Vec::with_capacity(size as usize + std::mem::size_of_val(&size));
             (size as i32).write_into(&mut buffer)?; //TODO: conversion error
             header.write_into(&mut buffer)?;
+            if let Some(tagged_fields) = header_tagged_fields {
+                tagged_fields.write_into(&mut buffer)?;
+            }
             self.write_into(&mut buffer)?;
             trace!("write: {:?}", buffer);
 
@@ -37,6 +49,9 @@ pub trait KafkaRequest: WireFormatWrite {
         } else {
             (size as i32).write_into(&mut writer)?; //TODO: conversion error
             header.write_into(&mut writer)?;
+            if let Some(tagged_fields) = header_tagged_fields {
+                tagged_fields.write_into(&mut writer)?;
+            }
             self.write_into(&mut writer)?;
         }
 
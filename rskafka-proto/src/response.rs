@@ -1,8 +1,15 @@
 use rskafka_wire_format::error::ParseError;
-use rskafka_wire_format::prelude::*;
+use rskafka_wire_format::{prelude::*, TaggedFields};
 
 pub trait KafkaResponse: WireFormatParse {
+    /// Flexible versions (KIP-482) use response header v1, ending with tagged fields
+    const FLEXIBLE: bool = false;
+
     fn from_bytes(input: &[u8]) -> Result<Self, ParseError> {
-        Self::from_wire_bytes(input)
+        if Self::FLEXIBLE {
+            <(TaggedFields, Self)>::from_wire_bytes(input).map(|(_, response)| response)
+        } else {
+            Self::from_wire_bytes(input)
+        }
     }
 }
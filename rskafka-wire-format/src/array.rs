@@ -1,5 +1,5 @@
 use crate::error::{custom_error, ParseError};
-use crate::{parse_helpers, prelude::*};
+use crate::{int::UnsignedVarInt, parse_helpers, prelude::*};
 use nom::{
     combinator::{iterator, map, map_res},
     multi::many_m_n,
@@ -102,6 +102,87 @@ where
     }
 }
 
+/// Array which can be null (encoded with length set to -1)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NullableArray<T>(pub Option<Vec<T>>);
+
+impl<T> NullableArray<T> {
+    pub fn with_null() -> Self {
+        NullableArray(None)
+    }
+}
+
+impl<T> From<Vec<T>> for NullableArray<T> {
+    fn from(v: Vec<T>) -> Self {
+        NullableArray(Some(v))
+    }
+}
+
+impl<T> WireFormatWrite for NullableArray<T>
+where
+    T: WireFormatWrite,
+{
+    fn wire_size(&self) -> usize {
+        match self.0.as_ref() {
+            None => i32::wire_size_static(),
+            Some(items) => items.as_slice().wire_size(),
+        }
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self.0.as_ref() {
+            None => (-1i32).write_into(writer),
+            Some(items) => items.as_slice().write_into(writer),
+        }
+    }
+}
+
+/// Array of flexible request versions, encoded with unsigned varint length + 1. Null array (length
+/// 0) is parsed as empty.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompactArray<T>(pub Vec<T>);
+
+impl<T> From<Vec<T>> for CompactArray<T> {
+    fn from(v: Vec<T>) -> Self {
+        CompactArray(v)
+    }
+}
+
+impl<T> WireFormatParse for CompactArray<T>
+where
+    T: WireFormatParse,
+{
+    fn parse(input: &[u8]) -> nom::IResult<&[u8], Self, ParseError> {
+        let (input, size) = map_res(UnsignedVarInt::parse, parse_helpers::int_as_usize)(input)?;
+        let size = size.saturating_sub(1);
+        map(many_m_n(size, size, T::parse), CompactArray)(input)
+    }
+}
+
+impl<T> WireFormatWrite for CompactArray<T>
+where
+    T: WireFormatWrite,
+{
+    fn wire_size(&self) -> usize {
+        let len_size = UnsignedVarInt(self.0.len() as u32 + 1).wire_size();
+        let fields_size: usize = self.0.iter().map(|v| v.wire_size()).sum::<usize>();
+
+        len_size + fields_size
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let len = u32::try_from(self.0.len() + 1)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        UnsignedVarInt(len).write_into(writer)?;
+
+        for item in self.0.iter() {
+            item.write_into(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -114,4 +195,29 @@ mod test {
         assert_eq!(array.wire_size(), expected.len());
         assert_eq!(array.to_wire_bytes(), expected);
     }
+
+    #[test]
+    fn nullable_array_write() {
+        let null = NullableArray::<i32>::with_null();
+        assert_eq!(null.wire_size(), 4);
+        assert_eq!(null.to_wire_bytes(), vec![0xff, 0xff, 0xff, 0xff]);
+
+        let array = NullableArray::from(vec![1i32]);
+        assert_eq!(array.wire_size(), 8);
+        assert_eq!(array.to_wire_bytes(), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn compact_array() {
+        let array = CompactArray(vec![1i32]);
+        let expected = vec![0x02, 0, 0, 0, 1];
+
+        assert_eq!(array.wire_size(), expected.len());
+        assert_eq!(array.to_wire_bytes(), expected);
+        assert_eq!(CompactArray::<i32>::from_wire_bytes(&expected), Ok(array));
+        assert_eq!(
+            CompactArray::<i32>::from_wire_bytes(&[0x00]),
+            Ok(CompactArray(vec![]))
+        );
+    }
 }
@@ -71,8 +71,8 @@ impl<'a> WireFormatBorrowParse<'a> for CompactBytes<'a> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct NullableBytes(Option<Vec<u8>>);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NullableBytes(pub Option<Vec<u8>>);
 
 impl NullableBytes {
     pub fn with_null() -> Self {
@@ -99,6 +99,28 @@ impl WireFormatParse for NullableBytes {
     }
 }
 
+impl WireFormatWrite for NullableBytes {
+    fn wire_size(&self) -> usize {
+        match self.0.as_ref() {
+            None => i32::wire_size_static(),
+            Some(bytes) => bytes.as_slice().wire_size(),
+        }
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self.0.as_ref() {
+            None => (-1i32).write_into(writer),
+            Some(bytes) => bytes.as_slice().write_into(writer),
+        }
+    }
+}
+
+impl From<Option<Vec<u8>>> for NullableBytes {
+    fn from(v: Option<Vec<u8>>) -> Self {
+        NullableBytes(v)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -147,6 +169,17 @@ mod test {
         assert_eq!(bytes.to_wire_bytes(), expected);
     }
 
+    #[test]
+    fn write_nullable_bytes() {
+        let null = NullableBytes::with_null();
+        assert_eq!(null.wire_size(), 4);
+        assert_eq!(null.to_wire_bytes(), vec![0xff, 0xff, 0xff, 0xff]);
+
+        let bytes = NullableBytes::with_data(&[1, 2, 3]);
+        assert_eq!(bytes.wire_size(), 7);
+        assert_eq!(bytes.to_wire_bytes(), vec![0, 0, 0, 3, 1, 2, 3]);
+    }
+
     #[test]
     fn compact_bytes_parse() {
         let bytes: Vec<u8> = vec![0, 0, 0, 2, 2];
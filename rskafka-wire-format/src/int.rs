@@ -157,6 +157,38 @@ impl From<i32> for VarInt {
     }
 }
 
+/// Unsigned variable length integer, used for lengths in flexible versions of requests
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct UnsignedVarInt(pub u32);
+
+impl WireFormatParse for UnsignedVarInt {
+    fn parse(mut input: &[u8]) -> nom::IResult<&[u8], Self, ParseError> {
+        let v: u32 = input
+            .read_varint()
+            .map_err(|_| custom_error("unsigned varint parse failed"))?;
+
+        Ok((input, UnsignedVarInt(v)))
+    }
+}
+
+impl WireFormatWrite for UnsignedVarInt {
+    fn wire_size(&self) -> usize {
+        use integer_encoding::VarInt as VarIntTrait;
+        self.0.required_space()
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_varint(self.0).map(|_| ())
+    }
+}
+
+impl TryInto<usize> for UnsignedVarInt {
+    type Error = std::num::TryFromIntError;
+    fn try_into(self) -> Result<usize, Self::Error> {
+        self.0.try_into()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -201,4 +233,14 @@ mod test {
         assert_eq!(VarInt(12).to_wire_bytes(), &[0x18]);
         assert_eq!(VarInt(300).to_wire_bytes(), &[0xd8, 0x04]);
     }
+
+    #[test]
+    fn unsigned_varint() {
+        assert_eq!(UnsignedVarInt(0).to_wire_bytes(), &[0x00]);
+        assert_eq!(UnsignedVarInt(300).to_wire_bytes(), &[0xac, 0x02]);
+        assert_eq!(
+            UnsignedVarInt::from_wire_bytes(&[0xac, 0x02]).unwrap(),
+            UnsignedVarInt(300)
+        );
+    }
 }
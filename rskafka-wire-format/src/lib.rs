@@ -4,10 +4,13 @@ mod bytes;
 pub mod error;
 mod int;
 mod string;
+mod tagged_fields;
 mod tuple;
 mod uuid;
 
 pub mod prelude {
+    pub use crate::array::NullableArray;
+    pub use crate::bytes::NullableBytes;
     pub use crate::string::NullableString;
     pub use crate::{
         WireFormatBorrowParse, WireFormatParse, WireFormatSizeStatic, WireFormatWrite,
@@ -15,10 +18,12 @@ pub mod prelude {
     pub use nom::IResult;
 }
 
+pub use array::CompactArray;
 pub use bytes::CompactBytes;
-pub use int::VarInt;
+pub use int::{UnsignedVarInt, VarInt};
 pub use nom::IResult;
-pub use string::CompactString;
+pub use string::{CompactNullableString, CompactString};
+pub use tagged_fields::TaggedFields;
 pub mod parse_helpers;
 
 /// Object that can be written in Kafka Protocol wire format
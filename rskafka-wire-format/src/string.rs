@@ -1,5 +1,5 @@
 use crate::error::{custom_error, custom_io_error, ParseError};
-use crate::int::VarInt;
+use crate::int::UnsignedVarInt;
 use crate::prelude::*;
 use crate::{parse_helpers, IResult};
 use byteorder::{BigEndian, WriteBytesExt};
@@ -139,8 +139,9 @@ impl WireFormatParse for NullableString<'static> {
         }
     }
 }
-#[derive(Debug, Clone, PartialEq)]
-pub struct CompactString<'a>(Cow<'a, str>);
+/// String of flexible request versions, encoded with unsigned varint length + 1
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompactString<'a>(pub Cow<'a, str>);
 
 impl<'a> CompactString<'a> {
     /// Transform to CompactString with 'static lifetime by switching wrapped data
@@ -170,14 +171,11 @@ impl<'a> From<String> for CompactString<'a> {
 
 impl<'a> WireFormatWrite for CompactString<'a> {
     fn wire_size(&self) -> usize {
-        let len = self.0.len();
-        let len_size = VarInt(len as i32).wire_size();
-        len_size + len
+        compact_str_size(Some(&self.0))
     }
 
     fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        VarInt(self.0.len() as i32).write_into(writer)?; // TODO: conversion error
-        writer.write_all(self.0.as_bytes())
+        write_compact_str(Some(&self.0), writer)
     }
 }
 
@@ -189,19 +187,71 @@ impl WireFormatParse for CompactString<'static> {
 
 impl<'a> WireFormatBorrowParse<'a> for CompactString<'a> {
     fn borrow_parse(input: &'a [u8]) -> IResult<&'a [u8], Self, ParseError> {
-        let (input, len) = map_res(VarInt::parse, parse_helpers::int_as_usize)(input)?;
-        let (input, parsed) = map_res(take(len - 1), parse_helpers::bytes_as_utf8)(input)?; //TODO underflow
-
-        Ok((input, CompactString(parsed.into())))
+        match parse_compact_str(input)? {
+            (input, Some(parsed)) => Ok((input, CompactString(parsed.into()))),
+            (_, None) => Err(custom_error("null compact string")),
+        }
     }
 }
 
+/// Nullable string of flexible request versions, encoded with unsigned varint length + 1 (0 for
+/// null)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CompactNullableString<'a>(pub Option<Cow<'a, str>>);
 
+impl<'a> CompactNullableString<'a> {
+    pub fn with_null() -> Self {
+        CompactNullableString(None)
+    }
+}
+
+impl<'a> From<Option<&'a str>> for CompactNullableString<'a> {
+    fn from(v: Option<&'a str>) -> Self {
+        CompactNullableString(v.map(Cow::Borrowed))
+    }
+}
+
+impl<'a> WireFormatWrite for CompactNullableString<'a> {
+    fn wire_size(&self) -> usize {
+        compact_str_size(self.0.as_deref())
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_compact_str(self.0.as_deref(), writer)
+    }
+}
+
 impl WireFormatParse for CompactNullableString<'static> {
-    fn parse(_input: &[u8]) -> IResult<&[u8], Self, ParseError> {
-        // map(compact_nullable_bytes, |v| v.map(try_into_utf8))(input)
-        todo!()
+    fn parse(input: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        let (input, parsed) = parse_compact_str(input)?;
+        Ok((
+            input,
+            CompactNullableString(parsed.map(|s| Cow::Owned(s.to_owned()))),
+        ))
+    }
+}
+
+fn compact_str_size(s: Option<&str>) -> usize {
+    let len = s.map_or(0, str::len);
+    UnsignedVarInt(len as u32 + 1).wire_size() + len
+}
+
+fn write_compact_str<W: std::io::Write>(s: Option<&str>, writer: &mut W) -> std::io::Result<()> {
+    match s {
+        None => UnsignedVarInt(0).write_into(writer),
+        Some(s) => {
+            let len = u32::try_from(s.len() + 1).map_err(custom_io_error)?;
+            UnsignedVarInt(len).write_into(writer)?;
+            writer.write_all(s.as_bytes())
+        }
+    }
+}
+
+fn parse_compact_str(input: &[u8]) -> IResult<&[u8], Option<&str>, ParseError> {
+    let (input, len) = map_res(UnsignedVarInt::parse, parse_helpers::int_as_usize)(input)?;
+    match len.checked_sub(1) {
+        None => Ok((input, None)),
+        Some(len) => map(map_res(take(len), parse_helpers::bytes_as_utf8), Some)(input),
     }
 }
 
@@ -283,4 +333,26 @@ mod test {
             Ok((remaining, NullableString::with_borrowed("abc")))
         );
     }
+
+    #[test]
+    fn compact_string() {
+        let s = CompactString::from("abc");
+        assert_eq!(s.wire_size(), 4);
+        assert_eq!(s.to_wire_bytes(), vec![0x04, 0x61, 0x62, 0x63]);
+        assert_eq!(
+            CompactString::from_wire_bytes(&[0x04, 0x61, 0x62, 0x63]),
+            Ok(s)
+        );
+    }
+
+    #[test]
+    fn compact_nullable_string() {
+        let null = CompactNullableString::with_null();
+        assert_eq!(null.to_wire_bytes(), vec![0x00]);
+        assert_eq!(CompactNullableString::from_wire_bytes(&[0x00]), Ok(null));
+
+        let empty = CompactNullableString::from(Some(""));
+        assert_eq!(empty.to_wire_bytes(), vec![0x01]);
+        assert_eq!(CompactNullableString::from_wire_bytes(&[0x01]), Ok(empty));
+    }
 }
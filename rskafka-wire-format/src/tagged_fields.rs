@@ -0,0 +1,46 @@
+use crate::error::ParseError;
+use crate::{int::UnsignedVarInt, parse_helpers, prelude::*};
+use nom::{bytes::complete::take, combinator::map_res};
+
+/// Tagged fields ending structures of flexible request versions. No tagged fields are written and
+/// parsed ones are skipped, as none of them is used yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TaggedFields;
+
+impl WireFormatParse for TaggedFields {
+    fn parse(input: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        let (mut input, count) = UnsignedVarInt::parse(input)?;
+        for _ in 0..count.0 {
+            let (rest, _tag) = UnsignedVarInt::parse(input)?;
+            let (rest, size) = map_res(UnsignedVarInt::parse, parse_helpers::int_as_usize)(rest)?;
+            let (rest, _data) = take(size)(rest)?;
+            input = rest;
+        }
+        Ok((input, TaggedFields))
+    }
+}
+
+impl WireFormatWrite for TaggedFields {
+    fn wire_size(&self) -> usize {
+        UnsignedVarInt(0).wire_size()
+    }
+
+    fn write_into<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        UnsignedVarInt(0).write_into(writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_tagged_fields_are_skipped() {
+        let remaining: &[u8] = &[7];
+        assert_eq!(
+            TaggedFields::parse(&[0x02, 0x00, 0x01, 0xff, 0x05, 0x00, 7]),
+            Ok((remaining, TaggedFields))
+        );
+        assert_eq!(TaggedFields.to_wire_bytes(), vec![0x00]);
+    }
+}
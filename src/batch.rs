@@ -1,4 +1,4 @@
-use crate::KafkaMessage;
+use crate::{KafkaHeader, KafkaMessage, TimestampType};
 use rskafka_proto::{Record, RecordBatch};
 use std::borrow::Cow;
 
@@ -6,15 +6,30 @@ pub struct KafkaBatch<'a> {
     pub topic: String,
     pub partition_index: i32,
     pub base_offset: i64,
+    pub leader_epoch: i32,
+    pub first_timestamp: i64,
+    pub max_timestamp: i64,
+    pub timestamp_type: TimestampType,
+    pub producer_id: i64,
     pub records: Vec<KafkaBatchRecord<'a>>,
 }
 
 impl<'a> KafkaBatch<'a> {
     pub(crate) fn new(data: RecordBatch<'a>, topic: String, partition_index: i32) -> Self {
+        let timestamp_type = if data.is_log_append_time() {
+            TimestampType::LogAppendTime
+        } else {
+            TimestampType::CreateTime
+        };
         KafkaBatch {
             topic,
             partition_index,
             base_offset: data.base_offset,
+            leader_epoch: data.partition_leader_epoch,
+            first_timestamp: data.first_timestamp,
+            max_timestamp: data.max_timestamp,
+            timestamp_type,
+            producer_id: data.producer_id,
             records: data
                 .records
                 .into_owned()
@@ -24,26 +39,40 @@ impl<'a> KafkaBatch<'a> {
         }
     }
 
+    /// Absolute timestamp of record. With log append time all records share the batch timestamp.
+    fn timestamp(&self, record: &KafkaBatchRecord) -> i64 {
+        match self.timestamp_type {
+            TimestampType::CreateTime => self.first_timestamp + record.timestamp_delta,
+            TimestampType::LogAppendTime => self.max_timestamp,
+        }
+    }
+
     pub fn messages(&'a self) -> impl Iterator<Item = KafkaMessage<'a>> + 'a {
         self.records.iter().map(move |r| KafkaMessage {
             topic: Cow::Borrowed(&self.topic),
             partition: self.partition_index,
             offset: self.base_offset + r.offset_delta as i64,
+            timestamp: self.timestamp(r),
+            timestamp_type: self.timestamp_type,
+            leader_epoch: self.leader_epoch,
             key: r.key.as_ref().map(|key| Cow::Borrowed(key.as_ref())),
             value: r.value.as_ref().map(|value| Cow::Borrowed(value.as_ref())),
+            headers: Cow::Borrowed(&r.headers),
         })
     }
 
-    pub fn into_messages_owned(self) -> Vec<KafkaMessage<'static>> {
-        let topic = self.topic;
-        let partition_index = self.partition_index;
-        let base_offset = self.base_offset;
-        self.records
+    pub fn into_messages_owned(mut self) -> Vec<KafkaMessage<'static>> {
+        let records = std::mem::take(&mut self.records);
+        records
             .into_iter()
-            .map(move |r| KafkaMessage {
-                topic: topic.clone().into(),
-                partition: partition_index,
-                offset: base_offset + r.offset_delta as i64,
+            .map(|r| KafkaMessage {
+                topic: self.topic.clone().into(),
+                partition: self.partition_index,
+                offset: self.base_offset + r.offset_delta as i64,
+                timestamp: self.timestamp(&r),
+                timestamp_type: self.timestamp_type,
+                leader_epoch: self.leader_epoch,
+                headers: r.headers.iter().map(KafkaHeader::detached).collect(),
                 key: r.key.map(|k| k.into_owned().into()),
                 value: r.value.map(|v| v.into_owned().into()),
             })
@@ -53,16 +82,91 @@ impl<'a> KafkaBatch<'a> {
 
 pub struct KafkaBatchRecord<'a> {
     pub offset_delta: i32,
+    pub timestamp_delta: i64,
     pub key: Option<Cow<'a, [u8]>>,
     pub value: Option<Cow<'a, [u8]>>,
+    pub headers: Vec<KafkaHeader<'a>>,
 }
 
 impl<'a> From<Record<'a>> for KafkaBatchRecord<'a> {
     fn from(v: Record<'a>) -> Self {
         KafkaBatchRecord {
             offset_delta: v.offset_delta.0,
+            timestamp_delta: v.timestamp_delta.0 as i64,
             key: v.key,
             value: v.value,
+            headers: v
+                .headers
+                .into_owned()
+                .into_iter()
+                .map(|h| KafkaHeader {
+                    key: h.key,
+                    value: h.value,
+                })
+                .collect(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::Header;
+    use rskafka_wire_format::VarInt;
+
+    fn batch(attributes: i16) -> RecordBatch<'static> {
+        RecordBatch {
+            base_offset: 10,
+            batch_length: 0,
+            partition_leader_epoch: 3,
+            magic: 2,
+            crc: 0,
+            attributes,
+            last_offset_delta: 1,
+            first_timestamp: 1000,
+            max_timestamp: 1500,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: (0..2)
+                .map(|delta| Record {
+                    length: VarInt(0),
+                    attributes: 0,
+                    timestamp_delta: VarInt(delta * 100),
+                    offset_delta: VarInt(delta),
+                    key: None,
+                    value: Some(vec![1].into()),
+                    headers: vec![Header {
+                        key: "h".into(),
+                        value: vec![delta as u8].into(),
+                    }]
+                    .into(),
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+
+    #[test]
+    fn messages_carry_create_time_and_headers() {
+        let batch = KafkaBatch::new(batch(0), "t1".into(), 0);
+        let messages: Vec<_> = batch.messages().collect();
+
+        assert_eq!(messages[1].offset, 11);
+        assert_eq!(messages[1].timestamp, 1100);
+        assert_eq!(messages[1].timestamp_type, TimestampType::CreateTime);
+        assert_eq!(messages[1].leader_epoch, 3);
+        assert_eq!(messages[1].header("h"), Some(&[1u8][..]));
+        assert!(matches!(messages[1].headers, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn log_append_time_applies_to_whole_batch() {
+        let messages = KafkaBatch::new(batch(0x08), "t1".into(), 0).into_messages_owned();
+
+        assert_eq!(messages[0].timestamp, 1500);
+        assert_eq!(messages[1].timestamp, 1500);
+        assert_eq!(messages[1].timestamp_type, TimestampType::LogAppendTime);
+        assert_eq!(messages[0].header("h"), Some(&[0u8][..]));
+    }
+}
@@ -1,9 +1,20 @@
 use super::async_connection::{BrokerConnection, Managed};
-use crate::Error;
-use log::{debug, error, info, log_enabled};
-use rskafka_proto::{apis::metadata::MetadataRequestV2, BrokerId, KafkaRequest};
-use std::collections::HashMap;
-use tokio::sync::{Mutex, MutexGuard};
+use crate::{message::KafkaPartition, Error};
+use futures::future;
+use log::{debug, error, info, log_enabled, trace};
+use rskafka_proto::{
+    apis::{
+        fetch::IsolationLevel,
+        list_offsets::{ListOffsetsPartition, ListOffsetsRequestV2, ListOffsetsTopic},
+        metadata::{MetadataRequestV2, MetadataResponseV2},
+    },
+    BrokerId, ErrorCode, KafkaRequest,
+};
+use std::{collections::HashMap, io, time::Duration};
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    time,
+};
 
 pub struct AsyncClusterClient {
     conns: HashMap<BrokerId, Mutex<Managed>>,
@@ -30,7 +41,9 @@ impl AsyncClusterClient {
 
     async fn try_bootstrap_from(server_addr: &str, client_id: String) -> Result<Self, Error> {
         let mut conn = BrokerConnection::connect(server_addr, "rskafka-async".to_string()).await?;
-        let request = MetadataRequestV2 { topics: Vec::new() };
+        let request = MetadataRequestV2 {
+            topics: Vec::new().into(),
+        };
         let response = conn.make_request(&request).await?;
         let addrs: HashMap<BrokerId, String> = response
             .brokers
@@ -54,6 +67,14 @@ impl AsyncClusterClient {
         Ok(AsyncClusterClient { conns })
     }
 
+    /// Client failing all requests, for tests not talking to brokers
+    #[cfg(test)]
+    pub(crate) fn without_brokers() -> Self {
+        AsyncClusterClient {
+            conns: HashMap::new(),
+        }
+    }
+
     pub(crate) async fn make_request<R: KafkaRequest>(
         &self,
         r: R,
@@ -64,12 +85,177 @@ impl AsyncClusterClient {
         conn.make_request(&r).await
     }
 
+    /// Fails with `TimedOut` I/O error if broker does not respond in time. Connection is closed
+    /// then, so that the late response is not read as response of the next request.
+    pub(crate) async fn make_request_timeout<R: KafkaRequest>(
+        &self,
+        r: R,
+        broker: Option<BrokerId>,
+        timeout: Duration,
+    ) -> Result<R::Response, Error> {
+        let mut managed = self.get_connection(broker).await?;
+        let request = async { managed.get().await?.make_request(&r).await };
+        match time::timeout(timeout, request).await {
+            Ok(result) => result,
+            Err(_) => {
+                managed.disconnect();
+                Err(timed_out(broker))
+            }
+        }
+    }
+
+    /// Sends request broker does not respond to
+    pub(crate) async fn send_request<R: KafkaRequest>(
+        &self,
+        r: R,
+        broker: Option<BrokerId>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let mut managed = self.get_connection(broker).await?;
+        let request = async { managed.get().await?.send_request(&r).await };
+        match time::timeout(timeout, request).await {
+            Ok(result) => result,
+            Err(_) => {
+                managed.disconnect();
+                Err(timed_out(broker))
+            }
+        }
+    }
+
+    pub(crate) async fn partition_leaders<'a, I>(
+        &self,
+        topics: I,
+    ) -> Result<HashMap<KafkaPartition, BrokerId>, Error>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let response = self.make_request(metadata_request(topics), None).await?;
+        leaders(response)
+    }
+
+    /// Same as `partition_leaders`, but fails if metadata is not received in time, see
+    /// `make_request_timeout`
+    pub(crate) async fn partition_leaders_timeout<'a, I>(
+        &self,
+        topics: I,
+        timeout: Duration,
+    ) -> Result<HashMap<KafkaPartition, BrokerId>, Error>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let response = self
+            .make_request_timeout(metadata_request(topics), None, timeout)
+            .await?;
+        leaders(response)
+    }
+
+    /// Finds offset of the earliest message with timestamp greater or equal to the given one for
+    /// every queried partition. Timestamps are milliseconds since Unix epoch.
+    ///
+    /// Partitions without such message (e.g. timestamp is in the future) map to `None`.
+    pub async fn offsets_for_times(
+        &self,
+        queries: &[(KafkaPartition, i64)],
+    ) -> Result<HashMap<KafkaPartition, Option<OffsetForTime>>, Error> {
+        if let Some((partition, timestamp)) = queries.iter().find(|(_, t)| *t < 0) {
+            return Err(Error::ValueError(
+                format!("invalid timestamp {} for {}", timestamp, partition).into(),
+            ));
+        }
+
+        let found = self
+            .list_offsets(queries, IsolationLevel::ReadCommitted)
+            .await?;
+        Ok(found
+            .into_iter()
+            .map(|(partition, (timestamp, offset))| {
+                let found = if offset >= 0 {
+                    Some(OffsetForTime { offset, timestamp })
+                } else {
+                    None
+                };
+                (partition, found)
+            })
+            .collect())
+    }
+
+    /// Looks up offsets for given timestamps (or special `EARLIEST_TIMESTAMP`/`LATEST_TIMESTAMP`
+    /// values) asking partition leaders. Returns (timestamp, offset) found for each partition.
+    pub(crate) async fn list_offsets(
+        &self,
+        queries: &[(KafkaPartition, i64)],
+        isolation_level: IsolationLevel,
+    ) -> Result<HashMap<KafkaPartition, (i64, i64)>, Error> {
+        let leaders = self
+            .partition_leaders(queries.iter().map(|(p, _)| p.topic_name.as_str()))
+            .await?;
+
+        let mut per_broker: HashMap<BrokerId, HashMap<&str, Vec<ListOffsetsPartition>>> =
+            HashMap::new();
+        for (partition, timestamp) in queries {
+            let leader = leaders.get(partition).ok_or_else(|| {
+                Error::ClusterError(format!("Leader for {} not found", partition))
+            })?;
+            per_broker
+                .entry(*leader)
+                .or_default()
+                .entry(&partition.topic_name)
+                .or_default()
+                .push(ListOffsetsPartition {
+                    partition_index: partition.partition_index,
+                    timestamp: *timestamp,
+                });
+        }
+
+        let requests = per_broker.into_iter().map(|(broker, topics)| {
+            let request = ListOffsetsRequestV2 {
+                replica_id: -1,
+                isolation_level,
+                topics: topics
+                    .into_iter()
+                    .map(|(name, partitions)| ListOffsetsTopic {
+                        name: name.into(),
+                        partitions,
+                    })
+                    .collect(),
+            };
+            self.make_request(request, Some(broker))
+        });
+
+        let mut offsets = HashMap::new();
+        for response in future::try_join_all(requests).await? {
+            for t in response.topics {
+                for p in t.partitions {
+                    let partition = KafkaPartition {
+                        topic_name: t.name.clone(),
+                        partition_index: p.partition_index,
+                    };
+                    match p.error_code {
+                        ErrorCode::None => {
+                            offsets.insert(partition, (p.timestamp, p.offset));
+                        }
+                        error => {
+                            return Err(
+                                (error, Some(format!("list offsets for {}", partition))).into()
+                            )
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(offsets)
+    }
+
     async fn get_connection<'a>(
         &'a self,
         broker: Option<BrokerId>,
     ) -> Result<MutexGuard<'a, Managed>, Error> {
         match broker {
-            None => Ok(self.conns.values().next().unwrap().lock().await),
+            None => match self.conns.values().next() {
+                Some(conn) => Ok(conn.lock().await),
+                None => Err(Error::ClusterError("no brokers known".into())),
+            },
             Some(id) => match self.conns.get(&id) {
                 Some(conn) => Ok(conn.lock().await),
                 None => Err(Error::ClusterError(format!("Broker {} not found", id))),
@@ -78,6 +264,54 @@ impl AsyncClusterClient {
     }
 }
 
+fn metadata_request<'a, I>(topics: I) -> MetadataRequestV2
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    MetadataRequestV2 {
+        topics: topics
+            .into_iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>()
+            .into(),
+    }
+}
+
+fn leaders(response: MetadataResponseV2) -> Result<HashMap<KafkaPartition, BrokerId>, Error> {
+    let mut leaders = HashMap::new();
+    for t in response.topics {
+        if t.error != ErrorCode::None {
+            return Err((t.error, Some(format!("metadata error for {}", t.name))).into());
+        }
+        for p in t.partitions {
+            let partition = KafkaPartition {
+                topic_name: t.name.clone(),
+                partition_index: p.partition_index,
+            };
+            trace!("Found leader for {}: {}", partition, p.leader);
+            leaders.insert(partition, p.leader);
+        }
+    }
+
+    Ok(leaders)
+}
+
+fn timed_out(broker: Option<BrokerId>) -> Error {
+    let message = match broker {
+        Some(broker) => format!("request to broker {} timed out", broker),
+        None => "request timed out".to_string(),
+    };
+    io::Error::new(io::ErrorKind::TimedOut, message).into()
+}
+
+/// Result of timestamp lookup
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OffsetForTime {
+    pub offset: i64,
+    /// Timestamp of message at `offset`
+    pub timestamp: i64,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Broker {
     Any,
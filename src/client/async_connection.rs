@@ -35,10 +35,12 @@ impl BrokerConnection {
         }
     }
 
-    pub(crate) async fn make_request_no_parse<Req: KafkaRequest>(
+    /// Writes request without reading response, for requests broker does not respond to
+    /// (produce request with acks=0)
+    pub(crate) async fn send_request<Req: KafkaRequest>(
         &mut self,
         request: &Req,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<(), Error> {
         debug!("Request {}", Req::API_KEY);
         self.last_correlation_id += 1;
         let mut request_buffer = Vec::new();
@@ -49,6 +51,14 @@ impl BrokerConnection {
             Some(&self.client_id),
         )?;
         self.stream.write_all(&request_buffer).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn make_request_no_parse<Req: KafkaRequest>(
+        &mut self,
+        request: &Req,
+    ) -> Result<Vec<u8>, Error> {
+        self.send_request(request).await?;
         let response_bytes = self.read_response(self.last_correlation_id).await?;
         Ok(response_bytes)
     }
@@ -131,6 +141,11 @@ impl Managed {
 
         Ok(self.conn.as_mut().unwrap())
     }
+
+    /// Closes connection, next request connects again
+    pub fn disconnect(&mut self) {
+        self.conn = None;
+    }
 }
 
 #[cfg(test)]
@@ -177,7 +192,7 @@ mod test {
         init_logger();
         let mut c = connect().await?;
         let request = MetadataRequestV2 {
-            topics: vec![String::from("test-topic")],
+            topics: vec![String::from("test-topic")].into(),
         };
 
         let metadata = c.make_request(&request).await?;
@@ -5,4 +5,4 @@ mod async_connection;
 
 pub use config::{ClientConfig, ClientConfigBuilder};
 
-pub use async_cluster_client::{AsyncClusterClient, Broker};
+pub use async_cluster_client::{AsyncClusterClient, Broker, OffsetForTime};
@@ -0,0 +1,189 @@
+use super::assignment_stream::CommitSink;
+use crate::{Error, KafkaMessage, KafkaOffset, KafkaPartition};
+use futures::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Commits offsets of messages processed out of order.
+///
+/// Every message has to be tracked before processing starts and acked when it is done. Offset is
+/// committed only when all tracked messages of the partition up to it are acked, so none of the
+/// unprocessed messages are skipped after restart.
+#[derive(Clone)]
+pub struct AckTracker {
+    partitions: Arc<Mutex<HashMap<KafkaPartition, PartitionAcks>>>,
+    /// Held while committing so that offsets reach the sink in order
+    sink: Arc<tokio::sync::Mutex<CommitSink>>,
+}
+
+impl AckTracker {
+    pub fn new(sink: CommitSink) -> Self {
+        AckTracker {
+            partitions: Arc::new(Mutex::new(HashMap::new())),
+            sink: Arc::new(tokio::sync::Mutex::new(sink)),
+        }
+    }
+
+    /// Registers message whose processing is about to start
+    pub fn track(&self, message: &KafkaMessage) {
+        let partition = KafkaPartition {
+            topic_name: message.topic.to_string(),
+            partition_index: message.partition,
+        };
+        self.partitions
+            .lock()
+            .unwrap()
+            .entry(partition)
+            .or_default()
+            .track(message.offset);
+    }
+
+    /// Marks message as processed and commits the highest offset below which all tracked messages
+    /// were acked
+    pub async fn ack(&self, offset: &KafkaOffset<'_>) -> Result<(), Error> {
+        let mut sink = self.sink.lock().await;
+        let committable = {
+            let mut partitions = self.partitions.lock().unwrap();
+            let acks = partitions
+                .iter_mut()
+                .find(|(p, _)| {
+                    p.partition_index == offset.partition && p.topic_name == offset.topic
+                })
+                .map(|(_, acks)| acks)
+                .ok_or_else(|| untracked(offset))?;
+            acks.ack(offset.offset).ok_or_else(|| untracked(offset))?
+        };
+
+        match committable {
+            Some(committable) => {
+                sink.send(KafkaOffset {
+                    topic: offset.topic.to_string().into(),
+                    partition: offset.partition,
+                    offset: committable,
+                })
+                .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Number of tracked messages not acked yet
+    pub fn in_flight(&self) -> usize {
+        self.partitions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|acks| acks.in_flight)
+            .sum()
+    }
+}
+
+fn untracked(offset: &KafkaOffset) -> Error {
+    Error::ValueError(
+        format!(
+            "offset {} of {}[{}] is not tracked",
+            offset.offset, offset.topic, offset.partition
+        )
+        .into(),
+    )
+}
+
+#[derive(Debug, Default)]
+struct PartitionAcks {
+    /// Tracked offsets which are not committable yet, with their ack state
+    pending: BTreeMap<i64, bool>,
+    in_flight: usize,
+}
+
+impl PartitionAcks {
+    fn track(&mut self, offset: i64) {
+        if self.pending.insert(offset, false) != Some(false) {
+            self.in_flight += 1;
+        }
+    }
+
+    /// Returns `None` for untracked offset, otherwise new committable offset if it advanced
+    fn ack(&mut self, offset: i64) -> Option<Option<i64>> {
+        let acked = self.pending.get_mut(&offset)?;
+        if !*acked {
+            *acked = true;
+            self.in_flight -= 1;
+        }
+
+        let mut committable = None;
+        while let Some((&first, &true)) = self.pending.iter().next() {
+            self.pending.remove(&first);
+            committable = Some(first);
+        }
+        Some(committable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TimestampType;
+    use tokio::sync::mpsc;
+
+    fn message(offset: i64) -> KafkaMessage<'static> {
+        KafkaMessage {
+            topic: "t1".into(),
+            partition: 0,
+            offset,
+            timestamp: 0,
+            timestamp_type: TimestampType::CreateTime,
+            leader_epoch: 0,
+            key: None,
+            value: None,
+            headers: Vec::new().into(),
+        }
+    }
+
+    fn tracker() -> (AckTracker, mpsc::Receiver<KafkaOffset<'static>>) {
+        let (sender, receiver) = mpsc::channel(10);
+        (AckTracker::new(CommitSink::new(sender)), receiver)
+    }
+
+    fn committed(receiver: &mut mpsc::Receiver<KafkaOffset<'static>>) -> Vec<i64> {
+        let mut offsets = Vec::new();
+        while let Ok(o) = receiver.try_recv() {
+            offsets.push(o.offset);
+        }
+        offsets
+    }
+
+    #[tokio::test]
+    async fn commits_contiguous_acked_offsets() {
+        let (tracker, mut receiver) = tracker();
+        for offset in &[1, 2, 3, 5] {
+            tracker.track(&message(*offset));
+        }
+        assert_eq!(tracker.in_flight(), 4);
+
+        tracker.ack(&message(2).into_offset()).await.unwrap();
+        tracker.ack(&message(5).into_offset()).await.unwrap();
+        assert!(committed(&mut receiver).is_empty());
+        assert_eq!(tracker.in_flight(), 2);
+
+        tracker.ack(&message(1).into_offset()).await.unwrap();
+        assert_eq!(committed(&mut receiver), vec![2]);
+
+        // Gap in offsets (e.g. compacted message) does not block commits
+        tracker.ack(&message(3).into_offset()).await.unwrap();
+        assert_eq!(committed(&mut receiver), vec![5]);
+        assert_eq!(tracker.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn ack_of_untracked_offset_fails() {
+        let (tracker, _receiver) = tracker();
+        tracker.track(&message(1));
+
+        assert!(matches!(
+            tracker.ack(&message(2).into_offset()).await,
+            Err(Error::ValueError(_))
+        ));
+    }
+}
@@ -1,101 +1,252 @@
-use super::fetch_data::FetchResponse;
-use crate::{Error, KafkaMessage, KafkaOffset};
+use super::{
+    ack_tracker::AckTracker,
+    auto_commit::DeliveredOffsets,
+    fetch_data::FetchResponse,
+    metrics::ConsumerMetrics,
+    partition_stream::{partition_streams, PartitionStream},
+    prefetch::{prefetch_buffer, PrefetchReader, PrefetchWriter},
+    typed_stream::{typed_stream, DeserializeErrorPolicy, TypedMessage},
+    ConsumerError, FetchConfig, StartPosition,
+};
+use crate::{deserializer::Deserializer, Error, KafkaMessage, KafkaOffset, KafkaPartition};
 use futures::{prelude::*, stream};
-use log::warn;
 use std::{
     pin::Pin,
     task::{Context, Poll},
 };
 use tokio::sync::mpsc;
 
-/// StreamingConsumer
-pub struct AssignmentStream {
-    receiver: mpsc::Receiver<Assignment>,
+/// StreamingAssignment
+#[derive(Debug)]
+pub struct Assignment {
+    partitions: Vec<KafkaPartition>,
+    prefetch: PrefetchReader,
+    commit_sender: mpsc::Sender<KafkaOffset<'static>>,
+    control_sender: mpsc::UnboundedSender<ControlCommand>,
+    /// Set in auto commit mode
+    delivered: Option<DeliveredOffsets>,
+    group: Option<ConsumerGroupMetadata>,
+}
+
+/// Consumer task side of an `Assignment`
+pub(super) struct AssignmentFeed {
+    pub prefetch: PrefetchWriter,
+    pub commit_receiver: mpsc::Receiver<KafkaOffset<'static>>,
+    pub control_receiver: mpsc::UnboundedReceiver<ControlCommand>,
+    /// Offsets of messages handed to user, tracked in auto commit mode
+    pub delivered: Option<DeliveredOffsets>,
+    /// Updated with fetch and commit results if consumer exposes metrics
+    pub metrics: Option<ConsumerMetrics>,
+    /// Consumer assignment stream, partition errors are reported to user through it
+    pub errors: Option<mpsc::Sender<Result<Assignment, ConsumerError>>>,
 }
 
-impl AssignmentStream {
-    pub(super) fn new(receiver: mpsc::Receiver<Assignment>) -> Self {
-        AssignmentStream { receiver }
+impl Assignment {
+    pub(super) fn new(
+        partitions: Vec<KafkaPartition>,
+        auto_commit: bool,
+        config: &FetchConfig,
+    ) -> (Self, AssignmentFeed) {
+        let (prefetch_writer, prefetch) = prefetch_buffer(config);
+        let (commit_sender, commit_receiver) = mpsc::channel(10);
+        let (control_sender, control_receiver) = mpsc::unbounded_channel();
+        let delivered = if auto_commit {
+            Some(DeliveredOffsets::new())
+        } else {
+            None
+        };
+
+        let assignment = Assignment {
+            partitions,
+            prefetch,
+            commit_sender,
+            control_sender,
+            delivered: delivered.clone(),
+            group: None,
+        };
+        let feed = AssignmentFeed {
+            prefetch: prefetch_writer,
+            commit_receiver,
+            control_receiver,
+            delivered,
+            metrics: None,
+            errors: None,
+        };
+
+        (assignment, feed)
     }
 
+    pub(super) fn with_group_metadata(mut self, group: ConsumerGroupMetadata) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Partitions assigned to consumer
+    pub fn partitions(&self) -> &[KafkaPartition] {
+        &self.partitions
+    }
+
+    /// Group generation the assignment belongs to, `None` for standalone consumer. Passed to
+    /// `Producer::send_offsets_to_transaction` when consumed offsets are committed by producer.
+    pub fn group_metadata(&self) -> Option<&ConsumerGroupMetadata> {
+        self.group.as_ref()
+    }
+
+    /// Stream of fetched data, each item holds all data buffered since the previous one
     pub fn into_fetch_stream(self) -> impl Stream<Item = FetchResponse> {
-        self.map(|assignment| assignment.into_fetch_stream())
-            .flatten()
+        let delivered = self.delivered;
+        let prefetch = self.prefetch;
+        stream::poll_fn(move |cx| prefetch.poll_fetch(cx)).inspect(move |fetch| {
+            if let Some(delivered) = delivered.as_ref() {
+                for (topic, partition, offset) in fetch.last_offsets() {
+                    delivered.record(topic, partition, offset);
+                }
+            }
+        })
+    }
+
+    /// Splits assignment into separate stream for every assigned partition, so partitions can be
+    /// processed concurrently. Partition whose stream is not polled stops being fetched once its
+    /// prefetch budget is used, without affecting other partitions. Partition whose stream is
+    /// dropped is not fetched anymore and its buffered data is released.
+    pub fn into_partition_streams(self) -> Vec<PartitionStream> {
+        partition_streams(
+            &self.partitions,
+            self.prefetch,
+            self.commit_sender,
+            self.delivered,
+        )
     }
 
     pub fn into_message_stream(self) -> impl Stream<Item = KafkaMessage<'static>> {
-        self.into_fetch_stream()
+        let delivered = self.delivered;
+        let prefetch = self.prefetch;
+        stream::poll_fn(move |cx| prefetch.poll_fetch(cx))
             .map(|f| stream::iter(f.into_messages_owned()))
             .flatten()
+            .inspect(move |m| {
+                if let Some(delivered) = delivered.as_ref() {
+                    delivered.record(&m.topic, m.partition, m.offset);
+                }
+            })
+    }
+
+    /// Stream of messages with keys and values decoded by given deserializers. Messages that
+    /// fail to decode are handled according to `policy`.
+    pub fn into_typed_stream<KD, VD>(
+        self,
+        key_deserializer: KD,
+        value_deserializer: VD,
+        policy: DeserializeErrorPolicy,
+    ) -> impl Stream<Item = Result<TypedMessage<KD::Item, VD::Item>, Error>>
+    where
+        KD: Deserializer,
+        VD: Deserializer,
+    {
+        typed_stream(
+            self.into_message_stream(),
+            key_deserializer,
+            value_deserializer,
+            policy,
+        )
     }
-}
 
-impl Stream for AssignmentStream {
-    type Item = Assignment;
+    pub fn commit_sink(&self) -> CommitSink {
+        CommitSink::new(self.commit_sender.clone())
+    }
 
-    fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        self.receiver.poll_next_unpin(cx)
+    /// Commits offsets of messages acked out of order, see `AckTracker`
+    pub fn ack_tracker(&self) -> AckTracker {
+        AckTracker::new(self.commit_sink())
+    }
+
+    /// Handle used to seek, pause and resume assigned partitions
+    pub fn control(&self) -> AssignmentControl {
+        AssignmentControl {
+            sender: self.control_sender.clone(),
+        }
     }
 }
 
-/// StreamingAssignment
-#[derive(Debug)]
-pub struct Assignment {
-    fetch_receiver: mpsc::Receiver<FetchResponse>,
-    commit_sender: mpsc::Sender<KafkaOffset<'static>>,
+/// Consumer group membership of an assignment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerGroupMetadata {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    /// Id of static member (KIP-345), consumer joins groups as dynamic member only
+    pub group_instance_id: Option<String>,
 }
 
-impl Assignment {
-    pub(super) fn new(
-        fetch_receiver: mpsc::Receiver<FetchResponse>,
-        commit_sender: mpsc::Sender<KafkaOffset<'static>>,
-    ) -> Self {
-        Assignment {
-            fetch_receiver,
-            commit_sender,
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ControlCommand {
+    Seek(KafkaPartition, StartPosition),
+    Pause(KafkaPartition),
+    Resume(KafkaPartition),
+}
+
+/// Controls fetching of assigned partitions.
+///
+/// Commands are applied by consumer task between fetches. Seek drops data of the partition
+/// fetched from the old position, except messages the assignment stream has already taken.
+#[derive(Debug, Clone)]
+pub struct AssignmentControl {
+    sender: mpsc::UnboundedSender<ControlCommand>,
+}
+
+impl AssignmentControl {
+    /// Continues fetching partition from given position
+    pub fn seek(&self, partition: KafkaPartition, position: StartPosition) -> Result<(), Error> {
+        self.send(ControlCommand::Seek(partition, position))
     }
 
-    pub fn into_fetch_stream(self) -> impl Stream<Item = FetchResponse> {
-        self.fetch_receiver
+    /// Stops fetching partition until it is resumed
+    pub fn pause(&self, partition: KafkaPartition) -> Result<(), Error> {
+        self.send(ControlCommand::Pause(partition))
     }
 
-    pub fn into_message_stream(self) -> impl Stream<Item = KafkaMessage<'static>> {
-        self.fetch_receiver
-            .map(|f| stream::iter(f.into_messages_owned()))
-            .flatten()
+    pub fn resume(&self, partition: KafkaPartition) -> Result<(), Error> {
+        self.send(ControlCommand::Resume(partition))
     }
 
-    pub fn commit_sink(&self) -> CommitSink {
-        CommitSink
+    fn send(&self, command: ControlCommand) -> Result<(), Error> {
+        self.sender
+            .send(command)
+            .map_err(|_| Error::AssignmentClosed)
     }
 }
 
-pub struct CommitSink;
+/// Sink of offsets to commit. Offsets are committed by consumer task in the background.
+pub struct CommitSink {
+    sender: mpsc::Sender<KafkaOffset<'static>>,
+}
+
+impl CommitSink {
+    pub(super) fn new(sender: mpsc::Sender<KafkaOffset<'static>>) -> Self {
+        CommitSink { sender }
+    }
+}
 
 impl Sink<KafkaOffset<'static>> for CommitSink {
     type Error = Error;
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        warn!("CommitSink::poll_ready not implemented");
-        Poll::Ready(Ok(()))
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.sender
+            .poll_ready(cx)
+            .map_err(|_| Error::AssignmentClosed)
     }
 
-    fn start_send(self: Pin<&mut Self>, item: KafkaOffset) -> Result<(), Self::Error> {
-        warn!("CommitSink::start_send not implemented");
-        Ok(())
+    fn start_send(mut self: Pin<&mut Self>, item: KafkaOffset<'static>) -> Result<(), Self::Error> {
+        self.sender
+            .try_send(item)
+            .map_err(|_| Error::AssignmentClosed)
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        warn!("MessageStream::poll_flush not implemented");
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        warn!("MessageStream::poll_close not implemented");
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 }
@@ -0,0 +1,337 @@
+use crate::Error;
+use rskafka_proto::apis::metadata::MetadataResponseV2;
+use rskafka_wire_format::prelude::*;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// Partition assignment strategy.
+///
+/// Every assignor configured for the consumer is advertised to the group coordinator. The
+/// coordinator picks one protocol supported by all members and the group leader uses the matching
+/// assignor to distribute partitions.
+pub trait Assignor: Send + Sync {
+    /// Protocol name advertised in JoinGroup request
+    fn name(&self) -> &str;
+
+    /// Opaque data attached to this member's subscription
+    fn subscription_user_data(&self, _topics: &[String]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Distributes partitions between group members. Called on group leader only.
+    ///
+    /// Returned map is keyed by member id. Members missing from the map get an empty assignment.
+    fn assign(
+        &self,
+        members: &[MemberSubscription],
+        metadata: &MetadataResponseV2,
+    ) -> Result<HashMap<String, MemberAssignment>, Error>;
+
+    /// Called on every member with the assignment received from group leader
+    fn on_assignment(&self, _assignment: &MemberAssignment) {}
+}
+
+/// Group member subscription as seen by group leader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberSubscription {
+    pub member_id: String,
+    pub topics: Vec<String>,
+    pub user_data: Option<Vec<u8>>,
+}
+
+impl MemberSubscription {
+    pub fn is_subscribed(&self, topic: &str) -> bool {
+        self.topics.iter().any(|t| t == topic)
+    }
+}
+
+/// Partitions assigned to a single group member
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemberAssignment {
+    pub partitions: HashMap<String, Vec<i32>>,
+    pub user_data: Option<Vec<u8>>,
+}
+
+impl MemberAssignment {
+    pub fn add(&mut self, topic: &str, partition: i32) {
+        match self.partitions.get_mut(topic) {
+            Some(partitions) => partitions.push(partition),
+            None => {
+                self.partitions.insert(topic.to_owned(), vec![partition]);
+            }
+        }
+    }
+}
+
+/// Assignors used when none are configured explicitly (in order of preference)
+pub fn default_assignors() -> Vec<Arc<dyn Assignor>> {
+    vec![Arc::new(RangeAssignor), Arc::new(RoundRobinAssignor)]
+}
+
+/// Assigns consecutive ranges of partitions of each topic to members subscribed to it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RangeAssignor;
+
+impl Assignor for RangeAssignor {
+    fn name(&self) -> &str {
+        "range"
+    }
+
+    fn assign(
+        &self,
+        members: &[MemberSubscription],
+        metadata: &MetadataResponseV2,
+    ) -> Result<HashMap<String, MemberAssignment>, Error> {
+        let mut assignments = empty_assignments(members);
+        for (topic, partitions) in partitions_per_topic(members, metadata)? {
+            let mut subscribed: Vec<&str> = members
+                .iter()
+                .filter(|m| m.is_subscribed(topic))
+                .map(|m| m.member_id.as_str())
+                .collect();
+            subscribed.sort();
+
+            let per_member = partitions.len() / subscribed.len();
+            let extra = partitions.len() % subscribed.len();
+            let mut partitions = partitions.into_iter();
+            for (idx, member_id) in subscribed.into_iter().enumerate() {
+                let count = per_member + if idx < extra { 1 } else { 0 };
+                let assignment = assignments.get_mut(member_id).unwrap(); // all members are present
+                for partition in partitions.by_ref().take(count) {
+                    assignment.add(topic, partition);
+                }
+            }
+        }
+
+        Ok(assignments)
+    }
+}
+
+/// Assigns all subscribed partitions one by one to subscribed members in round robin fashion.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoundRobinAssignor;
+
+impl Assignor for RoundRobinAssignor {
+    fn name(&self) -> &str {
+        "roundrobin"
+    }
+
+    fn assign(
+        &self,
+        members: &[MemberSubscription],
+        metadata: &MetadataResponseV2,
+    ) -> Result<HashMap<String, MemberAssignment>, Error> {
+        let mut assignments = empty_assignments(members);
+        let mut sorted_members: Vec<&MemberSubscription> = members.iter().collect();
+        sorted_members.sort_by_key(|m| &m.member_id);
+        let mut cycle = sorted_members.iter().cycle();
+
+        for (topic, partitions) in partitions_per_topic(members, metadata)? {
+            for partition in partitions {
+                // Topic comes from member subscriptions so at least one member is subscribed
+                let member = cycle.find(|m| m.is_subscribed(topic)).unwrap();
+                assignments
+                    .get_mut(member.member_id.as_str())
+                    .unwrap()
+                    .add(topic, partition);
+            }
+        }
+
+        Ok(assignments)
+    }
+}
+
+fn empty_assignments(members: &[MemberSubscription]) -> HashMap<String, MemberAssignment> {
+    members
+        .iter()
+        .map(|m| (m.member_id.clone(), MemberAssignment::default()))
+        .collect()
+}
+
+/// Sorted partitions of every topic subscribed by at least one member
+fn partitions_per_topic<'a>(
+    members: &'a [MemberSubscription],
+    metadata: &MetadataResponseV2,
+) -> Result<BTreeMap<&'a str, Vec<i32>>, Error> {
+    let mut partitions_per_topic = BTreeMap::new();
+    for topic in members.iter().flat_map(|m| m.topics.iter()) {
+        if partitions_per_topic.contains_key(topic.as_str()) {
+            continue;
+        }
+        let topic_metadata = metadata
+            .topics
+            .iter()
+            .find(|t| &t.name == topic)
+            .ok_or_else(|| {
+                Error::AssignmentFailed(format!("missing metadata for {}", topic).into())
+            })?;
+        let mut partitions: Vec<i32> = topic_metadata
+            .partitions
+            .iter()
+            .map(|p| p.partition_index)
+            .collect();
+        partitions.sort();
+        partitions_per_topic.insert(topic.as_str(), partitions);
+    }
+
+    Ok(partitions_per_topic)
+}
+
+//todo: rename to sth like: Private/Custom
+//todo: get rid of cow and as ref for simplicity
+#[derive(Debug, Clone, WireFormatWrite, WireFormatParse)]
+pub(super) struct GroupProtocolMetadata<'a, S: AsRef<str> + Clone> {
+    pub version: i16,
+    pub topics: Cow<'a, [S]>,
+    pub user_data: NullableBytes,
+}
+
+pub(super) type GroupProtocolMetadataOwned = GroupProtocolMetadata<'static, String>;
+
+impl<'a, S: AsRef<str> + Clone> GroupProtocolMetadata<'a, S> {
+    pub fn new(topics: Cow<'a, [S]>, user_data: Option<Vec<u8>>) -> Self {
+        GroupProtocolMetadata {
+            version: 0,
+            topics,
+            user_data: user_data.into(),
+        }
+    }
+}
+
+//todo: rename to sth like: Private/Custom
+#[derive(Debug, Clone, WireFormatWrite, WireFormatParse)]
+pub(super) struct AssignmentMetadata {
+    pub version: i16,
+    pub topics: Vec<(String, Vec<i32>)>,
+    pub user_data: NullableBytes,
+}
+
+impl From<MemberAssignment> for AssignmentMetadata {
+    fn from(v: MemberAssignment) -> Self {
+        AssignmentMetadata {
+            version: 0,
+            topics: v.partitions.into_iter().collect(),
+            user_data: v.user_data.into(),
+        }
+    }
+}
+
+impl From<AssignmentMetadata> for MemberAssignment {
+    fn from(v: AssignmentMetadata) -> Self {
+        MemberAssignment {
+            partitions: v.topics.into_iter().collect(),
+            user_data: v.user_data.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::{
+        apis::metadata::{PartitionMetadata, TopicMetadata},
+        ErrorCode,
+    };
+
+    fn metadata(topics: &[(&str, i32)]) -> MetadataResponseV2 {
+        MetadataResponseV2 {
+            brokers: Vec::new(),
+            cluster_id: NullableString::with_null(),
+            controller_id: 1,
+            topics: topics
+                .iter()
+                .map(|(name, partitions)| TopicMetadata {
+                    error: ErrorCode::None,
+                    name: name.to_string(),
+                    is_internal: false,
+                    partitions: (0..*partitions)
+                        .map(|partition_index| PartitionMetadata {
+                            error: ErrorCode::None,
+                            partition_index,
+                            leader: 1.into(),
+                            replicas: vec![1],
+                            isr: vec![1],
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn member(member_id: &str, topics: &[&str]) -> MemberSubscription {
+        MemberSubscription {
+            member_id: member_id.into(),
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            user_data: None,
+        }
+    }
+
+    fn partitions(assignment: &MemberAssignment, topic: &str) -> Vec<i32> {
+        assignment
+            .partitions
+            .get(topic)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn range_assignor_splits_partitions_in_ranges() {
+        let members = vec![member("b", &["t1", "t2"]), member("a", &["t1"])];
+        let assignments = RangeAssignor
+            .assign(&members, &metadata(&[("t1", 5), ("t2", 2)]))
+            .unwrap();
+
+        assert_eq!(partitions(&assignments["a"], "t1"), vec![0, 1, 2]);
+        assert_eq!(partitions(&assignments["b"], "t1"), vec![3, 4]);
+        assert_eq!(partitions(&assignments["a"], "t2"), Vec::<i32>::new());
+        assert_eq!(partitions(&assignments["b"], "t2"), vec![0, 1]);
+    }
+
+    #[test]
+    fn roundrobin_assignor_cycles_over_members() {
+        let members = vec![
+            member("b", &["t1"]),
+            member("a", &["t1"]),
+            member("c", &["t2"]),
+        ];
+        let assignments = RoundRobinAssignor
+            .assign(&members, &metadata(&[("t1", 3), ("t2", 1)]))
+            .unwrap();
+
+        assert_eq!(partitions(&assignments["a"], "t1"), vec![0, 2]);
+        assert_eq!(partitions(&assignments["b"], "t1"), vec![1]);
+        assert_eq!(partitions(&assignments["c"], "t2"), vec![0]);
+    }
+
+    #[test]
+    fn members_without_partitions_get_empty_assignment() {
+        let members = vec![member("a", &["t1"]), member("b", &["t1"])];
+        let assignments = RangeAssignor
+            .assign(&members, &metadata(&[("t1", 1)]))
+            .unwrap();
+
+        assert_eq!(assignments["b"], MemberAssignment::default());
+    }
+
+    #[test]
+    fn assignment_fails_on_missing_metadata() {
+        let members = vec![member("a", &["t1"])];
+        assert!(RoundRobinAssignor
+            .assign(&members, &metadata(&[("t2", 1)]))
+            .is_err());
+    }
+
+    #[test]
+    fn group_protocol_metadata_with_user_data() {
+        let topics = vec!["t1".to_string()];
+        let bytes =
+            GroupProtocolMetadata::new(topics.as_slice().into(), Some(vec![1, 2])).to_wire_bytes();
+        let parsed = GroupProtocolMetadataOwned::from_wire_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed.topics.as_ref(), topics.as_slice());
+        assert_eq!(parsed.user_data, NullableBytes(Some(vec![1, 2])));
+    }
+}
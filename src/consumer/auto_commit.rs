@@ -0,0 +1,141 @@
+use super::{Assignment, ConsumerError};
+use crate::{KafkaOffset, KafkaPartition};
+use anyhow::Error;
+use log::error;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// Offsets of messages handed to user by assignment streams, waiting to be auto committed.
+///
+/// Shared between assignment streams (which record offsets) and consumer task (which commits
+/// them).
+#[derive(Debug, Clone, Default)]
+pub(super) struct DeliveredOffsets(Arc<Mutex<HashMap<KafkaPartition, i64>>>);
+
+impl DeliveredOffsets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, topic: &str, partition: i32, offset: i64) {
+        let partition = KafkaPartition {
+            topic_name: topic.to_owned(),
+            partition_index: partition,
+        };
+        // Last delivered offset wins, partition could have been seeked back
+        self.0.lock().unwrap().insert(partition, offset);
+    }
+
+    /// Removes offsets delivered since last call
+    pub fn take(&self) -> Vec<KafkaOffset<'static>> {
+        self.0
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(p, offset)| KafkaOffset {
+                topic: p.topic_name.into(),
+                partition: p.partition_index,
+                offset,
+            })
+            .collect()
+    }
+
+    /// Puts back offsets which failed to commit, unless newer ones were delivered meanwhile
+    pub fn restore(&self, offsets: Vec<KafkaOffset<'static>>) {
+        let mut delivered = self.0.lock().unwrap();
+        for o in offsets {
+            let partition = KafkaPartition {
+                topic_name: o.topic.into_owned(),
+                partition_index: o.partition,
+            };
+            delivered.entry(partition).or_insert(o.offset);
+        }
+    }
+}
+
+/// Schedule of periodic offset commits
+#[derive(Debug)]
+pub(super) struct AutoCommit {
+    interval: Duration,
+    due: Instant,
+    /// Consumer assignment stream, commit failures are reported to user through it
+    errors: mpsc::Sender<Result<Assignment, ConsumerError>>,
+}
+
+impl AutoCommit {
+    pub fn new(
+        interval: Duration,
+        errors: mpsc::Sender<Result<Assignment, ConsumerError>>,
+    ) -> Self {
+        AutoCommit {
+            interval,
+            due: Instant::now() + interval,
+            errors,
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.due
+    }
+
+    pub fn reschedule(&mut self) {
+        self.due = Instant::now() + self.interval;
+    }
+
+    /// Passes commit failure to the assignment stream. Failure is only logged if user does not
+    /// keep up with reading the stream, so that consumer task never blocks on it.
+    pub fn report(&mut self, error: Error) {
+        error!("Auto commit failed: {:#}", error);
+        let _ = self
+            .errors
+            .try_send(Err(ConsumerError(error.context("auto commit failed"))));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn offsets(delivered: &DeliveredOffsets) -> Vec<(String, i32, i64)> {
+        let mut offsets: Vec<_> = delivered
+            .take()
+            .into_iter()
+            .map(|o| (o.topic.into_owned(), o.partition, o.offset))
+            .collect();
+        offsets.sort();
+        offsets
+    }
+
+    #[test]
+    fn take_returns_last_delivered_offsets() {
+        let delivered = DeliveredOffsets::new();
+        delivered.record("t1", 0, 5);
+        delivered.record("t1", 0, 7);
+        delivered.record("t1", 1, 2);
+
+        assert_eq!(
+            offsets(&delivered),
+            vec![("t1".to_string(), 0, 7), ("t1".to_string(), 1, 2)]
+        );
+        assert!(delivered.take().is_empty());
+    }
+
+    #[test]
+    fn restore_keeps_newer_offsets() {
+        let delivered = DeliveredOffsets::new();
+        delivered.record("t1", 0, 5);
+        delivered.record("t1", 1, 2);
+        let failed = delivered.take();
+        delivered.record("t1", 0, 8);
+        delivered.restore(failed);
+
+        assert_eq!(
+            offsets(&delivered),
+            vec![("t1".to_string(), 0, 8), ("t1".to_string(), 1, 2)]
+        );
+    }
+}
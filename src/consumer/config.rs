@@ -0,0 +1,577 @@
+use super::{
+    assignor::{self, Assignor},
+    position::{OffsetReset, StartPosition},
+    rebalance::RebalanceListener,
+};
+use crate::Error;
+use regex::Regex;
+use rskafka_proto::apis::fetch::IsolationLevel;
+use std::{convert::TryFrom, sync::Arc, time::Duration};
+
+pub struct ConsumerConfig {
+    pub(crate) topics: Vec<String>,
+    /// Pattern matching whole topic names, subscribes to all matching topics instead of `topics`
+    pub(crate) topic_pattern: Option<Regex>,
+    pub(crate) metadata_refresh_interval: Duration,
+    pub(crate) group_id: String,
+    pub(crate) client_id: String,
+    /// Partition assignment strategies in order of preference
+    pub(crate) assignors: Vec<Arc<dyn Assignor>>,
+    pub(crate) fetch: FetchConfig,
+    /// Position of partitions without offset committed by the group
+    pub(crate) start_position: StartPosition,
+    pub(crate) session_timeout_ms: i32,
+    pub(crate) rebalance_timeout_ms: i32,
+    pub(crate) heartbeat_interval: Duration,
+    pub(crate) rebalance_listener: Option<Arc<dyn RebalanceListener>>,
+    /// Interval of committing offsets of messages delivered by assignment streams
+    pub(crate) auto_commit_interval: Option<Duration>,
+    pub(crate) retry_backoff: Duration,
+    pub(crate) retry_backoff_max: Duration,
+    /// Consecutive retries of failed group requests after which consumer stops
+    pub(crate) group_retries: u32,
+}
+
+impl ConsumerConfig {
+    pub fn builder() -> ConsumerConfigBuilder {
+        ConsumerConfigBuilder::default()
+    }
+}
+
+pub struct ConsumerConfigBuilder {
+    topics: Vec<String>,
+    topic_pattern: Option<Regex>,
+    metadata_refresh_interval: Duration,
+    group_id: Option<String>,
+    client_id: String,
+    assignors: Vec<Arc<dyn Assignor>>,
+    fetch: FetchConfig,
+    start_position: StartPosition,
+    session_timeout: Duration,
+    rebalance_timeout: Duration,
+    heartbeat_interval: Duration,
+    rebalance_listener: Option<Arc<dyn RebalanceListener>>,
+    auto_commit_interval: Option<Duration>,
+    retry_backoff: Duration,
+    retry_backoff_max: Duration,
+    group_retries: u32,
+}
+
+impl Default for ConsumerConfigBuilder {
+    fn default() -> Self {
+        ConsumerConfigBuilder {
+            topics: Vec::new(),
+            topic_pattern: None,
+            metadata_refresh_interval: Duration::from_secs(5 * 60),
+            group_id: None,
+            client_id: "rskafka".to_string(),
+            assignors: assignor::default_assignors(),
+            fetch: FetchConfig::default(),
+            start_position: StartPosition::Committed,
+            session_timeout: Duration::from_secs(30),
+            rebalance_timeout: Duration::from_secs(10),
+            heartbeat_interval: Duration::from_secs(3),
+            rebalance_listener: None,
+            auto_commit_interval: None,
+            retry_backoff: Duration::from_millis(100),
+            retry_backoff_max: Duration::from_secs(5),
+            group_retries: 10,
+        }
+    }
+}
+
+impl ConsumerConfigBuilder {
+    pub fn topics(mut self, val: Vec<String>) -> Self {
+        self.topics = val;
+        self
+    }
+
+    /// Subscribes to all non-internal topics whose whole name matches the pattern (e.g.
+    /// `cdc\.db\..*`). Topics are re-checked every `metadata_refresh_interval` and consumer
+    /// rejoins the group when matching topics appear or disappear.
+    pub fn topic_pattern(mut self, val: Regex) -> Self {
+        self.topic_pattern = Some(val);
+        self
+    }
+
+    /// Interval of checking cluster metadata for topics matching `topic_pattern`
+    pub fn metadata_refresh_interval(mut self, val: Duration) -> Self {
+        self.metadata_refresh_interval = val;
+        self
+    }
+
+    pub fn group_id(mut self, val: String) -> Self {
+        self.group_id = Some(val);
+        self
+    }
+
+    pub fn client_id(mut self, val: String) -> Self {
+        self.client_id = val;
+        self
+    }
+
+    /// Partition assignment strategies in order of preference
+    pub fn assignors(mut self, val: Vec<Arc<dyn Assignor>>) -> Self {
+        self.assignors = val;
+        self
+    }
+
+    pub fn fetch(mut self, val: FetchConfig) -> Self {
+        self.fetch = val;
+        self
+    }
+
+    /// Position consumption of a partition starts from when the group has no committed offset for
+    /// it, e.g. `StartPosition::Timestamp` to replay messages since given time. Partitions with
+    /// committed offset always resume from it. Defaults to `StartPosition::Committed`.
+    pub fn start_position(mut self, val: StartPosition) -> Self {
+        self.start_position = val;
+        self
+    }
+
+    /// Time after which coordinator considers silent group member dead
+    pub fn session_timeout(mut self, val: Duration) -> Self {
+        self.session_timeout = val;
+        self
+    }
+
+    /// Time coordinator waits for all members to rejoin during rebalance
+    pub fn rebalance_timeout(mut self, val: Duration) -> Self {
+        self.rebalance_timeout = val;
+        self
+    }
+
+    /// Interval of heartbeats sent to group coordinator, must be lower than session timeout
+    pub fn heartbeat_interval(mut self, val: Duration) -> Self {
+        self.heartbeat_interval = val;
+        self
+    }
+
+    /// Listener notified when assigned partitions change
+    pub fn rebalance_listener(mut self, val: Arc<dyn RebalanceListener>) -> Self {
+        self.rebalance_listener = Some(val);
+        self
+    }
+
+    /// Enables auto commit mode. Offsets of messages delivered by assignment streams are committed
+    /// periodically and before partitions are revoked or consumer is stopped. Commit failures are
+    /// reported as errors in consumer's assignment stream.
+    pub fn auto_commit_interval(mut self, val: Duration) -> Self {
+        self.auto_commit_interval = Some(val);
+        self
+    }
+
+    /// Delay before retrying group requests which failed because coordinator moved, was not
+    /// available or group was rebalancing. Delay doubles with each consecutive failure up to `retry_backoff_max`.
+    pub fn retry_backoff(mut self, val: Duration) -> Self {
+        self.retry_backoff = val;
+        self
+    }
+
+    pub fn retry_backoff_max(mut self, val: Duration) -> Self {
+        self.retry_backoff_max = val;
+        self
+    }
+
+    /// Consecutive retries of joining group (or finding its coordinator) after which consumer
+    /// gives up and ends its assignment stream with the last error. Counter is reset once member
+    /// joins the group.
+    pub fn group_retries(mut self, val: u32) -> Self {
+        self.group_retries = val;
+        self
+    }
+
+    pub fn build(self) -> Result<ConsumerConfig, Error> {
+        let topic_pattern = match self.topic_pattern {
+            Some(_) if !self.topics.is_empty() => {
+                return Err(Error::InvalidConfig(
+                    "topic_pattern",
+                    "cannot be combined with topics".into(),
+                ))
+            }
+            Some(pattern) => Some(
+                Regex::new(&format!("^(?:{})$", pattern.as_str()))
+                    .map_err(|e| Error::InvalidConfig("topic_pattern", e.to_string().into()))?,
+            ),
+            None if self.topics.is_empty() => return Err(Error::IncompleteConfig("topics")),
+            None => None,
+        };
+        let group_id = self
+            .group_id
+            .map(Ok)
+            .unwrap_or(Err(Error::IncompleteConfig("group_id")))?;
+        if self.assignors.is_empty() {
+            return Err(Error::IncompleteConfig("assignors"));
+        }
+        if let StartPosition::Timestamp(timestamp) = self.start_position {
+            if timestamp < 0 {
+                return Err(Error::InvalidConfig(
+                    "start_position",
+                    "timestamp cannot be negative".into(),
+                ));
+            }
+        }
+        let session_timeout_ms = positive_millis("session_timeout", self.session_timeout)?;
+        let rebalance_timeout_ms = positive_millis("rebalance_timeout", self.rebalance_timeout)?;
+        if self.heartbeat_interval.as_millis() == 0 {
+            return Err(Error::InvalidConfig(
+                "heartbeat_interval",
+                "must be positive".into(),
+            ));
+        }
+        if self.heartbeat_interval >= self.session_timeout {
+            return Err(Error::InvalidConfig(
+                "heartbeat_interval",
+                "must be lower than session_timeout".into(),
+            ));
+        }
+
+        if let Some(interval) = self.auto_commit_interval {
+            positive_millis("auto_commit_interval", interval)?;
+        }
+        positive_millis("metadata_refresh_interval", self.metadata_refresh_interval)?;
+        positive_millis("retry_backoff", self.retry_backoff)?;
+        if self.retry_backoff_max < self.retry_backoff {
+            return Err(Error::InvalidConfig(
+                "retry_backoff_max",
+                "cannot be lower than retry_backoff".into(),
+            ));
+        }
+
+        Ok(ConsumerConfig {
+            topics: self.topics,
+            topic_pattern,
+            metadata_refresh_interval: self.metadata_refresh_interval,
+            group_id,
+            client_id: self.client_id,
+            assignors: self.assignors,
+            fetch: self.fetch,
+            start_position: self.start_position,
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            heartbeat_interval: self.heartbeat_interval,
+            rebalance_listener: self.rebalance_listener,
+            auto_commit_interval: self.auto_commit_interval,
+            retry_backoff: self.retry_backoff,
+            retry_backoff_max: self.retry_backoff_max,
+            group_retries: self.group_retries,
+        })
+    }
+}
+
+/// Limits applied to fetch requests. Partitions led by the same broker are fetched with a single
+/// request and requests to different brokers are sent concurrently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchConfig {
+    pub(crate) max_wait_ms: i32,
+    pub(crate) min_bytes: i32,
+    pub(crate) max_bytes: i32,
+    pub(crate) partition_max_bytes: i32,
+    pub(crate) isolation_level: IsolationLevel,
+    pub(crate) client_rack: Option<String>,
+    /// Fetched bytes buffered for a single partition, beyond which partition is not fetched
+    pub(crate) prefetch_partition_bytes: usize,
+    /// Fetched bytes buffered for all partitions, beyond which fetching stops
+    pub(crate) prefetch_total_bytes: usize,
+    pub(crate) offset_reset: OffsetReset,
+}
+
+impl FetchConfig {
+    pub fn builder() -> FetchConfigBuilder {
+        FetchConfigBuilder::default()
+    }
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            max_wait_ms: 500,
+            min_bytes: 1,
+            max_bytes: 50 * 1024 * 1024,
+            partition_max_bytes: 1024 * 1024,
+            isolation_level: IsolationLevel::ReadCommitted,
+            client_rack: None,
+            prefetch_partition_bytes: 2 * 1024 * 1024,
+            prefetch_total_bytes: 32 * 1024 * 1024,
+            offset_reset: OffsetReset::Earliest,
+        }
+    }
+}
+
+pub struct FetchConfigBuilder {
+    max_wait: Duration,
+    min_bytes: i32,
+    max_bytes: i32,
+    partition_max_bytes: i32,
+    isolation_level: IsolationLevel,
+    client_rack: Option<String>,
+    prefetch_partition_bytes: usize,
+    prefetch_total_bytes: usize,
+    offset_reset: OffsetReset,
+}
+
+impl Default for FetchConfigBuilder {
+    fn default() -> Self {
+        let defaults = FetchConfig::default();
+        FetchConfigBuilder {
+            max_wait: Duration::from_millis(defaults.max_wait_ms as u64),
+            min_bytes: defaults.min_bytes,
+            max_bytes: defaults.max_bytes,
+            partition_max_bytes: defaults.partition_max_bytes,
+            isolation_level: defaults.isolation_level,
+            client_rack: defaults.client_rack,
+            prefetch_partition_bytes: defaults.prefetch_partition_bytes,
+            prefetch_total_bytes: defaults.prefetch_total_bytes,
+            offset_reset: defaults.offset_reset,
+        }
+    }
+}
+
+impl FetchConfigBuilder {
+    /// Maximum time broker waits for `min_bytes` to become available
+    pub fn max_wait(mut self, val: Duration) -> Self {
+        self.max_wait = val;
+        self
+    }
+
+    pub fn min_bytes(mut self, val: i32) -> Self {
+        self.min_bytes = val;
+        self
+    }
+
+    /// Maximum size of a single fetch response (limit applies to each broker separately)
+    pub fn max_bytes(mut self, val: i32) -> Self {
+        self.max_bytes = val;
+        self
+    }
+
+    /// Maximum data size returned for a single partition
+    pub fn partition_max_bytes(mut self, val: i32) -> Self {
+        self.partition_max_bytes = val;
+        self
+    }
+
+    /// Whether messages of open and aborted transactions are returned (`ReadUncommitted`)
+    pub fn isolation_level(mut self, val: IsolationLevel) -> Self {
+        self.isolation_level = val;
+        self
+    }
+
+    /// Rack consumer runs in, sent with fetch requests. Partition leaders may point consumer to
+    /// a replica in the same rack to fetch from (KIP-392).
+    pub fn client_rack(mut self, val: String) -> Self {
+        self.client_rack = Some(val);
+        self
+    }
+
+    /// Bytes fetched ahead for a single partition. Partition is not fetched while its data
+    /// waiting to be consumed exceeds the limit, so one slow partition does not stall others.
+    /// Limit can be exceeded by a single fetch (`partition_max_bytes`).
+    pub fn prefetch_partition_bytes(mut self, val: usize) -> Self {
+        self.prefetch_partition_bytes = val;
+        self
+    }
+
+    /// Bytes fetched ahead for all partitions. Fetching stops while data waiting to be consumed
+    /// exceeds the limit. Limit can be exceeded by a single fetch (`max_bytes` per broker).
+    pub fn prefetch_total_bytes(mut self, val: usize) -> Self {
+        self.prefetch_total_bytes = val;
+        self
+    }
+
+    /// Position partition is moved to when its fetch offset is out of range (e.g. committed
+    /// messages were already removed by retention)
+    pub fn offset_reset(mut self, val: OffsetReset) -> Self {
+        self.offset_reset = val;
+        self
+    }
+
+    pub fn build(self) -> Result<FetchConfig, Error> {
+        if self.min_bytes < 0 {
+            return Err(Error::InvalidConfig(
+                "min_bytes",
+                "cannot be negative".into(),
+            ));
+        }
+        if self.max_bytes <= 0 {
+            return Err(Error::InvalidConfig("max_bytes", "must be positive".into()));
+        }
+        if self.partition_max_bytes <= 0 {
+            return Err(Error::InvalidConfig(
+                "partition_max_bytes",
+                "must be positive".into(),
+            ));
+        }
+        if self.min_bytes > self.max_bytes {
+            return Err(Error::InvalidConfig(
+                "min_bytes",
+                "cannot exceed max_bytes".into(),
+            ));
+        }
+        if self.prefetch_partition_bytes == 0 {
+            return Err(Error::InvalidConfig(
+                "prefetch_partition_bytes",
+                "must be positive".into(),
+            ));
+        }
+        if self.prefetch_partition_bytes > self.prefetch_total_bytes {
+            return Err(Error::InvalidConfig(
+                "prefetch_partition_bytes",
+                "cannot exceed prefetch_total_bytes".into(),
+            ));
+        }
+
+        Ok(FetchConfig {
+            max_wait_ms: millis("max_wait", self.max_wait)?,
+            min_bytes: self.min_bytes,
+            max_bytes: self.max_bytes,
+            partition_max_bytes: self.partition_max_bytes,
+            isolation_level: self.isolation_level,
+            client_rack: self.client_rack,
+            prefetch_partition_bytes: self.prefetch_partition_bytes,
+            prefetch_total_bytes: self.prefetch_total_bytes,
+            offset_reset: self.offset_reset,
+        })
+    }
+}
+
+fn millis(name: &'static str, val: Duration) -> Result<i32, Error> {
+    i32::try_from(val.as_millis()).map_err(|_| Error::InvalidConfig(name, "too long".into()))
+}
+
+fn positive_millis(name: &'static str, val: Duration) -> Result<i32, Error> {
+    match millis(name, val)? {
+        0 => Err(Error::InvalidConfig(name, "must be positive".into())),
+        ms => Ok(ms),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn consumer_config_requires_topics_and_group() {
+        let result = ConsumerConfig::builder().group_id("group".into()).build();
+        assert!(matches!(result, Err(Error::IncompleteConfig("topics"))));
+
+        let result = ConsumerConfig::builder().topics(vec!["t1".into()]).build();
+        assert!(matches!(result, Err(Error::IncompleteConfig("group_id"))));
+    }
+
+    #[test]
+    fn topic_pattern_matches_whole_name() {
+        let config = ConsumerConfig::builder()
+            .topic_pattern(Regex::new(r"cdc\.db\..*|audit").unwrap())
+            .group_id("group".into())
+            .build()
+            .unwrap();
+        let pattern = config.topic_pattern.unwrap();
+
+        assert!(pattern.is_match("cdc.db.users"));
+        assert!(pattern.is_match("audit"));
+        assert!(!pattern.is_match("old.cdc.db.users"));
+        assert!(!pattern.is_match("audit.log"));
+
+        let result = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .topic_pattern(Regex::new("t.*").unwrap())
+            .group_id("group".into())
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("topic_pattern", _))
+        ));
+    }
+
+    #[test]
+    fn consumer_config_rejects_invalid_timeouts() {
+        let result = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .group_id("group".into())
+            .session_timeout(Duration::from_secs(0))
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("session_timeout", _))
+        ));
+
+        let result = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .group_id("group".into())
+            .rebalance_timeout(Duration::from_secs(u64::MAX))
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("rebalance_timeout", _))
+        ));
+    }
+
+    #[test]
+    fn heartbeat_interval_must_be_lower_than_session_timeout() {
+        let result = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .group_id("group".into())
+            .session_timeout(Duration::from_secs(10))
+            .heartbeat_interval(Duration::from_secs(10))
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("heartbeat_interval", _))
+        ));
+    }
+
+    #[test]
+    fn start_position_timestamp_cannot_be_negative() {
+        let result = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .group_id("group".into())
+            .start_position(StartPosition::Timestamp(-1))
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("start_position", _))
+        ));
+    }
+
+    #[test]
+    fn fetch_config_build() {
+        let config = FetchConfig::builder()
+            .max_wait(Duration::from_millis(10))
+            .min_bytes(1)
+            .max_bytes(1024)
+            .partition_max_bytes(512)
+            .isolation_level(IsolationLevel::ReadUncommitted)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.max_wait_ms, 10);
+        assert_eq!(config.max_bytes, 1024);
+        assert_eq!(config.partition_max_bytes, 512);
+        assert_eq!(config.isolation_level, IsolationLevel::ReadUncommitted);
+    }
+
+    #[test]
+    fn fetch_config_validates_limits() {
+        let result = FetchConfig::builder()
+            .min_bytes(2048)
+            .max_bytes(1024)
+            .build();
+        assert!(matches!(result, Err(Error::InvalidConfig("min_bytes", _))));
+
+        let result = FetchConfig::builder().partition_max_bytes(0).build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("partition_max_bytes", _))
+        ));
+
+        let result = FetchConfig::builder()
+            .prefetch_partition_bytes(2048)
+            .prefetch_total_bytes(1024)
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidConfig("prefetch_partition_bytes", _))
+        ));
+    }
+}
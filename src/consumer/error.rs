@@ -0,0 +1,131 @@
+use crate::{Error as RsKafkaError, KafkaPartition};
+use rskafka_proto::ErrorCode;
+use thiserror::Error;
+
+/// Failure of a single assigned partition. Consumer keeps fetching other partitions while the
+/// failed one is recovered (or stays stopped if error is fatal).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("fetching {partition} failed: {error_code}")]
+pub struct PartitionError {
+    pub partition: KafkaPartition,
+    pub error_code: ErrorCode,
+}
+
+impl PartitionError {
+    pub fn new(partition: KafkaPartition, error_code: ErrorCode) -> Self {
+        PartitionError {
+            partition,
+            error_code,
+        }
+    }
+
+    pub fn kind(&self) -> PartitionErrorKind {
+        PartitionErrorKind::from_code(self.error_code)
+    }
+}
+
+/// How consumer recovers from partition error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionErrorKind {
+    /// Partition leader moved or is unknown (also reported when leader is unreachable). Leader is
+    /// looked up in cluster metadata before partition is fetched again.
+    LeaderChanged,
+    /// Fetch position is outside of partition log, e.g. messages were removed by retention.
+    /// Position is reset according to `FetchConfig::offset_reset`.
+    OffsetOutOfRange,
+    /// Broker could not serve partition temporarily, fetch is retried after a while
+    Retriable,
+    /// Partition can't be fetched (e.g. consumer is not authorized to read the topic). Error is
+    /// reported in consumer's assignment stream and partition stays stopped until it is seeked
+    /// or reassigned.
+    Fatal,
+}
+
+impl PartitionErrorKind {
+    pub fn from_code(error_code: ErrorCode) -> Self {
+        match error_code {
+            ErrorCode::NotLeaderForPartition
+            | ErrorCode::LeaderNotAvailable
+            | ErrorCode::FencedLeaderEpoch
+            | ErrorCode::UnknownLeaderEpoch
+            | ErrorCode::UnknownTopicOrPartition
+            | ErrorCode::NetworkException => PartitionErrorKind::LeaderChanged,
+            ErrorCode::OffsetOutOfRange => PartitionErrorKind::OffsetOutOfRange,
+            ErrorCode::ReplicaNotAvailable
+            | ErrorCode::KafkaStorageError
+            | ErrorCode::CorruptMessage
+            | ErrorCode::UnknownServerError => PartitionErrorKind::Retriable,
+            _ => PartitionErrorKind::Fatal,
+        }
+    }
+
+    pub fn is_retriable(&self) -> bool {
+        !matches!(self, PartitionErrorKind::Fatal)
+    }
+}
+
+/// Error code of partitions fetched by a request which failed as a whole, so they are recovered
+/// like partitions failed with per-partition error. `None` if the error is not caused by broker
+/// or its response (e.g. fetch version is not supported), consumer can't continue then.
+pub(super) fn request_error_code(error: &RsKafkaError) -> Option<ErrorCode> {
+    match error {
+        // Broker is unreachable or no longer part of the cluster, partitions are fetched from
+        // new leaders
+        RsKafkaError::Io(_) | RsKafkaError::ClusterError(_) => Some(ErrorCode::NetworkException),
+        // Fetch is retried after a while
+        RsKafkaError::ParseError(_) | RsKafkaError::ProtocolError(_) => {
+            Some(ErrorCode::CorruptMessage)
+        }
+        RsKafkaError::ErrorResponse(error_code, _) => Some(*error_code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::ApiKey;
+
+    #[test]
+    fn error_codes_are_classified() {
+        let kind = PartitionErrorKind::from_code;
+        assert_eq!(
+            kind(ErrorCode::NotLeaderForPartition),
+            PartitionErrorKind::LeaderChanged
+        );
+        assert_eq!(
+            kind(ErrorCode::UnknownTopicOrPartition),
+            PartitionErrorKind::LeaderChanged
+        );
+        assert_eq!(
+            kind(ErrorCode::OffsetOutOfRange),
+            PartitionErrorKind::OffsetOutOfRange
+        );
+        assert_eq!(
+            kind(ErrorCode::KafkaStorageError),
+            PartitionErrorKind::Retriable
+        );
+        assert_eq!(
+            kind(ErrorCode::TopicAuthorizationFailed),
+            PartitionErrorKind::Fatal
+        );
+        assert!(!kind(ErrorCode::UnsupportedCompressionType).is_retriable());
+    }
+
+    #[test]
+    fn request_errors_are_classified() {
+        let unreachable =
+            RsKafkaError::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        let kind = |e| request_error_code(&e).map(PartitionErrorKind::from_code);
+        assert_eq!(kind(unreachable), Some(PartitionErrorKind::LeaderChanged));
+        assert_eq!(
+            kind(RsKafkaError::ProtocolError("unexpected response".into())),
+            Some(PartitionErrorKind::Retriable)
+        );
+        assert_eq!(
+            kind(RsKafkaError::from(ErrorCode::ClusterAuthorizationFailed)),
+            Some(PartitionErrorKind::Fatal)
+        );
+        assert_eq!(kind(RsKafkaError::ApiNotSupported(ApiKey::Fetch, 11)), None);
+    }
+}
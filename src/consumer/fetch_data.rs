@@ -1,17 +1,27 @@
 use crate::{batch::KafkaBatch, KafkaMessage};
+use log::warn;
 use rskafka_proto::{
-    apis::fetch::{FetchResponsePartition, FetchResponseTopic, FetchResponseV4},
-    Record, RecordBatch,
+    apis::fetch::{AbortedTransaction, FetchResponsePartition, FetchResponseTopic, IsolationLevel},
+    RecordBatch,
 };
 use rskafka_wire_format::WireFormatBorrowParse;
-use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 
 #[derive(Debug)]
 pub struct FetchResponse {
     pub topics: Vec<FetchResponseTopic>,
+    /// Isolation level data was fetched with, decides which batches are visible
+    pub isolation_level: IsolationLevel,
 }
 
 impl FetchResponse {
+    pub fn new(isolation_level: IsolationLevel) -> Self {
+        FetchResponse {
+            topics: Vec::new(),
+            isolation_level,
+        }
+    }
+
     pub fn partitions<'a>(
         &'a self,
     ) -> impl Iterator<Item = (&'a str, &'a FetchResponsePartition)> + 'a {
@@ -21,32 +31,341 @@ impl FetchResponse {
     }
 }
 
-impl From<FetchResponseV4> for FetchResponse {
-    fn from(v: FetchResponseV4) -> Self {
-        FetchResponse { topics: v.topics }
-    }
-}
-
 impl FetchResponse {
     pub fn batches<'a>(&'a self) -> impl Iterator<Item = KafkaBatch<'a>> + 'a {
-        self.topics.iter().flat_map(|t| {
-            t.partitions.iter().map(move |p| {
-                let batch = RecordBatch::over_wire_bytes(&p.record_set).unwrap();
-                KafkaBatch::new(batch, t.name.clone(), p.index)
+        let isolation_level = self.isolation_level;
+        self.topics.iter().flat_map(move |t| {
+            t.partitions.iter().flat_map(move |p| {
+                visible_batches(p, isolation_level)
+                    .into_iter()
+                    .map(move |batch| KafkaBatch::new(batch, t.name.clone(), p.index))
             })
         })
     }
 
+    /// Offset of the last complete batch fetched for each partition
+    pub fn last_offsets<'a>(&'a self) -> impl Iterator<Item = (&'a str, i32, i64)> + 'a {
+        self.partitions().filter_map(|(topic, p)| {
+            next_offset(&p.record_set).map(|next| (topic, p.index, next - 1))
+        })
+    }
+
+    /// Merges responses of requests sent to different brokers
+    pub fn extend<I: IntoIterator<Item = FetchResponseTopic>>(&mut self, topics: I) {
+        self.topics.extend(topics)
+    }
+
     pub fn into_messages_owned(self) -> impl Iterator<Item = KafkaMessage<'static>> {
-        self.topics.into_iter().flat_map(|t| {
+        let isolation_level = self.isolation_level;
+        self.topics.into_iter().flat_map(move |t| {
             let topic = t.name;
             t.partitions
                 .into_iter()
                 .filter(|p| !p.record_set.is_empty())
                 .flat_map(move |p| {
-                    let batch = RecordBatch::over_wire_bytes(&p.record_set).unwrap();
-                    KafkaBatch::new(batch, topic.clone(), p.index).into_messages_owned()
+                    let topic = topic.clone();
+                    visible_batches(&p, isolation_level)
+                        .into_iter()
+                        .flat_map(|batch| {
+                            KafkaBatch::new(batch, topic.clone(), p.index).into_messages_owned()
+                        })
+                        .collect::<Vec<_>>()
                 })
         })
     }
 }
+
+/// Length of batch header fields preceding `batch_length` (inclusive)
+const BATCH_LENGTH_END: usize = 12;
+/// Offset of `last_offset_delta` field in batch
+const LAST_OFFSET_DELTA_START: usize = 23;
+/// Offset of `records_count` field in batch
+const RECORDS_COUNT_START: usize = 57;
+
+/// Raw complete record batches contained in fetched record set.
+///
+/// Broker may return a partial batch at the end of record set when partition byte limit is
+/// reached. Such batch is skipped and will be fetched again.
+fn raw_batches(record_set: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut input = record_set;
+    std::iter::from_fn(move || {
+        if input.len() < BATCH_LENGTH_END {
+            return None;
+        }
+        let batch_length = i32::from_be_bytes(read_array(&input[8..BATCH_LENGTH_END]));
+        let total_length = BATCH_LENGTH_END + batch_length.max(0) as usize;
+        if input.len() < total_length || total_length <= LAST_OFFSET_DELTA_START {
+            return None;
+        }
+        let (batch, rest) = input.split_at(total_length);
+        input = rest;
+        Some(batch)
+    })
+}
+
+fn read_array(bytes: &[u8]) -> [u8; 4] {
+    let mut array = [0; 4];
+    array.copy_from_slice(&bytes[..4]);
+    array
+}
+
+/// Complete record batches contained in fetched record set
+pub fn record_batches(record_set: &[u8]) -> impl Iterator<Item = RecordBatch<'_>> {
+    raw_batches(record_set).filter_map(|bytes| match RecordBatch::over_wire_bytes(bytes) {
+        Ok(batch) => Some(batch),
+        Err(e) => {
+            warn!("Skipping unparsable record batch: {}", e);
+            None
+        }
+    })
+}
+
+/// Batches of partition data delivered to consumer. Control batches are always dropped, in
+/// `ReadCommitted` mode records of aborted transactions are dropped as well.
+fn visible_batches(
+    p: &FetchResponsePartition,
+    isolation_level: IsolationLevel,
+) -> Vec<RecordBatch<'_>> {
+    match isolation_level {
+        IsolationLevel::ReadUncommitted => record_batches(&p.record_set)
+            .filter(|b| !b.is_control())
+            .collect(),
+        IsolationLevel::ReadCommitted => {
+            let mut filter = AbortedTransactionFilter::new(&p.aborted_transactions);
+            record_batches(&p.record_set)
+                .filter(|b| p.last_stable_offset < 0 || b.base_offset < p.last_stable_offset)
+                .filter(|b| filter.is_visible(b))
+                .collect()
+        }
+    }
+}
+
+/// Drops batches of aborted transactions. Batches must be checked in offset order.
+struct AbortedTransactionFilter {
+    /// Aborted transactions not reached yet, keyed by first offset
+    pending: BTreeMap<i64, Vec<i64>>,
+    /// Producers which transaction was aborted and its abort marker not reached yet
+    aborted_producers: HashSet<i64>,
+}
+
+impl AbortedTransactionFilter {
+    fn new(aborted_transactions: &[AbortedTransaction]) -> Self {
+        let mut pending: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+        for t in aborted_transactions {
+            pending
+                .entry(t.first_offset)
+                .or_default()
+                .push(t.producer_id);
+        }
+
+        AbortedTransactionFilter {
+            pending,
+            aborted_producers: HashSet::new(),
+        }
+    }
+
+    fn is_visible(&mut self, batch: &RecordBatch) -> bool {
+        if batch.producer_id >= 0 {
+            // Transactions started before end of this batch
+            let started: Vec<i64> = self
+                .pending
+                .range(..=batch.last_offset())
+                .map(|(first_offset, _)| *first_offset)
+                .collect();
+            for first_offset in started {
+                let producers = self.pending.remove(&first_offset).unwrap_or_default();
+                self.aborted_producers.extend(producers);
+            }
+
+            if is_abort_marker(batch) {
+                self.aborted_producers.remove(&batch.producer_id);
+            } else if batch.is_transactional()
+                && self.aborted_producers.contains(&batch.producer_id)
+            {
+                return false;
+            }
+        }
+
+        !batch.is_control()
+    }
+}
+
+/// Control record type of transaction abort marker
+const ABORT_MARKER_TYPE: i16 = 0;
+
+/// Control batch ending aborted transaction. Control record key holds version and record type.
+fn is_abort_marker(batch: &RecordBatch) -> bool {
+    if !batch.is_control() {
+        return false;
+    }
+    match batch.records.first().and_then(|r| r.key.as_ref()) {
+        Some(key) if key.len() >= 4 => i16::from_be_bytes([key[2], key[3]]) == ABORT_MARKER_TYPE,
+        _ => false,
+    }
+}
+
+/// Offset following the last complete batch in record set
+pub fn next_offset(record_set: &[u8]) -> Option<i64> {
+    raw_batches(record_set).last().map(|batch| {
+        let mut base_offset = [0; 8];
+        base_offset.copy_from_slice(&batch[..8]);
+        let last_offset_delta = i32::from_be_bytes(read_array(&batch[LAST_OFFSET_DELTA_START..]));
+        i64::from_be_bytes(base_offset) + last_offset_delta as i64 + 1
+    })
+}
+
+/// Number of records in complete batches of record set, including control records
+pub fn record_count(record_set: &[u8]) -> usize {
+    raw_batches(record_set)
+        .filter(|batch| batch.len() >= RECORDS_COUNT_START + 4)
+        .map(|batch| i32::from_be_bytes(read_array(&batch[RECORDS_COUNT_START..])).max(0) as usize)
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::record_batch_bytes;
+    use rskafka_proto::Record;
+    use rskafka_wire_format::VarInt;
+
+    fn batch_bytes() -> Vec<u8> {
+        record_batch_bytes(1)
+    }
+
+    #[test]
+    fn record_set_with_multiple_batches() {
+        let batch = batch_bytes();
+        let mut record_set = batch.clone();
+        record_set.extend_from_slice(&batch);
+
+        assert_eq!(record_batches(&record_set).count(), 2);
+        assert_eq!(next_offset(&record_set), Some(2));
+        assert_eq!(record_count(&record_set), 2);
+    }
+
+    #[test]
+    fn partial_batch_is_skipped() {
+        let batch = batch_bytes();
+        let mut record_set = batch.clone();
+        record_set.extend_from_slice(&batch[..50]);
+
+        assert_eq!(record_batches(&record_set).count(), 1);
+        assert_eq!(next_offset(&record_set), Some(2));
+        assert_eq!(next_offset(&batch[..50]), None);
+    }
+
+    const COMMIT_MARKER_TYPE: i16 = 1;
+
+    fn batch(
+        base_offset: i64,
+        records: i32,
+        producer_id: i64,
+        attributes: i16,
+    ) -> RecordBatch<'static> {
+        RecordBatch {
+            base_offset,
+            batch_length: 0,
+            partition_leader_epoch: 0,
+            magic: 2,
+            crc: 0,
+            attributes,
+            last_offset_delta: records - 1,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id,
+            producer_epoch: 0,
+            base_sequence: 0,
+            records: (0..records)
+                .map(|delta| Record {
+                    length: VarInt(0),
+                    attributes: 0,
+                    timestamp_delta: VarInt(0),
+                    offset_delta: VarInt(delta),
+                    key: None,
+                    value: Some(vec![1].into()),
+                    headers: vec![].into(),
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+
+    fn transactional(base_offset: i64, records: i32, producer_id: i64) -> RecordBatch<'static> {
+        batch(base_offset, records, producer_id, 0x10)
+    }
+
+    fn marker(offset: i64, producer_id: i64, marker_type: i16) -> RecordBatch<'static> {
+        let mut batch = batch(offset, 1, producer_id, 0x30);
+        let mut key = 0i16.to_be_bytes().to_vec();
+        key.extend_from_slice(&marker_type.to_be_bytes());
+        batch.records.to_mut()[0].key = Some(key.into());
+        batch
+    }
+
+    fn visible(batches: &[RecordBatch], aborted_transactions: &[AbortedTransaction]) -> Vec<i64> {
+        let mut filter = AbortedTransactionFilter::new(aborted_transactions);
+        batches
+            .iter()
+            .filter(|b| filter.is_visible(b))
+            .map(|b| b.base_offset)
+            .collect()
+    }
+
+    #[test]
+    fn aborted_transaction_records_are_dropped() {
+        let batches = vec![
+            transactional(0, 2, 1),
+            transactional(2, 1, 2),
+            marker(3, 1, ABORT_MARKER_TYPE),
+            marker(4, 2, COMMIT_MARKER_TYPE),
+            batch(5, 1, -1, 0),
+        ];
+        let aborted = vec![AbortedTransaction {
+            producer_id: 1,
+            first_offset: 0,
+        }];
+
+        assert_eq!(visible(&batches, &aborted), vec![2, 5]);
+    }
+
+    #[test]
+    fn producer_is_visible_after_abort_marker() {
+        let batches = vec![
+            transactional(0, 1, 1),
+            marker(1, 1, ABORT_MARKER_TYPE),
+            transactional(2, 1, 1),
+            marker(3, 1, COMMIT_MARKER_TYPE),
+        ];
+        let aborted = vec![AbortedTransaction {
+            producer_id: 1,
+            first_offset: 0,
+        }];
+
+        assert_eq!(visible(&batches, &aborted), vec![2]);
+    }
+
+    #[test]
+    fn transaction_aborted_later_in_record_set() {
+        // Transaction of producer 1 starting at offset 3 is aborted, earlier one was committed
+        let batches = vec![
+            transactional(0, 2, 1),
+            marker(2, 1, COMMIT_MARKER_TYPE),
+            transactional(3, 1, 1),
+            marker(4, 1, ABORT_MARKER_TYPE),
+        ];
+        let aborted = vec![AbortedTransaction {
+            producer_id: 1,
+            first_offset: 3,
+        }];
+
+        assert_eq!(visible(&batches, &aborted), vec![0]);
+    }
+
+    #[test]
+    fn control_batches_are_dropped() {
+        let batches = vec![batch(0, 1, -1, 0), marker(1, 1, COMMIT_MARKER_TYPE)];
+
+        assert_eq!(visible(&batches, &[]), vec![0]);
+    }
+}
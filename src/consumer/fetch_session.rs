@@ -0,0 +1,316 @@
+use crate::Error;
+use log::{debug, warn};
+use rskafka_proto::{
+    apis::fetch::{
+        FetchRequestV11, FetchResponseV11, ForgottenTopic, PartitionFetchV11, TopicFetchV11,
+        INITIAL_SESSION_EPOCH, INVALID_SESSION_ID,
+    },
+    BrokerId, ErrorCode,
+};
+use std::collections::{BTreeMap, HashMap};
+
+type PartitionKey = (String, i32);
+
+/// Incremental fetch sessions (KIP-227), one per broker.
+///
+/// Turns full fetch requests into incremental ones which carry only partitions whose fetch state
+/// changed since the previous request. Sessions that broker no longer recognizes are dropped
+/// and recreated with the next full request.
+#[derive(Debug, Default)]
+pub struct FetchSessions {
+    sessions: HashMap<BrokerId, FetchSession>,
+}
+
+impl FetchSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adjusts request listing all fetched partitions to the session held with broker
+    pub fn prepare(
+        &mut self,
+        broker: BrokerId,
+        request: FetchRequestV11<'static>,
+    ) -> FetchRequestV11<'static> {
+        self.sessions.entry(broker).or_default().prepare(request)
+    }
+
+    /// Updates session with broker response. Session errors reset the session, other top level
+    /// errors are returned.
+    pub fn update(&mut self, broker: BrokerId, response: &FetchResponseV11) -> Result<(), Error> {
+        self.sessions
+            .entry(broker)
+            .or_default()
+            .update(broker, response)
+    }
+
+    /// Drops session so next request to broker is a full one
+    pub fn reset(&mut self, broker: BrokerId) {
+        self.sessions.remove(&broker);
+    }
+}
+
+#[derive(Debug)]
+struct FetchSession {
+    id: i32,
+    epoch: i32,
+    /// Fetch state of partitions as known by broker
+    partitions: HashMap<PartitionKey, PartitionFetchV11>,
+    /// State sent in request awaiting response
+    pending: Option<HashMap<PartitionKey, PartitionFetchV11>>,
+}
+
+impl Default for FetchSession {
+    fn default() -> Self {
+        FetchSession {
+            id: INVALID_SESSION_ID,
+            epoch: INITIAL_SESSION_EPOCH,
+            partitions: HashMap::new(),
+            pending: None,
+        }
+    }
+}
+
+impl FetchSession {
+    fn prepare(&mut self, mut request: FetchRequestV11<'static>) -> FetchRequestV11<'static> {
+        let requested: HashMap<PartitionKey, PartitionFetchV11> = request
+            .topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions
+                    .iter()
+                    .map(move |p| ((t.name.to_string(), p.index), p.clone()))
+            })
+            .collect();
+
+        if self.id == INVALID_SESSION_ID {
+            // Full request, asks broker to create new session
+            request.session_id = INVALID_SESSION_ID;
+            request.session_epoch = INITIAL_SESSION_EPOCH;
+            request.forgotten_topics = Vec::new();
+        } else {
+            let mut changed: BTreeMap<&str, Vec<PartitionFetchV11>> = BTreeMap::new();
+            for (key, p) in requested.iter() {
+                if self.partitions.get(key) != Some(p) {
+                    changed.entry(&key.0).or_default().push(p.clone());
+                }
+            }
+            let mut forgotten: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+            for key in self.partitions.keys() {
+                if !requested.contains_key(key) {
+                    forgotten.entry(&key.0).or_default().push(key.1);
+                }
+            }
+
+            request.session_id = self.id;
+            request.session_epoch = self.epoch;
+            request.topics = changed
+                .into_iter()
+                .map(|(name, mut partitions)| {
+                    partitions.sort_by_key(|p| p.index);
+                    TopicFetchV11 {
+                        name: name.to_owned().into(),
+                        partitions,
+                    }
+                })
+                .collect();
+            request.forgotten_topics = forgotten
+                .into_iter()
+                .map(|(name, mut partitions)| {
+                    partitions.sort();
+                    ForgottenTopic {
+                        name: name.to_owned().into(),
+                        partitions,
+                    }
+                })
+                .collect();
+        }
+
+        self.pending = Some(requested);
+        request
+    }
+
+    fn update(&mut self, broker: BrokerId, response: &FetchResponseV11) -> Result<(), Error> {
+        match response.error_code {
+            ErrorCode::None => {
+                if self.id == INVALID_SESSION_ID {
+                    self.id = response.session_id;
+                    self.epoch = INITIAL_SESSION_EPOCH;
+                    if self.id != INVALID_SESSION_ID {
+                        debug!("Created fetch session {} with broker {}", self.id, broker);
+                    }
+                }
+                self.epoch = next_epoch(self.epoch);
+                self.partitions = self.pending.take().unwrap_or_default();
+                Ok(())
+            }
+            error @ ErrorCode::FetchSessionIdNotFound
+            | error @ ErrorCode::InvalidFetchSessionEpoch => {
+                warn!(
+                    "Fetch session {} with broker {} reset: {}",
+                    self.id, broker, error
+                );
+                *self = FetchSession::default();
+                Ok(())
+            }
+            error => {
+                *self = FetchSession::default();
+                Err(error.into())
+            }
+        }
+    }
+}
+
+fn next_epoch(epoch: i32) -> i32 {
+    if epoch == i32::MAX {
+        1
+    } else {
+        epoch + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::apis::fetch::{IsolationLevel, FINAL_SESSION_EPOCH};
+
+    fn full_request(partitions: &[(&str, i32, i64)]) -> FetchRequestV11<'static> {
+        let mut topics: Vec<TopicFetchV11> = Vec::new();
+        for (topic, index, fetch_offset) in partitions {
+            let partition = PartitionFetchV11 {
+                index: *index,
+                current_leader_epoch: -1,
+                fetch_offset: *fetch_offset,
+                log_start_offset: -1,
+                partition_max_bytes: 1024,
+            };
+            match topics.last_mut() {
+                Some(t) if t.name == *topic => t.partitions.push(partition),
+                _ => topics.push(TopicFetchV11 {
+                    name: topic.to_string().into(),
+                    partitions: vec![partition],
+                }),
+            }
+        }
+
+        FetchRequestV11 {
+            replica_id: -1,
+            max_wait_time: 500,
+            min_bytes: 1,
+            max_bytes: 1024,
+            isolation_level: IsolationLevel::ReadCommitted,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
+            topics,
+            forgotten_topics: Vec::new(),
+            rack_id: "".into(),
+        }
+    }
+
+    fn response(error_code: ErrorCode, session_id: i32) -> FetchResponseV11 {
+        FetchResponseV11 {
+            throttle_time_ms: 0,
+            error_code,
+            session_id,
+            topics: Vec::new(),
+        }
+    }
+
+    fn fetched(request: &FetchRequestV11) -> Vec<(String, i32, i64)> {
+        request
+            .topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions
+                    .iter()
+                    .map(move |p| (t.name.to_string(), p.index, p.fetch_offset))
+            })
+            .collect()
+    }
+
+    fn broker() -> BrokerId {
+        BrokerId::from(1)
+    }
+
+    #[test]
+    fn first_request_creates_session() {
+        let mut sessions = FetchSessions::new();
+        let request = sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+
+        assert_eq!(request.session_id, INVALID_SESSION_ID);
+        assert_eq!(request.session_epoch, INITIAL_SESSION_EPOCH);
+        assert_eq!(fetched(&request), vec![("t1".to_string(), 0, 5)]);
+    }
+
+    #[test]
+    fn incremental_request_contains_only_changes() {
+        let mut sessions = FetchSessions::new();
+        sessions.prepare(
+            broker(),
+            full_request(&[("t1", 0, 5), ("t1", 1, 7), ("t2", 0, 1)]),
+        );
+        sessions
+            .update(broker(), &response(ErrorCode::None, 42))
+            .unwrap();
+
+        let request = sessions.prepare(broker(), full_request(&[("t1", 0, 6), ("t1", 1, 7)]));
+        assert_eq!(request.session_id, 42);
+        assert_eq!(request.session_epoch, 1);
+        assert_eq!(fetched(&request), vec![("t1".to_string(), 0, 6)]);
+        assert_eq!(
+            request.forgotten_topics,
+            vec![ForgottenTopic {
+                name: "t2".into(),
+                partitions: vec![0],
+            }]
+        );
+
+        sessions
+            .update(broker(), &response(ErrorCode::None, 42))
+            .unwrap();
+        let request = sessions.prepare(broker(), full_request(&[("t1", 0, 6), ("t1", 1, 7)]));
+        assert_eq!(request.session_epoch, 2);
+        assert!(request.topics.is_empty());
+        assert!(request.forgotten_topics.is_empty());
+    }
+
+    #[test]
+    fn session_errors_reset_session() {
+        for error in &[
+            ErrorCode::FetchSessionIdNotFound,
+            ErrorCode::InvalidFetchSessionEpoch,
+        ] {
+            let mut sessions = FetchSessions::new();
+            sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+            sessions
+                .update(broker(), &response(ErrorCode::None, 42))
+                .unwrap();
+            sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+            sessions.update(broker(), &response(*error, 0)).unwrap();
+
+            let request = sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+            assert_eq!(request.session_id, INVALID_SESSION_ID);
+            assert_eq!(request.session_epoch, INITIAL_SESSION_EPOCH);
+            assert_eq!(fetched(&request), vec![("t1".to_string(), 0, 5)]);
+        }
+    }
+
+    #[test]
+    fn sessionless_broker_gets_full_requests() {
+        let mut sessions = FetchSessions::new();
+        sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+        sessions
+            .update(broker(), &response(ErrorCode::None, INVALID_SESSION_ID))
+            .unwrap();
+
+        let request = sessions.prepare(broker(), full_request(&[("t1", 0, 5)]));
+        assert_eq!(request.session_epoch, INITIAL_SESSION_EPOCH);
+        assert_eq!(fetched(&request), vec![("t1".to_string(), 0, 5)]);
+    }
+
+    #[test]
+    fn epoch_wraps_around() {
+        assert_eq!(next_epoch(1), 2);
+        assert_eq!(next_epoch(i32::MAX), 1);
+    }
+}
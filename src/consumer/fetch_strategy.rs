@@ -1,35 +1,87 @@
-use super::fetch_data::FetchResponse;
-use crate::Error;
+use super::{
+    error::{PartitionError, PartitionErrorKind},
+    fetch_data::next_offset,
+    FetchConfig,
+};
+use crate::KafkaPartition;
+use log::{debug, warn};
 use rskafka_proto::{
     apis::{
-        fetch::{FetchRequestV4, IsolationLevel, PartitionFetch, TopicFetch},
+        fetch::{
+            FetchRequestV11, FetchResponseV11, PartitionFetchV11, TopicFetchV11,
+            FINAL_SESSION_EPOCH, INVALID_SESSION_ID,
+        },
         metadata::TopicMetadata,
-        offset_fetch::TopicOffsets,
     },
-    BrokerId, ErrorCode, RecordBatch,
+    BrokerId, ErrorCode,
 };
-use rskafka_wire_format::WireFormatBorrowParse;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub trait FetchStrategy {
-    fn next_fetch(&mut self) -> (BrokerId, FetchRequestV4);
-    fn update_fetched(&mut self, r: &FetchResponse);
+    /// Requests to perform concurrently, at most one per broker. Empty if there is nothing to
+    /// fetch (e.g. all partitions are paused).
+    ///
+    /// Returned requests list all fetched partitions and don't use fetch sessions.
+    fn next_fetches(&mut self) -> Vec<(BrokerId, FetchRequestV11<'static>)>;
+    /// Updates fetch state with response of given broker. Returns errors of partitions which
+    /// are excluded from fetching until recovered (see `fail`).
+    fn update_fetched(&mut self, broker: BrokerId, r: &FetchResponseV11) -> Vec<PartitionError>;
+    /// Excludes partition from fetching until it is recovered - by `set_leader` if leader
+    /// changed, `seek` if offset is out of range and `retry` otherwise. Returns false if
+    /// partition is not assigned.
+    fn fail(&mut self, error: &PartitionError) -> bool;
+    /// Fetches partition from new leader. Returns false if partition is not assigned.
+    fn set_leader(&mut self, topic: &str, partition: i32, leader: BrokerId) -> bool;
+    /// Fetches partition excluded by retriable error again. Returns false if partition is not
+    /// assigned.
+    fn retry(&mut self, topic: &str, partition: i32) -> bool;
+
+    /// Moves fetch position of partition, resuming partition stopped by error. Returns false if
+    /// partition is not assigned.
+    fn seek(&mut self, topic: &str, partition: i32, offset: i64) -> bool;
+    /// Excludes partition from fetching. Returns false if partition is not assigned.
+    fn pause(&mut self, topic: &str, partition: i32) -> bool;
+    /// Returns false if partition is not assigned.
+    fn resume(&mut self, topic: &str, partition: i32) -> bool;
+    /// Excludes partition from fetching while its prefetched data waits to be consumed,
+    /// independently of `pause`. Returns false if partition is not assigned.
+    fn throttle(&mut self, topic: &str, partition: i32, throttled: bool) -> bool;
+    /// Offset of the next message to fetch from partition
+    fn position(&self, topic: &str, partition: i32) -> Option<i64>;
 }
 
+/// Fetches all assigned partitions with one request per broker.
+///
+/// Partitions are fetched from their leaders until a leader points to a preferred read replica,
+/// chosen by broker's replica selector for consumer's `client_rack`. Any partition error returned
+/// by a non-leader replica makes strategy go back to the leader.
+///
+/// Order of partitions in requests is rotated between fetches so partitions placed first don't
+/// exhaust `max_bytes` budget every time.
+///
+/// Partitions without known leader or fetch offset, and those failed with other errors, are
+/// skipped until recovered. Remaining partitions keep being fetched.
 pub struct SimpleFetchStrategy<'a> {
-    a: &'a AssignmentContext,
     offsets: Offsets,
-    cycle: Box<dyn Iterator<Item = (&'a str, i32)> + Send + 'a>,
+    config: FetchConfig,
+    partitions: Vec<(&'a str, i32)>,
+    first_partition: usize,
+    paused: HashSet<(&'a str, i32)>,
+    throttled: HashSet<(&'a str, i32)>,
+    failed: HashSet<(&'a str, i32)>,
     leaders: HashMap<(&'a str, i32), BrokerId>,
+    preferred_replicas: HashMap<(&'a str, i32), BrokerId>,
+    broker_racks: &'a HashMap<BrokerId, Option<String>>,
 }
 
 impl<'a> SimpleFetchStrategy<'a> {
-    pub fn new(a: &'a AssignmentContext, offsets: Offsets) -> Self {
-        let cycle = a
+    pub fn new(a: &'a AssignmentContext, offsets: Offsets, config: FetchConfig) -> Self {
+        let mut partitions: Vec<(&'a str, i32)> = a
             .assigned_partitions
             .iter()
             .flat_map(|(t, partitions)| partitions.iter().map(move |p| (t.as_str(), *p)))
-            .cycle();
+            .collect();
+        partitions.sort();
 
         let leaders = a
             .topic_metadata
@@ -42,50 +94,243 @@ impl<'a> SimpleFetchStrategy<'a> {
             .collect();
 
         SimpleFetchStrategy {
-            a,
             offsets,
-            cycle: Box::new(cycle),
+            config,
+            partitions,
+            first_partition: 0,
+            paused: HashSet::new(),
+            throttled: HashSet::new(),
+            failed: HashSet::new(),
             leaders,
+            preferred_replicas: HashMap::new(),
+            broker_racks: &a.broker_racks,
+        }
+    }
+
+    /// Broker partition is fetched from, `None` if leader is unknown
+    fn source(&self, partition: &(&'a str, i32)) -> Option<BrokerId> {
+        self.preferred_replicas
+            .get(partition)
+            .or_else(|| self.leaders.get(partition))
+            .copied()
+    }
+
+    fn assigned(&self, topic: &str, partition: i32) -> Option<(&'a str, i32)> {
+        self.partitions
+            .iter()
+            .find(|(t, p)| *t == topic && *p == partition)
+            .copied()
+    }
+
+    fn build_request(&self, partitions: Vec<(&'a str, i32, i64)>) -> FetchRequestV11<'static> {
+        let mut topics: Vec<TopicFetchV11> = Vec::new();
+        for (topic, partition, offset) in partitions {
+            let partition_fetch = PartitionFetchV11 {
+                index: partition,
+                current_leader_epoch: -1,
+                fetch_offset: offset,
+                log_start_offset: -1,
+                partition_max_bytes: self.config.partition_max_bytes,
+            };
+            // Consecutive partitions of the same topic share topic entry
+            match topics.last_mut() {
+                Some(t) if t.name == topic => t.partitions.push(partition_fetch),
+                _ => topics.push(TopicFetchV11 {
+                    name: topic.to_owned().into(),
+                    partitions: vec![partition_fetch],
+                }),
+            }
+        }
+
+        FetchRequestV11 {
+            replica_id: -1,
+            max_wait_time: self.config.max_wait_ms,
+            min_bytes: self.config.min_bytes,
+            max_bytes: self.config.max_bytes,
+            isolation_level: self.config.isolation_level,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
+            topics,
+            forgotten_topics: Vec::new(),
+            rack_id: self.config.client_rack.clone().unwrap_or_default().into(),
         }
     }
 }
 
 impl<'a> FetchStrategy for SimpleFetchStrategy<'a> {
-    fn next_fetch(&mut self) -> (BrokerId, FetchRequestV4) {
-        let (topic, partition) = self.cycle.next().unwrap();
-        let broker = *self.leaders.get(&(topic, partition)).unwrap();
-        let request = FetchRequestV4 {
-            replica_id: -1,
-            max_wait_time: 100,
-            min_bytes: 1024,
-            max_bytes: 1024 * 1024 * 1024,
-            isolation_level: IsolationLevel::ReadCommitted,
-            topics: vec![TopicFetch {
-                name: topic.to_owned().into(),
-                partitions: vec![PartitionFetch {
-                    fetch_offset: self.offsets.get(topic, partition).expect("missing offset"), //TODO: error on missing topic
-                    index: partition,
-                    partition_max_bytes: 1024 * 1024 * 1024,
-                }],
-            }],
+    fn next_fetches(&mut self) -> Vec<(BrokerId, FetchRequestV11<'static>)> {
+        let count = self.partitions.len();
+        let mut per_broker: HashMap<BrokerId, Vec<(&'a str, i32, i64)>> = HashMap::new();
+        for i in 0..count {
+            let partition = self.partitions[(self.first_partition + i) % count];
+            if self.paused.contains(&partition)
+                || self.throttled.contains(&partition)
+                || self.failed.contains(&partition)
+            {
+                continue;
+            }
+            let (topic, index) = partition;
+            match (self.source(&partition), self.offsets.get(topic, index)) {
+                (Some(broker), Some(offset)) => {
+                    per_broker
+                        .entry(broker)
+                        .or_default()
+                        .push((topic, index, offset));
+                }
+                _ => continue,
+            }
+        }
+        if count > 0 {
+            self.first_partition = (self.first_partition + 1) % count;
+        }
+
+        per_broker
+            .into_iter()
+            .map(|(broker, partitions)| (broker, self.build_request(partitions)))
+            .collect()
+    }
+
+    fn update_fetched(&mut self, broker: BrokerId, r: &FetchResponseV11) -> Vec<PartitionError> {
+        let mut errors = Vec::new();
+        for t in r.topics.iter() {
+            for p in t.partitions.iter() {
+                let partition = match self.assigned(&t.name, p.index) {
+                    Some(partition) => partition,
+                    None => continue,
+                };
+
+                if p.error_code != ErrorCode::None && Some(&broker) != self.leaders.get(&partition)
+                {
+                    warn!(
+                        "Fetching {}[{}] from replica {} failed: {}, falling back to leader",
+                        t.name, p.index, broker, p.error_code
+                    );
+                    self.preferred_replicas.remove(&partition);
+                    continue;
+                }
+                if p.error_code != ErrorCode::None {
+                    let partition = KafkaPartition {
+                        topic_name: t.name.to_string(),
+                        partition_index: p.index,
+                    };
+                    let error = PartitionError::new(partition, p.error_code);
+                    self.fail(&error);
+                    errors.push(error);
+                    continue;
+                }
+
+                if p.preferred_read_replica >= 0 {
+                    let replica = BrokerId::from(p.preferred_read_replica);
+                    match self.broker_racks.get(&replica) {
+                        Some(rack) if replica != broker => {
+                            debug!(
+                                "Fetching {}[{}] from preferred replica {} (rack {:?})",
+                                t.name, p.index, replica, rack
+                            );
+                            self.preferred_replicas.insert(partition, replica);
+                        }
+                        Some(_) => (),
+                        None => warn!(
+                            "Ignoring unknown preferred replica {} for {}[{}]",
+                            replica, t.name, p.index
+                        ),
+                    }
+                }
+
+                if let Some(offset) = next_offset(&p.record_set) {
+                    self.offsets.update(&t.name, p.index, offset)
+                }
+            }
+        }
+        errors
+    }
+
+    fn fail(&mut self, error: &PartitionError) -> bool {
+        let p = &error.partition;
+        let partition = match self.assigned(&p.topic_name, p.partition_index) {
+            Some(partition) => partition,
+            None => return false,
         };
+        match error.kind() {
+            PartitionErrorKind::LeaderChanged => {
+                self.leaders.remove(&partition);
+                self.preferred_replicas.remove(&partition);
+            }
+            PartitionErrorKind::OffsetOutOfRange => self.offsets.remove(partition.0, partition.1),
+            PartitionErrorKind::Retriable | PartitionErrorKind::Fatal => {
+                self.failed.insert(partition);
+            }
+        }
+        true
+    }
 
-        (broker, request)
-    }
-
-    fn update_fetched(&mut self, r: &FetchResponse) {
-        r.partitions().for_each(|(t, p)| {
-            // let batch = RecordBatch::over_wire_bytes(&p.record_set).unwrap(); // TODO: change batch parsing so it's not parsed twice
-            // let offset_val = batch.base_offset
-            //     + batch
-            //         .records
-            //         .as_ref()
-            //         .last()
-            //         .map(|r| r.offset_delta.0 as i64)
-            //         .unwrap_or(0);
-            let offset_val = p.high_watermark; //TODO: Hihgly unsure if this is correct value to use as fetched offset
-            self.offsets.update(t, p.index, offset_val)
-        });
+    fn set_leader(&mut self, topic: &str, partition: i32, leader: BrokerId) -> bool {
+        match self.assigned(topic, partition) {
+            Some(p) => {
+                self.leaders.insert(p, leader);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn retry(&mut self, topic: &str, partition: i32) -> bool {
+        match self.assigned(topic, partition) {
+            Some(p) => {
+                self.failed.remove(&p);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn seek(&mut self, topic: &str, partition: i32, offset: i64) -> bool {
+        match self.assigned(topic, partition) {
+            Some((topic, partition)) => {
+                self.offsets.insert(topic.to_owned(), partition, offset);
+                self.failed.remove(&(topic, partition));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pause(&mut self, topic: &str, partition: i32) -> bool {
+        match self.assigned(topic, partition) {
+            Some(p) => {
+                self.paused.insert(p);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn resume(&mut self, topic: &str, partition: i32) -> bool {
+        match self.assigned(topic, partition) {
+            Some(p) => {
+                self.paused.remove(&p);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn throttle(&mut self, topic: &str, partition: i32, throttled: bool) -> bool {
+        match self.assigned(topic, partition) {
+            Some(p) => {
+                if throttled {
+                    self.throttled.insert(p);
+                } else {
+                    self.throttled.remove(&p);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn position(&self, topic: &str, partition: i32) -> Option<i64> {
+        self.offsets.get(topic, partition)
     }
 }
 
@@ -93,27 +338,27 @@ impl<'a> FetchStrategy for SimpleFetchStrategy<'a> {
 pub struct Offsets(HashMap<String, HashMap<i32, i64>>);
 
 impl Offsets {
-    pub fn from_response(r: Vec<TopicOffsets>) -> Result<Self, Error> {
-        let mut topics = HashMap::new();
-        for t in r.into_iter() {
-            let mut partitions = HashMap::new();
-            for p in t.partitions {
-                match p.error_code {
-                    ErrorCode::None => {
-                        partitions.insert(p.index, p.committed_offset + 1);
-                    }
-                    error => return Err(Error::ErrorResponse(error, "offset fetch error".into())),
-                }
-            }
-            topics.insert(t.name, partitions);
-        }
+    pub fn new() -> Self {
+        Offsets(HashMap::new())
+    }
 
-        Ok(Offsets(topics))
+    pub fn insert(&mut self, topic: String, partition: i32, offset: i64) {
+        self.0.entry(topic).or_default().insert(partition, offset);
     }
 
     pub fn update(&mut self, topic: &str, partition: i32, offset: i64) {
-        let partitions = self.0.get_mut(topic).unwrap(); //TODO: error on missing topic
-        *partitions.entry(partition).or_default() = offset;
+        match self.0.get_mut(topic) {
+            Some(partitions) => {
+                partitions.insert(partition, offset);
+            }
+            None => self.insert(topic.to_owned(), partition, offset),
+        }
+    }
+
+    pub fn remove(&mut self, topic: &str, partition: i32) {
+        if let Some(partitions) = self.0.get_mut(topic) {
+            partitions.remove(&partition);
+        }
     }
 
     pub fn get(&self, topic: &str, partition: i32) -> Option<i64> {
@@ -123,9 +368,359 @@ impl Offsets {
 
 #[derive(Debug)]
 pub struct AssignmentContext {
-    pub generation_id: i32,
-    pub member_id: String,
     pub assigned_partitions: HashMap<String, Vec<i32>>,
     pub topic_metadata: HashMap<String, TopicMetadata>,
+    /// Rack of every broker in cluster
+    pub broker_racks: HashMap<BrokerId, Option<String>>,
+    /// Group used for committing offsets, `None` if commits are disabled
+    pub group: Option<GroupMembership>,
+}
+
+impl AssignmentContext {
+    /// Sorted list of assigned partitions
+    pub fn partitions(&self) -> Vec<KafkaPartition> {
+        let mut partitions: Vec<KafkaPartition> = self
+            .assigned_partitions
+            .iter()
+            .flat_map(|(topic, partitions)| {
+                partitions.iter().map(move |p| KafkaPartition {
+                    topic_name: topic.clone(),
+                    partition_index: *p,
+                })
+            })
+            .collect();
+        partitions.sort_by(|a, b| {
+            (&a.topic_name, a.partition_index).cmp(&(&b.topic_name, b.partition_index))
+        });
+        partitions
+    }
+
+    /// Points group requests to coordinator found after failover
+    pub fn set_coordinator(&mut self, coordinator: BrokerId) {
+        if let Some(group) = self.group.as_mut() {
+            group.coordinator = coordinator;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupMembership {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
     pub coordinator: BrokerId,
 }
+
+impl GroupMembership {
+    /// Membership used to commit offsets outside of group management
+    pub fn standalone(group_id: String, coordinator: BrokerId) -> Self {
+        GroupMembership {
+            group_id,
+            generation_id: -1,
+            member_id: String::new(),
+            coordinator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::apis::{
+        fetch::{FetchResponsePartitionV11, FetchResponseTopicV11},
+        metadata::PartitionMetadata,
+    };
+
+    /// Every partition is replicated to brokers 1-3, each broker in its own rack ("az-<id>")
+    fn context(partitions: &[(&str, i32, i32)]) -> AssignmentContext {
+        let mut assigned_partitions: HashMap<String, Vec<i32>> = HashMap::new();
+        let mut topic_metadata: HashMap<String, TopicMetadata> = HashMap::new();
+        for (topic, partition, leader) in partitions {
+            assigned_partitions
+                .entry(topic.to_string())
+                .or_default()
+                .push(*partition);
+            topic_metadata
+                .entry(topic.to_string())
+                .or_insert_with(|| TopicMetadata {
+                    error: ErrorCode::None,
+                    name: topic.to_string(),
+                    is_internal: false,
+                    partitions: Vec::new(),
+                })
+                .partitions
+                .push(PartitionMetadata {
+                    error: ErrorCode::None,
+                    partition_index: *partition,
+                    leader: BrokerId::from(*leader),
+                    replicas: vec![1, 2, 3],
+                    isr: vec![1, 2, 3],
+                });
+        }
+
+        AssignmentContext {
+            assigned_partitions,
+            topic_metadata,
+            broker_racks: (1..=3)
+                .map(|id| (BrokerId::from(id), Some(format!("az-{}", id))))
+                .collect(),
+            group: None,
+        }
+    }
+
+    fn offsets(partitions: &[(&str, i32, i64)]) -> Offsets {
+        let mut offsets = Offsets::new();
+        for (topic, partition, offset) in partitions {
+            offsets.insert(topic.to_string(), *partition, *offset);
+        }
+        offsets
+    }
+
+    fn fetched(request: &FetchRequestV11) -> Vec<(String, i32, i64)> {
+        request
+            .topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions
+                    .iter()
+                    .map(move |p| (t.name.to_string(), p.index, p.fetch_offset))
+            })
+            .collect()
+    }
+
+    fn fetched_from(
+        fetches: &[(BrokerId, FetchRequestV11)],
+        broker: i32,
+    ) -> Vec<(String, i32, i64)> {
+        let (_, request) = fetches
+            .iter()
+            .find(|(b, _)| *b == BrokerId::from(broker))
+            .expect("no request to broker");
+        let mut partitions = fetched(request);
+        partitions.sort();
+        partitions
+    }
+
+    fn strategy<'a>(context: &'a AssignmentContext, offsets: Offsets) -> SimpleFetchStrategy<'a> {
+        SimpleFetchStrategy::new(context, offsets, FetchConfig::default())
+    }
+
+    #[test]
+    fn partitions_are_grouped_by_leader() {
+        let context = context(&[("t1", 0, 1), ("t1", 1, 2), ("t2", 0, 1)]);
+        let offsets = offsets(&[("t1", 0, 5), ("t1", 1, 7), ("t2", 0, 9)]);
+        let fetches = strategy(&context, offsets).next_fetches();
+
+        assert_eq!(fetches.len(), 2);
+        assert_eq!(
+            fetched_from(&fetches, 1),
+            vec![("t1".to_string(), 0, 5), ("t2".to_string(), 0, 9)]
+        );
+        assert_eq!(fetched_from(&fetches, 2), vec![("t1".to_string(), 1, 7)]);
+    }
+
+    #[test]
+    fn requests_use_configured_limits() {
+        let context = context(&[("t1", 0, 1)]);
+        let config = FetchConfig {
+            max_wait_ms: 10,
+            min_bytes: 20,
+            max_bytes: 30,
+            partition_max_bytes: 40,
+            client_rack: Some("az-1".into()),
+            ..FetchConfig::default()
+        };
+        let mut strategy = SimpleFetchStrategy::new(&context, offsets(&[("t1", 0, 5)]), config);
+        let (_, request) = strategy.next_fetches().pop().unwrap();
+
+        assert_eq!(request.max_wait_time, 10);
+        assert_eq!(request.min_bytes, 20);
+        assert_eq!(request.max_bytes, 30);
+        assert_eq!(request.topics[0].partitions[0].partition_max_bytes, 40);
+        assert_eq!(request.rack_id, "az-1");
+    }
+
+    fn response(
+        topic: &str,
+        partition: i32,
+        error_code: ErrorCode,
+        preferred: i32,
+    ) -> FetchResponseV11 {
+        FetchResponseV11 {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::None,
+            session_id: 0,
+            topics: vec![FetchResponseTopicV11 {
+                name: topic.into(),
+                partitions: vec![FetchResponsePartitionV11 {
+                    index: partition,
+                    error_code,
+                    high_watermark: 0,
+                    last_stable_offset: 0,
+                    log_start_offset: 0,
+                    aborted_transactions: vec![],
+                    preferred_read_replica: preferred,
+                    record_set: vec![],
+                }],
+            }],
+        }
+    }
+
+    fn brokers(strategy: &mut SimpleFetchStrategy) -> Vec<BrokerId> {
+        strategy
+            .next_fetches()
+            .into_iter()
+            .map(|(b, _)| b)
+            .collect()
+    }
+
+    #[test]
+    fn in_rack_replica_is_not_chosen_by_client() {
+        let context = context(&[("t1", 0, 1)]);
+        let config = FetchConfig {
+            client_rack: Some("az-2".into()),
+            ..FetchConfig::default()
+        };
+        let mut strategy = SimpleFetchStrategy::new(&context, offsets(&[("t1", 0, 5)]), config);
+
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(1)]);
+    }
+
+    #[test]
+    fn preferred_replica_is_followed() {
+        let context = context(&[("t1", 0, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5)]));
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(1)]);
+
+        strategy.update_fetched(BrokerId::from(1), &response("t1", 0, ErrorCode::None, 3));
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(3)]);
+    }
+
+    #[test]
+    fn replica_error_falls_back_to_leader() {
+        let context = context(&[("t1", 0, 1)]);
+        let config = FetchConfig {
+            client_rack: Some("az-2".into()),
+            ..FetchConfig::default()
+        };
+        let mut strategy = SimpleFetchStrategy::new(&context, offsets(&[("t1", 0, 5)]), config);
+        strategy.update_fetched(BrokerId::from(1), &response("t1", 0, ErrorCode::None, 2));
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(2)]);
+
+        strategy.update_fetched(
+            BrokerId::from(2),
+            &response("t1", 0, ErrorCode::OffsetOutOfRange, -1),
+        );
+
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(1)]);
+    }
+
+    #[test]
+    fn failed_partitions_are_skipped_until_recovered() {
+        let context = context(&[("t1", 0, 1), ("t1", 1, 1), ("t1", 2, 1)]);
+        let offsets = offsets(&[("t1", 0, 5), ("t1", 1, 7), ("t1", 2, 9)]);
+        let mut strategy = strategy(&context, offsets);
+        let mut errors = Vec::new();
+        for (partition, error_code) in &[
+            (0, ErrorCode::NotLeaderForPartition),
+            (1, ErrorCode::OffsetOutOfRange),
+            (2, ErrorCode::KafkaStorageError),
+        ] {
+            let response = response("t1", *partition, *error_code, -1);
+            errors.extend(strategy.update_fetched(BrokerId::from(1), &response));
+        }
+        let codes: Vec<_> = errors.iter().map(|e| e.error_code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                ErrorCode::NotLeaderForPartition,
+                ErrorCode::OffsetOutOfRange,
+                ErrorCode::KafkaStorageError
+            ]
+        );
+        assert!(strategy.next_fetches().is_empty());
+
+        assert!(strategy.set_leader("t1", 0, BrokerId::from(2)));
+        assert!(strategy.seek("t1", 1, 0));
+        assert!(strategy.retry("t1", 2));
+        let fetches = strategy.next_fetches();
+        assert_eq!(fetched_from(&fetches, 2), vec![("t1".to_string(), 0, 5)]);
+        assert_eq!(
+            fetched_from(&fetches, 1),
+            vec![("t1".to_string(), 1, 0), ("t1".to_string(), 2, 9)]
+        );
+    }
+
+    #[test]
+    fn unknown_preferred_replica_is_ignored() {
+        let context = context(&[("t1", 0, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5)]));
+        strategy.update_fetched(BrokerId::from(1), &response("t1", 0, ErrorCode::None, 9));
+
+        assert_eq!(brokers(&mut strategy), vec![BrokerId::from(1)]);
+    }
+
+    #[test]
+    fn partition_order_is_rotated() {
+        let context = context(&[("t1", 0, 1), ("t1", 1, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5), ("t1", 1, 7)]));
+
+        let (_, first) = strategy.next_fetches().pop().unwrap();
+        let (_, second) = strategy.next_fetches().pop().unwrap();
+        assert_eq!(first.topics[0].partitions[0].index, 0);
+        assert_eq!(second.topics[0].partitions[0].index, 1);
+    }
+
+    #[test]
+    fn paused_partitions_are_skipped() {
+        let context = context(&[("t1", 0, 1), ("t1", 1, 2)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5), ("t1", 1, 7)]));
+
+        assert!(strategy.pause("t1", 0));
+        let fetches = strategy.next_fetches();
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetched_from(&fetches, 2), vec![("t1".to_string(), 1, 7)]);
+
+        assert!(strategy.pause("t1", 1));
+        assert!(strategy.next_fetches().is_empty());
+
+        assert!(strategy.resume("t1", 0));
+        let fetches = strategy.next_fetches();
+        assert_eq!(fetched_from(&fetches, 1), vec![("t1".to_string(), 0, 5)]);
+    }
+
+    #[test]
+    fn throttling_is_independent_of_pause() {
+        let context = context(&[("t1", 0, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5)]));
+
+        assert!(strategy.pause("t1", 0));
+        assert!(strategy.throttle("t1", 0, true));
+        assert!(strategy.resume("t1", 0));
+        assert!(strategy.next_fetches().is_empty());
+
+        assert!(strategy.throttle("t1", 0, false));
+        assert_eq!(strategy.next_fetches().len(), 1);
+        assert!(!strategy.throttle("t1", 1, true));
+    }
+
+    #[test]
+    fn seek_changes_fetch_offset() {
+        let context = context(&[("t1", 0, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5)]));
+
+        assert!(strategy.seek("t1", 0, 42));
+        let fetches = strategy.next_fetches();
+        assert_eq!(fetched_from(&fetches, 1), vec![("t1".to_string(), 0, 42)]);
+    }
+
+    #[test]
+    fn control_of_unassigned_partition_is_rejected() {
+        let context = context(&[("t1", 0, 1)]);
+        let mut strategy = strategy(&context, offsets(&[("t1", 0, 5)]));
+
+        assert!(!strategy.seek("t1", 1, 0));
+        assert!(!strategy.pause("t2", 0));
+        assert!(!strategy.resume("t2", 0));
+    }
+}
@@ -0,0 +1,152 @@
+use super::{fetch_strategy::GroupMembership, StopKind};
+use crate::{client::AsyncClusterClient, Error as RsKafkaError};
+use anyhow::{anyhow, Context as AnyhowContext, Result};
+use log::{info, warn};
+use rskafka_proto::{
+    apis::heartbeat::{HeartbeatRequestV1, HeartbeatResponseV1},
+    ErrorCode,
+};
+use std::{borrow::Cow, sync::Arc, time::Duration};
+use tokio::{
+    sync::oneshot::{self, error::TryRecvError},
+    time,
+};
+
+/// Heartbeats keeping group membership alive, sent by a background task independently of
+/// fetching, so that slow fetches or slow consumption of messages don't get member evicted.
+///
+/// Task stops once heartbeat response tells member to stop fetching, or when `Heartbeats` is
+/// dropped.
+pub(super) struct Heartbeats {
+    stopped: oneshot::Receiver<Result<StopKind>>,
+    /// Dropped to stop the task
+    _cancel: oneshot::Sender<()>,
+}
+
+impl Heartbeats {
+    pub fn start(
+        cluster: Arc<AsyncClusterClient>,
+        group: GroupMembership,
+        interval: Duration,
+    ) -> Self {
+        let (stopped_sender, stopped) = oneshot::channel();
+        let (cancel, cancelled) = oneshot::channel();
+        tokio::spawn(async move {
+            let heartbeats = send_heartbeats(&cluster, &group, interval);
+            tokio::select! {
+                _ = cancelled => (),
+                result = heartbeats => {
+                    let _ = stopped_sender.send(result);
+                }
+            }
+        });
+
+        Heartbeats {
+            stopped,
+            _cancel: cancel,
+        }
+    }
+
+    /// Completes with reason to stop fetching once heartbeats stopped
+    pub async fn stopped(&mut self) -> Result<StopKind> {
+        (&mut self.stopped)
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("heartbeat task stopped")))
+    }
+
+    /// Reason to stop fetching if heartbeats stopped already
+    pub fn try_stopped(&mut self) -> Option<Result<StopKind>> {
+        match self.stopped.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(anyhow!("heartbeat task stopped"))),
+        }
+    }
+}
+
+/// Sends heartbeats until member has to stop fetching
+async fn send_heartbeats(
+    cluster: &AsyncClusterClient,
+    group: &GroupMembership,
+    interval: Duration,
+) -> Result<StopKind> {
+    loop {
+        time::delay_for(interval).await;
+        if let Some(stop) = heartbeat(cluster, group).await? {
+            return Ok(stop);
+        }
+    }
+}
+
+/// Sends heartbeat to group coordinator. Returns reason to stop fetching if group is rebalancing
+/// or member was removed from the group.
+async fn heartbeat(
+    cluster: &AsyncClusterClient,
+    group: &GroupMembership,
+) -> Result<Option<StopKind>> {
+    if group.generation_id < 0 {
+        // Offsets committed outside of group management, no membership to keep alive
+        return Ok(None);
+    }
+
+    let request = HeartbeatRequestV1 {
+        group_id: Cow::Borrowed(&group.group_id),
+        generation_id: group.generation_id,
+        member_id: Cow::Borrowed(&group.member_id),
+    };
+    let response: HeartbeatResponseV1 =
+        match cluster.make_request(request, Some(group.coordinator)).await {
+            Ok(response) => response,
+            Err(RsKafkaError::Io(e)) => {
+                warn!(
+                    "Heartbeat to coordinator {} failed: {}",
+                    group.coordinator, e
+                );
+                return Ok(Some(StopKind::CoordinatorLost));
+            }
+            Err(e) => return Err(e).context("heartbeat"),
+        };
+
+    match response.error_code {
+        ErrorCode::None => Ok(None),
+        error if error.is_coordinator_error() => {
+            warn!(
+                "Heartbeat to coordinator {} failed: {}",
+                group.coordinator, error
+            );
+            Ok(Some(StopKind::CoordinatorLost))
+        }
+        ErrorCode::RebalanceInProgress => {
+            info!("Group {} is rebalancing", group.group_id);
+            Ok(Some(StopKind::RebalanceInProgress))
+        }
+        ErrorCode::IllegalGeneration | ErrorCode::UnknownMemberId | ErrorCode::FencedInstanceId => {
+            Ok(Some(StopKind::MemberLost))
+        }
+        error => Err(RsKafkaError::from(error)).context("heartbeat"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::BrokerId;
+
+    #[tokio::test]
+    async fn heartbeats_are_sent_without_fetching() {
+        let group = GroupMembership {
+            group_id: "group".into(),
+            generation_id: 1,
+            member_id: "member".into(),
+            coordinator: BrokerId::from(1),
+        };
+        let cluster = Arc::new(AsyncClusterClient::without_brokers());
+        let mut heartbeats = Heartbeats::start(cluster, group, Duration::from_millis(10));
+        assert!(heartbeats.try_stopped().is_none());
+
+        // Nobody polls heartbeats, yet the first one is sent and fails on unknown coordinator
+        time::delay_for(Duration::from_millis(100)).await;
+        let stopped = heartbeats.try_stopped().expect("heartbeat not sent");
+        assert!(format!("{:#}", stopped.unwrap_err()).contains("Broker 1 not found"));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{KafkaOffset, KafkaPartition};
+use futures::prelude::*;
+use rskafka_proto::{
+    apis::fetch::{FetchResponsePartitionV11, IsolationLevel},
+    ErrorCode,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Period byte and record rates are averaged over
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Metrics of a single assigned partition
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionMetrics {
+    pub partition: KafkaPartition,
+    /// Offset of the next message to fetch
+    pub position: Option<i64>,
+    /// Offset of the last message committed by consumer group
+    pub committed_offset: Option<i64>,
+    pub high_watermark: Option<i64>,
+    pub last_stable_offset: Option<i64>,
+    /// Number of messages between position and the end of partition visible to consumer (last
+    /// stable offset in `ReadCommitted` mode, high watermark otherwise)
+    pub lag: Option<i64>,
+    /// Duration of the last fetch request which returned partition data
+    pub fetch_latency: Option<Duration>,
+    pub bytes_per_second: f64,
+    pub records_per_second: f64,
+}
+
+/// Metrics of all partitions assigned to consumer, sorted by partition
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub partitions: Vec<PartitionMetrics>,
+}
+
+impl MetricsSnapshot {
+    pub fn partition(&self, topic: &str, partition: i32) -> Option<&PartitionMetrics> {
+        self.partitions
+            .iter()
+            .find(|m| m.partition.partition_index == partition && m.partition.topic_name == topic)
+    }
+
+    /// Sum of known lags of all partitions
+    pub fn total_lag(&self) -> i64 {
+        self.partitions.iter().filter_map(|m| m.lag).sum()
+    }
+}
+
+/// Position, lag and throughput of partitions assigned to consumer. Updated by consumer after
+/// every fetch and commit.
+///
+/// Handle is cheap to clone and stays valid after consumer is split.
+#[derive(Clone)]
+pub struct ConsumerMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+struct MetricsInner {
+    isolation_level: IsolationLevel,
+    partitions: Mutex<HashMap<KafkaPartition, PartitionState>>,
+    sender: watch::Sender<MetricsSnapshot>,
+    receiver: watch::Receiver<MetricsSnapshot>,
+}
+
+impl ConsumerMetrics {
+    pub(super) fn new(isolation_level: IsolationLevel) -> Self {
+        let (sender, receiver) = watch::channel(MetricsSnapshot::default());
+        ConsumerMetrics {
+            inner: Arc::new(MetricsInner {
+                isolation_level,
+                partitions: Mutex::new(HashMap::new()),
+                sender,
+                receiver,
+            }),
+        }
+    }
+
+    /// Current metrics of assigned partitions
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let now = Instant::now();
+        let states = self.inner.partitions.lock().unwrap();
+        let mut partitions: Vec<PartitionMetrics> = states
+            .iter()
+            .map(|(p, state)| state.metrics(p.clone(), self.inner.isolation_level, now))
+            .collect();
+        partitions.sort_by(|a, b| {
+            let (a, b) = (&a.partition, &b.partition);
+            (&a.topic_name, a.partition_index).cmp(&(&b.topic_name, b.partition_index))
+        });
+        MetricsSnapshot { partitions }
+    }
+
+    /// Snapshots published after every fetch. Intermediate snapshots are skipped if stream is not
+    /// polled often enough.
+    pub fn updates(&self) -> impl Stream<Item = MetricsSnapshot> {
+        self.inner.receiver.clone()
+    }
+
+    /// Replaces tracked partitions, keeping metrics of partitions which stay assigned
+    pub(super) fn assign(&self, partitions: &[KafkaPartition]) {
+        let mut states = self.inner.partitions.lock().unwrap();
+        states.retain(|p, _| partitions.contains(p));
+        for p in partitions {
+            states.entry(p.clone()).or_default();
+        }
+    }
+
+    pub(super) fn record_fetch(
+        &self,
+        topic: &str,
+        fetched: &FetchResponsePartitionV11,
+        position: Option<i64>,
+        latency: Duration,
+    ) {
+        let partition = KafkaPartition {
+            topic_name: topic.to_owned(),
+            partition_index: fetched.index,
+        };
+        let mut states = self.inner.partitions.lock().unwrap();
+        let state = match states.get_mut(&partition) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let now = Instant::now();
+        state.position = position.or(state.position);
+        if fetched.error_code == ErrorCode::None {
+            state.high_watermark = Some(fetched.high_watermark);
+            state.last_stable_offset = Some(fetched.last_stable_offset).filter(|o| *o >= 0);
+        }
+        state.fetch_latency = Some(latency);
+        state.bytes.record(now, fetched.record_set.len() as u64);
+        state.records.record(
+            now,
+            super::fetch_data::record_count(&fetched.record_set) as u64,
+        );
+    }
+
+    pub(super) fn record_committed(&self, offsets: &[KafkaOffset]) {
+        let mut states = self.inner.partitions.lock().unwrap();
+        for o in offsets {
+            let state = states
+                .iter_mut()
+                .find(|(p, _)| p.partition_index == o.partition && p.topic_name == o.topic);
+            if let Some((_, state)) = state {
+                state.committed_offset = Some(o.offset).filter(|o| *o >= 0);
+            }
+        }
+    }
+
+    /// Sends current snapshot to `updates` streams
+    pub(super) fn publish(&self) {
+        // Never fails, channel's own receiver is kept alive
+        let _ = self.inner.sender.broadcast(self.snapshot());
+    }
+}
+
+impl std::fmt::Debug for ConsumerMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConsumerMetrics")
+            .field("partitions", &self.snapshot().partitions)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct PartitionState {
+    position: Option<i64>,
+    committed_offset: Option<i64>,
+    high_watermark: Option<i64>,
+    last_stable_offset: Option<i64>,
+    fetch_latency: Option<Duration>,
+    bytes: Rate,
+    records: Rate,
+}
+
+impl PartitionState {
+    fn metrics(
+        &self,
+        partition: KafkaPartition,
+        isolation_level: IsolationLevel,
+        now: Instant,
+    ) -> PartitionMetrics {
+        let end = match isolation_level {
+            IsolationLevel::ReadCommitted => self.last_stable_offset.or(self.high_watermark),
+            IsolationLevel::ReadUncommitted => self.high_watermark,
+        };
+        let lag = match (end, self.position) {
+            (Some(end), Some(position)) => Some((end - position).max(0)),
+            _ => None,
+        };
+
+        PartitionMetrics {
+            partition,
+            position: self.position,
+            committed_offset: self.committed_offset,
+            high_watermark: self.high_watermark,
+            last_stable_offset: self.last_stable_offset,
+            lag,
+            fetch_latency: self.fetch_latency,
+            bytes_per_second: self.bytes.per_second(now),
+            records_per_second: self.records.per_second(now),
+        }
+    }
+}
+
+/// Rate of values recorded within `RATE_WINDOW`
+#[derive(Debug, Default)]
+struct Rate {
+    samples: VecDeque<(Instant, u64)>,
+    /// Time of the first sample, rate of the first window is averaged over shorter period
+    since: Option<Instant>,
+}
+
+impl Rate {
+    fn record(&mut self, now: Instant, value: u64) {
+        self.since.get_or_insert(now);
+        self.samples.push_back((now, value));
+        while let Some((time, _)) = self.samples.front() {
+            if now.duration_since(*time) <= RATE_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn per_second(&self, now: Instant) -> f64 {
+        let since = match self.since {
+            Some(since) => since,
+            None => return 0.0,
+        };
+        // At least a second, so that the first samples don't produce huge rates
+        let period = now
+            .duration_since(since)
+            .min(RATE_WINDOW)
+            .max(Duration::from_secs(1));
+        let total: u64 = self
+            .samples
+            .iter()
+            .filter(|(time, _)| now.duration_since(*time) <= RATE_WINDOW)
+            .map(|(_, value)| value)
+            .sum();
+        total as f64 / period.as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn partition(index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: index,
+        }
+    }
+
+    fn fetched(
+        index: i32,
+        high_watermark: i64,
+        last_stable_offset: i64,
+    ) -> FetchResponsePartitionV11 {
+        FetchResponsePartitionV11 {
+            index,
+            error_code: ErrorCode::None,
+            high_watermark,
+            last_stable_offset,
+            log_start_offset: 0,
+            aborted_transactions: Vec::new(),
+            preferred_read_replica: -1,
+            record_set: vec![0; 100],
+        }
+    }
+
+    #[test]
+    fn lag_is_measured_to_visible_end_of_partition() {
+        let metrics = ConsumerMetrics::new(IsolationLevel::ReadCommitted);
+        metrics.assign(&[partition(0), partition(1)]);
+        metrics.record_fetch(
+            "t1",
+            &fetched(0, 120, 110),
+            Some(100),
+            Duration::from_millis(5),
+        );
+        metrics.record_fetch(
+            "t1",
+            &fetched(1, 50, -1),
+            Some(45),
+            Duration::from_millis(5),
+        );
+        metrics.record_fetch("t1", &fetched(2, 50, 50), Some(0), Duration::from_millis(5));
+        metrics.record_committed(&[KafkaOffset {
+            topic: "t1".into(),
+            partition: 0,
+            offset: 99,
+        }]);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.partitions.len(), 2);
+        let first = snapshot.partition("t1", 0).unwrap();
+        assert_eq!(first.lag, Some(10));
+        assert_eq!(first.committed_offset, Some(99));
+        assert_eq!(first.fetch_latency, Some(Duration::from_millis(5)));
+        assert_eq!(snapshot.partition("t1", 1).unwrap().lag, Some(5));
+        assert_eq!(snapshot.total_lag(), 15);
+
+        metrics.assign(&[partition(1)]);
+        assert_eq!(metrics.snapshot().partitions.len(), 1);
+    }
+
+    #[test]
+    fn rate_is_averaged_over_window() {
+        let start = Instant::now();
+        let mut rate = Rate::default();
+        assert_eq!(rate.per_second(start), 0.0);
+
+        rate.record(start, 100);
+        rate.record(start + Duration::from_secs(10), 200);
+        assert_eq!(rate.per_second(start + Duration::from_secs(10)), 30.0);
+
+        rate.record(start + Duration::from_secs(35), 100);
+        assert_eq!(rate.per_second(start + Duration::from_secs(35)), 10.0);
+    }
+}
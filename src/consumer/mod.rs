@@ -1,20 +1,34 @@
 use crate::{
+    backoff::Backoff,
     client::{AsyncClusterClient, Broker},
     message::KafkaPartition,
     Error as RsKafkaError, KafkaMessage, KafkaOffset,
 };
 use anyhow::{Context as AnyhowContext, Error, Result};
-use assignment_stream::{Assignment, AssignmentStream};
-use fetch_data::FetchResponse;
-use fetch_strategy::{AssignmentContext, FetchStrategy, Offsets, SimpleFetchStrategy};
+use assignment_stream::{AssignmentFeed, ControlCommand};
+use assignor::{
+    AssignmentMetadata, Assignor, GroupProtocolMetadata, GroupProtocolMetadataOwned,
+    MemberAssignment, MemberSubscription,
+};
+use auto_commit::{AutoCommit, DeliveredOffsets};
+use fetch_session::FetchSessions;
+use fetch_strategy::{
+    AssignmentContext, FetchStrategy, GroupMembership, Offsets, SimpleFetchStrategy,
+};
 use futures::prelude::*;
+use heartbeat::Heartbeats;
 use log::{debug, error, info, log_enabled, trace, warn};
+use prefetch::PrefetchWriter;
+use recovery::PartitionRecovery;
 use rskafka_proto::{
     apis::{
-        fetch::FetchResponseV4,
+        fetch::FetchRequestV11,
         find_coordinator::{self, FindCoordinatorRequestV2, FindCoordinatorResponseV2},
         join_group::{GroupMember, JoinGroupRequestV4, JoinGroupResponseV4, Protocol},
-        metadata::{MetadataRequestV2, MetadataResponseV2, TopicMetadata},
+        metadata::{MetadataRequestV2, MetadataResponseV2},
+        offset_commit::{
+            OffsetCommitPartition, OffsetCommitRequestV2, OffsetCommitResponseV2, OffsetCommitTopic,
+        },
         offset_fetch::{
             OffsetFetchRequestV1, OffsetFetchResponseV1, TopicOffsets, TopicPartitions,
         },
@@ -29,16 +43,47 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
+use subscription::PatternSubscription;
 use tokio::{
     sync::{mpsc, Notify},
     task::JoinHandle,
     time,
 };
 
+mod ack_tracker;
 mod assignment_stream;
+pub mod assignor;
+mod auto_commit;
+mod config;
+mod error;
 mod fetch_data;
+mod fetch_session;
 mod fetch_strategy;
+mod heartbeat;
+mod metrics;
+mod partition_stream;
+mod position;
+mod prefetch;
+mod rebalance;
+mod recovery;
+mod standalone;
+mod subscription;
+mod typed_stream;
+
+pub use ack_tracker::AckTracker;
+pub use assignment_stream::{Assignment, AssignmentControl, CommitSink, ConsumerGroupMetadata};
+pub use config::{ConsumerConfig, ConsumerConfigBuilder, FetchConfig, FetchConfigBuilder};
+use error::request_error_code;
+pub use error::{PartitionError, PartitionErrorKind};
+pub use metrics::{ConsumerMetrics, MetricsSnapshot, PartitionMetrics};
+pub use partition_stream::PartitionStream;
+pub use position::{OffsetReset, StartPosition};
+pub use rebalance::RebalanceListener;
+pub use rskafka_proto::apis::fetch::IsolationLevel;
+pub use standalone::{StandaloneConsumer, StandaloneConsumerConfig};
+pub use typed_stream::{DeadLetter, DeserializeErrorPolicy, TypedMessage};
 
 pub struct ConsumerError(pub Error);
 
@@ -49,6 +94,12 @@ impl ConsumerError {
     {
         ConsumerError(self.0.context(context))
     }
+
+    /// Failure of a single partition, consumer keeps fetching other partitions. Other errors
+    /// end the assignment stream unless they come from auto commit.
+    pub fn partition_error(&self) -> Option<&PartitionError> {
+        self.0.downcast_ref()
+    }
 }
 
 impl<E> From<E> for ConsumerError
@@ -60,15 +111,10 @@ where
     }
 }
 
-pub struct ConsumerConfig {
-    pub topics: Vec<String>,
-    pub group_id: String,
-    pub client_id: String,
-}
-
 pub struct Consumer {
     receiver: mpsc::Receiver<Result<Assignment, ConsumerError>>,
     killswitch: ConsumerKillswitch,
+    metrics: ConsumerMetrics,
 }
 
 impl Consumer {
@@ -84,6 +130,12 @@ impl Consumer {
         ConsumerInternals::spawn(Arc::clone(client), config)
     }
 
+    /// Per partition position, lag and throughput metrics. Handle can be kept after consumer is
+    /// split.
+    pub fn metrics(&self) -> ConsumerMetrics {
+        self.metrics.clone()
+    }
+
     pub fn split(
         self,
     ) -> (
@@ -98,6 +150,7 @@ impl Consumer {
 struct ConsumerInternals {
     cluster: Arc<AsyncClusterClient>,
     config: ConsumerConfig,
+    metrics: ConsumerMetrics,
 }
 
 impl ConsumerInternals {
@@ -129,7 +182,12 @@ impl ConsumerInternals {
         let (sender, receiver) = mpsc::channel(1);
         let shutdown = Arc::new(Notify::new());
 
-        let internals = ConsumerInternals { cluster, config };
+        let metrics = ConsumerMetrics::new(config.fetch.isolation_level);
+        let internals = ConsumerInternals {
+            cluster,
+            config,
+            metrics: metrics.clone(),
+        };
 
         let join_handle = tokio::spawn({
             let shutdown = Arc::clone(&shutdown);
@@ -144,6 +202,7 @@ impl ConsumerInternals {
         Consumer {
             receiver,
             killswitch,
+            metrics,
         }
     }
 
@@ -169,30 +228,144 @@ impl ConsumerInternals {
         shutdown: Arc<Notify>,
     ) -> Result<(), Error> {
         // let leaders = self.fetch_partition_leaders().await?;
-        let coordinator = self.find_coordinator().await?;
-
-        let mut assignment_context = self.join_group(coordinator, None).await?;
+        let mut retries = GroupRetries::new(&self.config);
+        let mut coordinator = self.find_coordinator(&mut retries).await?;
+
+        let mut group_tasks = GroupTasks {
+            heartbeat_interval: self.config.heartbeat_interval,
+            auto_commit: self
+                .config
+                .auto_commit_interval
+                .map(|interval| AutoCommit::new(interval, sender.clone())),
+            subscription: self.config.topic_pattern.as_ref().map(|pattern| {
+                PatternSubscription::new(pattern.clone(), self.config.metadata_refresh_interval)
+            }),
+        };
+        let mut member_id = None;
         loop {
-            // Send new message stream for assignment
-            let (fetch_sender, fetch_receiver) = mpsc::channel(1);
-            let (commit_sender, commit_receiver) = mpsc::channel(10);
-            let assignment = Assignment::new(fetch_receiver, commit_sender);
+            let topics = match group_tasks.subscription.as_mut() {
+                Some(subscription) => {
+                    if subscription.is_due() {
+                        subscription.refresh(&self.cluster).await;
+                    }
+                    subscription.topics().to_vec()
+                }
+                None => self.config.topics.clone(),
+            };
+            let mut assignment_context = loop {
+                let e = match self
+                    .join_group(coordinator, &topics, member_id.as_deref())
+                    .await
+                {
+                    Ok(context) => break context,
+                    Err(e) => e,
+                };
+                match join_retry(&e) {
+                    JoinRetry::FindCoordinator => {
+                        warn!("Joining group failed: {:#}", e);
+                        coordinator = self.rediscover_coordinator(&mut retries).await?;
+                    }
+                    retry @ JoinRetry::Rejoin | retry @ JoinRetry::RejoinAsNewMember => {
+                        let delay = match retries.next_delay() {
+                            Some(delay) => delay,
+                            None => return Err(e),
+                        };
+                        warn!("Joining group failed: {:#}, rejoining in {:?}", e, delay);
+                        if retry == JoinRetry::RejoinAsNewMember {
+                            member_id = None;
+                        }
+                        time::delay_for(delay).await;
+                    }
+                    JoinRetry::Fail => return Err(e),
+                }
+            };
+            let partitions = assignment_context.partitions();
+            if let Some(listener) = self.config.rebalance_listener.as_ref() {
+                listener.on_partitions_assigned(&partitions).await;
+            }
 
-            if let Err(_) = sender.send(Ok(assignment)).await {
+            // Send new message stream for assignment
+            let (assignment, mut feed) = Assignment::new(
+                partitions.clone(),
+                group_tasks.auto_commit.is_some(),
+                &self.config.fetch,
+            );
+            let assignment = match assignment_context.group.as_ref() {
+                Some(group) => assignment.with_group_metadata(ConsumerGroupMetadata {
+                    group_id: group.group_id.clone(),
+                    generation_id: group.generation_id,
+                    member_id: group.member_id.clone(),
+                    group_instance_id: None,
+                }),
+                None => assignment,
+            };
+            self.metrics.assign(&partitions);
+            feed.metrics = Some(self.metrics.clone());
+            feed.errors = Some(sender.clone());
+
+            if sender.send(Ok(assignment)).await.is_err() {
                 debug!("shutting down - Assignment stream receiver deallocated");
                 return Ok(());
             }
 
-            match self
-                .fetch_loop(&assignment_context, &shutdown, fetch_sender)
-                .await
-                .context("fetch loop failed")?
-            {
-                StopKind::RebalanceInProgress => continue,
-                StopKind::Shutdown => break,
+            let offsets = loop {
+                match self.fetch_offsets(&assignment_context).await {
+                    Err(e) if is_coordinator_error(&e) => {
+                        warn!("Fetching committed offsets failed: {:#}", e);
+                        coordinator = self.rediscover_coordinator(&mut retries).await?;
+                        assignment_context.set_coordinator(coordinator);
+                    }
+                    result => break result?,
+                }
+            };
+            retries.reset();
+
+            let stop = fetch_loop(
+                &self.cluster,
+                &assignment_context,
+                offsets,
+                &self.config.fetch,
+                Backoff::new(self.config.retry_backoff, self.config.retry_backoff_max),
+                Some(&mut group_tasks),
+                &shutdown,
+                &mut feed,
+            )
+            .await
+            .context("fetch loop failed")?;
+
+            if let StopKind::CoordinatorLost = stop {
+                coordinator = self.rediscover_coordinator(&mut retries).await?;
+                assignment_context.set_coordinator(coordinator);
             }
 
-            assignment_context = self.join_group(coordinator, None).await?;
+            let group = assignment_context.group.as_ref();
+            member_id = match stop {
+                StopKind::Shutdown
+                | StopKind::RebalanceInProgress
+                | StopKind::SubscriptionChanged
+                | StopKind::CoordinatorLost => {
+                    if let Some(listener) = self.config.rebalance_listener.as_ref() {
+                        listener.on_partitions_revoked(&partitions).await;
+                    }
+                    // Includes offsets not committed by fetch loop when coordinator was lost
+                    let metrics = feed.metrics.as_ref();
+                    commit_pending(&self.cluster, group, &mut feed.commit_receiver, metrics).await;
+                    group_tasks
+                        .auto_commit(&self.cluster, group, &feed.delivered, &feed.metrics)
+                        .await;
+                    group.map(|g| g.member_id.clone())
+                }
+                StopKind::MemberLost => {
+                    warn!("Consumer was removed from group, rejoining");
+                    if let Some(listener) = self.config.rebalance_listener.as_ref() {
+                        listener.on_partitions_lost(&partitions).await;
+                    }
+                    None
+                }
+            };
+            if let StopKind::Shutdown = stop {
+                break;
+            }
         }
 
         //do cleanup stuff
@@ -200,62 +373,34 @@ impl ConsumerInternals {
         return Ok(());
     }
 
-    async fn fetch_loop(
-        &self,
-        assignment: &AssignmentContext,
-        shutdown: &Notify,
-        mut fetch_sender: mpsc::Sender<FetchResponse>,
-    ) -> Result<StopKind> {
-        let offsets = self.fetch_offsets(&assignment).await?;
-        let mut fetch_strategy = SimpleFetchStrategy::new(assignment, offsets);
-
-        loop {
-            let (broker, fetch_request) = fetch_strategy.next_fetch();
-            trace!(target: "rskafka::fetch", "REQUEST\n{:#?}", fetch_request);
-            let fetch_response: FetchResponseV4 = self
-                .cluster
-                .make_request(fetch_request, Some(broker))
-                .await?;
-            trace!(target: "rskafka::fetch", "RESPONSE\n{:#?}", fetch_response);
-            let fetch = FetchResponse::from(fetch_response);
-
-            //todo: handle errors
-            fetch_strategy.update_fetched(&fetch);
-            fetch_sender.send(fetch).await.unwrap(); //todo handle error
-        }
-        shutdown.notified().await;
-        Ok(StopKind::Shutdown)
-    }
-
+    /// Offsets fetching of assigned partitions starts from. Partitions with offset committed by the
+    /// group resume from it, others start from configured start position.
     async fn fetch_offsets(&self, a: &AssignmentContext) -> Result<Offsets, Error> {
-        let request = OffsetFetchRequestV1 {
-            group_id: Cow::Borrowed(&self.config.group_id),
-            topics: a
-                .assigned_partitions
-                .iter()
-                .map(|(t, p)| TopicPartitions {
-                    name: t.into(),
-                    partition_indexes: p.clone(),
+        let group = a.group.as_ref().context("missing group membership")?;
+        let topics = fetch_committed_offsets(&self.cluster, group, &a.assigned_partitions).await?;
+        let committed: Vec<KafkaOffset> = topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions.iter().map(move |p| KafkaOffset {
+                    topic: Cow::Borrowed(&t.name),
+                    partition: p.index,
+                    offset: p.committed_offset,
                 })
-                .collect(),
-        };
-
-        let response: OffsetFetchResponseV1 = self
-            .cluster
-            .make_request(request, Some(a.coordinator))
-            .await?;
-
-        response.topics.iter().for_each(|t| {
-            t.partitions.iter().for_each(|p| match p.error_code {
-                ErrorCode::None => debug!(
-                    "Fetched offset {}[{}]: {}",
-                    t.name, p.index, p.committed_offset
-                ),
-                error => error!("Offset fetch error: {}", error),
             })
-        });
-
-        Offsets::from_response(response.topics).map_err(Into::into)
+            .collect();
+        self.metrics.record_committed(&committed);
+        let committed = committed_by_partition(topics)?;
+
+        let positions =
+            position::group_positions(&a.partitions(), &committed, self.config.start_position);
+        let offsets = position::resolve_offsets(
+            &self.cluster,
+            &positions,
+            &committed,
+            self.config.fetch.isolation_level,
+        )
+        .await?;
+        Ok(offsets)
     }
 
     // async fn fetch_partition_leaders(&self) -> Result<HashMap<KafkaPartition, BrokerId>, Error> {
@@ -294,36 +439,53 @@ impl ConsumerInternals {
     //     Ok(leaders)
     // }
 
-    async fn find_coordinator(&self) -> Result<BrokerId> {
-        let request = FindCoordinatorRequestV2 {
-            key: self.config.group_id.clone(),
-            key_type: find_coordinator::KeyType::Group,
-        };
-        let response: FindCoordinatorResponseV2 = self.cluster.make_request(request, None).await?;
-
-        match response.error_code {
-            ErrorCode::None => {
-                trace!(
-                    "Found coordinator for group {}: {}",
-                    self.config.group_id,
-                    response.node_id
-                );
-                Ok(response.node_id)
+    /// Looks up group coordinator, retrying while it is not available
+    async fn find_coordinator(&self, retries: &mut GroupRetries) -> Result<BrokerId> {
+        loop {
+            match find_coordinator(&self.cluster, &self.config.group_id).await {
+                Err(e) if is_coordinator_error(&e) => {
+                    let delay = match retries.next_delay() {
+                        Some(delay) => delay,
+                        None => return Err(e),
+                    };
+                    warn!(
+                        "Finding coordinator failed: {:#}, retrying in {:?}",
+                        e, delay
+                    );
+                    time::delay_for(delay).await;
+                }
+                result => return result,
             }
-            error => Err(RsKafkaError::from(error).into()),
         }
     }
 
+    /// Finds coordinator again after it failed over to another broker
+    async fn rediscover_coordinator(&self, retries: &mut GroupRetries) -> Result<BrokerId> {
+        let delay = retries.next_delay().with_context(|| {
+            format!(
+                "coordinator of group {} not reachable after {} retries",
+                self.config.group_id, self.config.group_retries
+            )
+        })?;
+        info!(
+            "Finding coordinator of group {} in {:?}",
+            self.config.group_id, delay
+        );
+        time::delay_for(delay).await;
+        self.find_coordinator(retries).await
+    }
+
     async fn join_group(
         &self,
         coordinator: BrokerId,
+        topics: &[String],
         member_id: Option<&str>,
     ) -> Result<AssignmentContext> {
         let mut member_id = member_id.map(Cow::Borrowed);
-        let metadata = self.build_group_protocol_metadata();
+        let protocols = self.build_group_protocols(topics);
 
         let join_group_response: JoinGroupResponseV4 = loop {
-            let request = self.build_join_group_request(&metadata, member_id.clone());
+            let request = self.build_join_group_request(&protocols, member_id.clone());
             let response: JoinGroupResponseV4 = self
                 .cluster
                 .make_request(request, Some(coordinator))
@@ -340,32 +502,55 @@ impl ConsumerInternals {
             }
         };
 
-        let members_per_topic = Self::extract_members_for_topics(&join_group_response.members);
-        let topics_metadata = self
-            .get_topics_metadata(members_per_topic.keys().map(String::as_str))
-            .await?;
+        let assignor = self
+            .find_assignor(&join_group_response.protocol_name)
+            .with_context(|| {
+                format!(
+                    "group selected unknown protocol {}",
+                    join_group_response.protocol_name
+                )
+            })?;
+
+        let assignments = if !join_group_response.members.is_empty() {
+            debug!(
+                "Performing partition assignment using {} assignor",
+                assignor.name()
+            );
+            let members = Self::extract_member_subscriptions(&join_group_response.members)?;
+            let mut topics: Vec<&str> = members
+                .iter()
+                .flat_map(|m| m.topics.iter().map(String::as_str))
+                .collect();
+            topics.sort();
+            topics.dedup();
+            let metadata = self.get_metadata(topics).await?;
+
+            let mut assignments = assignor.assign(&members, &metadata)?;
+            for m in members.iter() {
+                assignments.entry(m.member_id.clone()).or_default();
+            }
 
-        let sync_group_request = if !join_group_response.members.is_empty() {
-            debug!("Performing partition assignment");
-            // Assign partitions
-            let assignments =
-                self.assign_partitions_roundrobin(&members_per_topic, &topics_metadata)?;
-
-            SyncGroupRequestV2 {
-                group_id: Cow::Borrowed(&self.config.group_id),
-                generation_id: join_group_response.generation_id,
-                member_id: Cow::Borrowed(&join_group_response.member_id),
-                assignments,
+            if log_enabled!(log::Level::Debug) {
+                Self::log_assingment(&assignments);
             }
+
+            assignments
+                .into_iter()
+                .map(|(member_id, assignment)| MemberAssignmentData {
+                    member_id: Cow::Owned(member_id),
+                    assignment: AssignmentMetadata::from(assignment).to_wire_bytes(),
+                })
+                .collect()
         } else {
             debug!("Requesting partition assignment");
-            // Wait for assignment
-            SyncGroupRequestV2 {
-                group_id: Cow::Borrowed(&self.config.group_id),
-                generation_id: join_group_response.generation_id,
-                member_id: Cow::Borrowed(&join_group_response.member_id),
-                assignments: Vec::new(),
-            }
+            Vec::new()
+        };
+
+        let sync_group_request = SyncGroupRequestV2 {
+            group_id: Cow::Borrowed(&self.config.group_id),
+            generation_id: join_group_response.generation_id,
+            member_id: Cow::Borrowed(&join_group_response.member_id),
+            assignments,
         };
 
         let sync_group_response: SyncGroupResponseV2 = self
@@ -375,204 +560,721 @@ impl ConsumerInternals {
 
         match sync_group_response.error_code {
             ErrorCode::None => {
-                let assigned =
-                    AssignmentMetadata::from_wire_bytes(&sync_group_response.assignment)?.topics;
+                let assignment = if sync_group_response.assignment.is_empty() {
+                    MemberAssignment::default()
+                } else {
+                    AssignmentMetadata::from_wire_bytes(&sync_group_response.assignment)?.into()
+                };
+                assignor.on_assignment(&assignment);
 
                 if log_enabled!(log::Level::Debug) {
-                    for (topic, partitions) in assigned.iter() {
+                    for (topic, partitions) in assignment.partitions.iter() {
                         for partition in partitions {
                             debug!("Assigned to partition {}[{}]", topic, partition);
                         }
                     }
                 }
 
+                let metadata = self
+                    .get_metadata(assignment.partitions.keys().map(String::as_str))
+                    .await?;
+
                 Ok(AssignmentContext {
-                    generation_id: join_group_response.generation_id,
-                    member_id: join_group_response.member_id,
-                    assigned_partitions: assigned.into_iter().collect(),
-                    topic_metadata: topics_metadata
+                    assigned_partitions: assignment.partitions,
+                    broker_racks: broker_racks(&metadata),
+                    topic_metadata: metadata
+                        .topics
                         .into_iter()
                         .map(|t| (t.name.clone(), t))
                         .collect(),
-                    coordinator,
+                    group: Some(GroupMembership {
+                        group_id: self.config.group_id.clone(),
+                        generation_id: join_group_response.generation_id,
+                        member_id: join_group_response.member_id,
+                        coordinator,
+                    }),
                 })
             }
             error => Err(RsKafkaError::from(error).into()),
         }
     }
 
-    async fn get_topics_metadata<'a, I>(&self, topics: I) -> Result<Vec<TopicMetadata>, Error>
+    async fn get_metadata<'a, I>(&self, topics: I) -> Result<MetadataResponseV2, Error>
     where
         I: IntoIterator<Item = &'a str>,
     {
-        let request = MetadataRequestV2 {
-            topics: topics.into_iter().map(|s| s.to_owned()).collect(),
-        };
-        let response: MetadataResponseV2 = self.cluster.make_request(request, None).await?;
+        get_metadata(&self.cluster, topics).await
+    }
 
-        Ok(response.topics)
+    fn find_assignor(&self, protocol_name: &str) -> Option<&dyn Assignor> {
+        self.config
+            .assignors
+            .iter()
+            .find(|a| a.name() == protocol_name)
+            .map(AsRef::as_ref)
     }
 
-    fn build_group_protocol_metadata(&self) -> Vec<u8> {
-        let protocol_metadata = GroupProtocolMetadata::new(self.config.topics.as_slice().into());
-        protocol_metadata.to_wire_bytes()
+    fn build_group_protocols<'a>(&'a self, topics: &'a [String]) -> Vec<(&'a str, Vec<u8>)> {
+        self.config
+            .assignors
+            .iter()
+            .map(|assignor| {
+                let user_data = assignor.subscription_user_data(topics);
+                let protocol_metadata = GroupProtocolMetadata::new(topics.into(), user_data);
+                (assignor.name(), protocol_metadata.to_wire_bytes())
+            })
+            .collect()
     }
 
     fn build_join_group_request<'a>(
         &'a self,
-        protocol_metadata: &'a [u8],
+        protocols: &'a [(&'a str, Vec<u8>)],
         member_id: Option<Cow<'a, str>>,
     ) -> JoinGroupRequestV4<'a> {
         let member_id = member_id.unwrap_or(Cow::Borrowed(""));
         JoinGroupRequestV4 {
-            rebalance_timeout_ms: 10000,
-            session_timeout_ms: 30000,
+            rebalance_timeout_ms: self.config.rebalance_timeout_ms,
+            session_timeout_ms: self.config.session_timeout_ms,
             group_id: self.config.group_id.as_str().into(),
             member_id,
             protocol_type: "consumer".into(),
-            protocols: vec![
-                Protocol {
-                    name: "range".into(),
-                    metadata: protocol_metadata.into(),
-                },
-                Protocol {
-                    name: "roundrobin".into(),
-                    metadata: protocol_metadata.into(),
-                },
-            ],
+            protocols: protocols
+                .iter()
+                .map(|(name, metadata)| Protocol {
+                    name: Cow::Borrowed(name),
+                    metadata: Cow::Borrowed(metadata),
+                })
+                .collect(),
         }
     }
 
-    fn extract_members_for_topics(members: &[GroupMember]) -> HashMap<String, Vec<&str>> {
-        let mut members_per_topic: HashMap<String, Vec<&str>> = HashMap::new();
-        for m in members {
-            let topics: Vec<String> = GroupProtocolMetadataOwned::from_wire_bytes(&m.metadata)
-                .unwrap()
-                .topics
-                .into_iter()
-                .map(Into::into)
-                .collect();
+    fn extract_member_subscriptions(members: &[GroupMember]) -> Result<Vec<MemberSubscription>> {
+        members
+            .iter()
+            .map(|m| {
+                let metadata = GroupProtocolMetadataOwned::from_wire_bytes(&m.metadata)
+                    .with_context(|| format!("invalid subscription of member {}", m.member_id))?;
+                Ok(MemberSubscription {
+                    member_id: m.member_id.clone(),
+                    topics: metadata.topics.into_owned(),
+                    user_data: metadata.user_data.0,
+                })
+            })
+            .collect()
+    }
 
-            for topic in topics.into_iter() {
-                members_per_topic
-                    .entry(topic)
-                    .or_default()
-                    .push(&m.member_id)
+    fn log_assingment(assignments: &HashMap<String, MemberAssignment>) {
+        for (member, assignment) in assignments.iter() {
+            for (topic, partitions) in assignment.partitions.iter() {
+                for partition in partitions {
+                    debug!("Assigning {}[{}] to {}", topic, partition, member)
+                }
             }
         }
+    }
+}
+
+/// Whether request failed because group coordinator moved, is temporarily unavailable or could
+/// not be reached. Such requests are retried after finding the coordinator again.
+fn is_coordinator_error(e: &Error) -> bool {
+    e.chain()
+        .any(|cause| match cause.downcast_ref::<RsKafkaError>() {
+            Some(RsKafkaError::ErrorResponse(code, _)) => code.is_coordinator_error(),
+            Some(RsKafkaError::Io(_)) => true,
+            _ => false,
+        })
+}
+
+/// How to recover from failed attempt to join group
+#[derive(Debug, PartialEq, Eq)]
+enum JoinRetry {
+    /// Coordinator moved or is unreachable
+    FindCoordinator,
+    /// Group started another rebalance before assignment was received
+    Rejoin,
+    /// Coordinator forgot member, e.g. its session expired during coordinator failover
+    RejoinAsNewMember,
+    Fail,
+}
 
-        members_per_topic
+fn join_retry(e: &Error) -> JoinRetry {
+    if is_coordinator_error(e) {
+        return JoinRetry::FindCoordinator;
     }
+    let code = e.chain().find_map(|cause| match cause.downcast_ref() {
+        Some(RsKafkaError::ErrorResponse(code, _)) => Some(*code),
+        _ => None,
+    });
+    match code {
+        Some(ErrorCode::RebalanceInProgress) => JoinRetry::Rejoin,
+        Some(ErrorCode::UnknownMemberId) | Some(ErrorCode::IllegalGeneration) => {
+            JoinRetry::RejoinAsNewMember
+        }
+        _ => JoinRetry::Fail,
+    }
+}
 
-    //todo: unittests!
-    fn assign_partitions_roundrobin(
-        &self,
-        members_per_topic: &HashMap<String, Vec<&str>>,
-        topics_metadata: &[TopicMetadata],
-    ) -> Result<Vec<MemberAssignmentData<'static>>, Error> {
-        let topics_metadata: HashMap<_, _> =
-            topics_metadata.into_iter().map(|m| (&m.name, m)).collect();
-        // Only roundrobin supported as for now
-
-        let mut assignments_per_member: HashMap<&str, HashMap<&str, Vec<i32>>> = HashMap::new();
-        for (topic, members) in members_per_topic {
-            let t_meta = topics_metadata.get(&topic).unwrap(); // Metadata existence should be guaranteed earlier
-            t_meta
-                .partitions
-                .iter()
-                .map(|p| p.partition_index)
-                .zip(members.iter().cycle())
-                .for_each(|(idx, member_id)| {
-                    assignments_per_member
-                        .entry(*member_id)
-                        .or_default()
-                        .entry(&t_meta.name)
-                        .or_default()
-                        .push(idx)
-                });
+/// Consecutive failed attempts of group requests. Consumer gives up once `group_retries` of
+/// them were retried without success.
+struct GroupRetries {
+    backoff: Backoff,
+    failures: u32,
+    max: u32,
+}
+
+impl GroupRetries {
+    fn new(config: &ConsumerConfig) -> Self {
+        GroupRetries {
+            backoff: Backoff::new(config.retry_backoff, config.retry_backoff_max),
+            failures: 0,
+            max: config.group_retries,
         }
+    }
 
-        let assignments: Vec<(String, Vec<(String, Vec<i32>)>)> = assignments_per_member
-            .into_iter()
-            .map(|(member_id, topics)| {
-                (
-                    member_id.to_string(),
-                    topics
-                        .into_iter()
-                        .map(|(topic, partitions)| (topic.to_owned(), partitions))
-                        .collect(),
-                )
-            })
-            .collect();
+    /// Delay before the next attempt, `None` if no retries are left
+    fn next_delay(&mut self) -> Option<Duration> {
+        if self.failures >= self.max {
+            return None;
+        }
+        self.failures += 1;
+        Some(self.backoff.next_delay())
+    }
 
-        if log_enabled!(log::Level::Debug) {
-            Self::log_assingment(&assignments);
+    /// Member joined the group and fetched its offsets
+    fn reset(&mut self) {
+        self.failures = 0;
+        self.backoff.reset();
+    }
+}
+
+async fn find_coordinator(cluster: &AsyncClusterClient, group_id: &str) -> Result<BrokerId> {
+    let request = FindCoordinatorRequestV2 {
+        key: group_id.to_owned(),
+        key_type: find_coordinator::KeyType::Group,
+    };
+    let response: FindCoordinatorResponseV2 = cluster.make_request(request, None).await?;
+
+    match response.error_code {
+        ErrorCode::None => {
+            trace!(
+                "Found coordinator for group {}: {}",
+                group_id,
+                response.node_id
+            );
+            Ok(response.node_id)
         }
+        error => Err(RsKafkaError::from(error).into()),
+    }
+}
 
-        let assignments = assignments
+async fn get_metadata<'a, I>(cluster: &AsyncClusterClient, topics: I) -> Result<MetadataResponseV2>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let request = MetadataRequestV2 {
+        topics: topics
             .into_iter()
-            .map(|(member_id, topics)| MemberAssignmentData {
-                member_id: Cow::Owned(member_id),
-                assignment: AssignmentMetadata::new(topics).to_wire_bytes(),
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>()
+            .into(),
+    };
+    let response: MetadataResponseV2 = cluster.make_request(request, None).await?;
+
+    Ok(response)
+}
+
+fn broker_racks(metadata: &MetadataResponseV2) -> HashMap<BrokerId, Option<String>> {
+    metadata
+        .brokers
+        .iter()
+        .map(|b| (b.node_id, b.rack.clone().into_owned_option()))
+        .collect()
+}
+
+async fn fetch_committed_offsets(
+    cluster: &AsyncClusterClient,
+    group: &GroupMembership,
+    partitions: &HashMap<String, Vec<i32>>,
+) -> Result<Vec<TopicOffsets>> {
+    let request = OffsetFetchRequestV1 {
+        group_id: Cow::Borrowed(&group.group_id),
+        topics: partitions
+            .iter()
+            .map(|(t, p)| TopicPartitions {
+                name: t.into(),
+                partition_indexes: p.clone(),
             })
-            .collect();
+            .collect(),
+    };
+
+    let response: OffsetFetchResponseV1 = cluster
+        .make_request(request, Some(group.coordinator))
+        .await?;
+
+    response.topics.iter().for_each(|t| {
+        t.partitions.iter().for_each(|p| match p.error_code {
+            ErrorCode::None => debug!(
+                "Fetched offset {}[{}]: {}",
+                t.name, p.index, p.committed_offset
+            ),
+            error => error!("Offset fetch error: {}", error),
+        })
+    });
+
+    Ok(response.topics)
+}
+
+/// Offsets committed by group, keyed by partition
+async fn committed_offsets(
+    cluster: &AsyncClusterClient,
+    group: &GroupMembership,
+    partitions: &HashMap<String, Vec<i32>>,
+) -> Result<HashMap<KafkaPartition, i64>> {
+    committed_by_partition(fetch_committed_offsets(cluster, group, partitions).await?)
+}
 
-        Ok(assignments)
+fn committed_by_partition(topics: Vec<TopicOffsets>) -> Result<HashMap<KafkaPartition, i64>> {
+    let mut committed = HashMap::new();
+    for t in topics {
+        for p in t.partitions {
+            if p.error_code != ErrorCode::None {
+                return Err(RsKafkaError::from(p.error_code))
+                    .context(format!("offset fetch for {}[{}]", t.name, p.index));
+            }
+            let partition = KafkaPartition {
+                topic_name: t.name.clone(),
+                partition_index: p.index,
+            };
+            committed.insert(partition, p.committed_offset);
+        }
     }
 
-    fn log_assingment(assignments: &[(String, Vec<(String, Vec<i32>)>)]) {
-        for (member, topics) in assignments.iter() {
-            for (topic, partitions) in topics {
-                for partition in partitions {
-                    debug!("Assigning {}[{}] to {}", topic, partition, member)
+    Ok(committed)
+}
+
+/// Interval of checking for new commands and commits when all partitions are paused or throttled
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Periodic work keeping group membership and committed offsets up to date
+struct GroupTasks {
+    heartbeat_interval: Duration,
+    auto_commit: Option<AutoCommit>,
+    subscription: Option<PatternSubscription>,
+}
+
+impl GroupTasks {
+    /// Commits offsets delivered to user in auto commit mode
+    async fn auto_commit(
+        &mut self,
+        cluster: &AsyncClusterClient,
+        group: Option<&GroupMembership>,
+        delivered: &Option<DeliveredOffsets>,
+        metrics: &Option<ConsumerMetrics>,
+    ) {
+        if let (Some(auto_commit), Some(delivered)) = (self.auto_commit.as_mut(), delivered) {
+            if let Err(e) = commit_delivered(cluster, group, delivered, metrics.as_ref()).await {
+                auto_commit.report(e);
+            }
+            auto_commit.reschedule();
+        }
+    }
+
+    fn auto_commit_due(&self) -> bool {
+        self.auto_commit.as_ref().is_some_and(AutoCommit::is_due)
+    }
+
+    /// Re-checks topics matching subscribed pattern when due. Returns true if they changed.
+    async fn subscription_changed(&mut self, cluster: &AsyncClusterClient) -> bool {
+        match self.subscription.as_mut() {
+            Some(subscription) if subscription.is_due() => subscription.refresh(cluster).await,
+            _ => false,
+        }
+    }
+}
+
+/// Fetches assigned partitions until shutdown or until group starts rebalancing. With
+/// `group_tasks` group membership is kept alive with heartbeats and offsets are auto committed.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_loop(
+    cluster: &Arc<AsyncClusterClient>,
+    assignment: &AssignmentContext,
+    offsets: Offsets,
+    config: &FetchConfig,
+    retry_backoff: Backoff,
+    mut group_tasks: Option<&mut GroupTasks>,
+    shutdown: &Notify,
+    feed: &mut AssignmentFeed,
+) -> Result<StopKind> {
+    let AssignmentFeed {
+        prefetch,
+        commit_receiver,
+        control_receiver,
+        delivered,
+        metrics,
+        errors,
+    } = feed;
+    let mut fetch_strategy = SimpleFetchStrategy::new(assignment, offsets, config.clone());
+    let mut recovery = PartitionRecovery::new(errors.clone(), retry_backoff);
+    let partitions = assignment.partitions();
+    let mut sessions = FetchSessions::new();
+    let mut heartbeats = match (group_tasks.as_ref(), assignment.group.as_ref()) {
+        (Some(tasks), Some(group)) => Some(Heartbeats::start(
+            Arc::clone(cluster),
+            group.clone(),
+            tasks.heartbeat_interval,
+        )),
+        _ => None,
+    };
+
+    let stop = loop {
+        if let Some(stop) = heartbeats.as_mut().and_then(Heartbeats::try_stopped) {
+            break stop?;
+        }
+        if let (Some(tasks), Some(group)) = (group_tasks.as_mut(), assignment.group.as_ref()) {
+            if tasks.auto_commit_due() {
+                tasks
+                    .auto_commit(cluster, Some(group), delivered, metrics)
+                    .await;
+            }
+            if tasks.subscription_changed(cluster).await {
+                break StopKind::SubscriptionChanged;
+            }
+        }
+
+        commit_pending(
+            cluster,
+            assignment.group.as_ref(),
+            commit_receiver,
+            metrics.as_ref(),
+        )
+        .await;
+        while let Ok(command) = control_receiver.try_recv() {
+            apply_control(
+                cluster,
+                assignment,
+                config,
+                &mut fetch_strategy,
+                prefetch,
+                command,
+            )
+            .await;
+        }
+        if recovery.is_due() {
+            recovery
+                .recover(cluster, assignment, config, &mut fetch_strategy, prefetch)
+                .await;
+        }
+
+        for p in partitions.iter() {
+            let throttled = !prefetch.has_room(&p.topic_name, p.partition_index);
+            fetch_strategy.throttle(&p.topic_name, p.partition_index, throttled);
+        }
+
+        let fetch_requests = fetch_strategy.next_fetches();
+        if fetch_requests.is_empty() {
+            tokio::select! {
+                _ = shutdown.notified() => break StopKind::Shutdown,
+                Some(command) = control_receiver.recv() => {
+                    apply_control(
+                        cluster,
+                        assignment,
+                        config,
+                        &mut fetch_strategy,
+                        prefetch,
+                        command,
+                    )
+                    .await
+                }
+                _ = prefetch.released() => (),
+                stop = heartbeats_stopped(&mut heartbeats) => break stop?,
+                _ = time::delay_for(IDLE_INTERVAL) => (),
+            }
+            continue;
+        }
+
+        let mut requested: HashMap<BrokerId, Vec<KafkaPartition>> = HashMap::new();
+        let requests = fetch_requests.into_iter().map(|(broker, fetch_request)| {
+            requested.insert(broker, requested_partitions(&fetch_request));
+            let fetch_request = sessions.prepare(broker, fetch_request);
+            trace!(target: "rskafka::fetch", "REQUEST to {}\n{:#?}", broker, fetch_request);
+            let started = Instant::now();
+            cluster
+                .make_request(fetch_request, Some(broker))
+                .map(move |response| (broker, response, started.elapsed()))
+        });
+        let fetch_responses = tokio::select! {
+            _ = shutdown.notified() => break StopKind::Shutdown,
+            responses = future::join_all(requests) => responses,
+        };
+        for (broker, response, latency) in fetch_responses {
+            trace!(target: "rskafka::fetch", "RESPONSE from {}\n{:#?}", broker, response);
+            let response = response.and_then(|r| sessions.update(broker, &r).map(|_| r));
+            match response {
+                Ok(response) => {
+                    for error in fetch_strategy.update_fetched(broker, &response) {
+                        recovery.add(error, config);
+                    }
+                    if let Some(metrics) = metrics.as_ref() {
+                        for t in response.topics.iter() {
+                            for p in t.partitions.iter() {
+                                let position = fetch_strategy.position(&t.name, p.index);
+                                metrics.record_fetch(&t.name, p, position, latency);
+                            }
+                        }
+                    }
+                    prefetch.push(response.topics.into_iter().map(Into::into).collect());
+                }
+                // Request failed as a whole, all its partitions fail with the same error and are
+                // recovered (or reported) one by one
+                Err(e) => {
+                    sessions.reset(broker);
+                    let error_code = match request_error_code(&e) {
+                        Some(error_code) => error_code,
+                        None => {
+                            return Err(e).with_context(|| format!("fetch from broker {}", broker))
+                        }
+                    };
+                    warn!("Fetching from broker {} failed: {:#}", broker, e);
+                    for p in requested.remove(&broker).unwrap_or_default() {
+                        let error = PartitionError::new(p, error_code);
+                        fetch_strategy.fail(&error);
+                        recovery.add(error, config);
+                    }
                 }
             }
         }
+
+        if let Some(metrics) = metrics.as_ref() {
+            metrics.publish();
+        }
+    };
+
+    match stop {
+        // Member is no longer part of the group, commits would be rejected
+        StopKind::MemberLost => (),
+        // Committed by consumer after coordinator is found again
+        StopKind::CoordinatorLost => (),
+        _ => {
+            let group = assignment.group.as_ref();
+            commit_pending(cluster, group, commit_receiver, metrics.as_ref()).await
+        }
     }
+    Ok(stop)
 }
 
-enum StopKind {
-    Shutdown,
-    RebalanceInProgress,
+/// Completes when heartbeats stopped, never if there are none
+async fn heartbeats_stopped(heartbeats: &mut Option<Heartbeats>) -> Result<StopKind> {
+    match heartbeats {
+        Some(heartbeats) => heartbeats.stopped().await,
+        None => future::pending().await,
+    }
 }
 
-//todo: rename to sth like: Private/Custom
-//todo: get rid of cow and as ref for simplicity
-#[derive(Debug, Clone, WireFormatWrite, WireFormatParse)]
-struct GroupProtocolMetadata<'a, S: AsRef<str> + Clone> {
-    _placeholder_head: i16,
-    pub topics: Cow<'a, [S]>,
-    _placeholder_tail: i32,
+/// Partitions listed in fetch request
+fn requested_partitions(request: &FetchRequestV11) -> Vec<KafkaPartition> {
+    request
+        .topics
+        .iter()
+        .flat_map(|t| {
+            t.partitions.iter().map(move |p| KafkaPartition {
+                topic_name: t.name.to_string(),
+                partition_index: p.index,
+            })
+        })
+        .collect()
 }
 
-type GroupProtocolMetadataOwned = GroupProtocolMetadata<'static, String>;
+/// Applies command received from `AssignmentControl`. Failures are logged and do not stop fetching.
+async fn apply_control<S: FetchStrategy>(
+    cluster: &AsyncClusterClient,
+    assignment: &AssignmentContext,
+    config: &FetchConfig,
+    fetch_strategy: &mut S,
+    prefetch: &PrefetchWriter,
+    command: ControlCommand,
+) {
+    let applied = match command {
+        ControlCommand::Pause(ref p) => fetch_strategy.pause(&p.topic_name, p.partition_index),
+        ControlCommand::Resume(ref p) => fetch_strategy.resume(&p.topic_name, p.partition_index),
+        ControlCommand::Seek(ref p, position) => {
+            match resolve_position(cluster, assignment, config, p, position).await {
+                Ok(offset) => {
+                    debug!("Seeking {} to offset {}", p, offset);
+                    // Data fetched from the old position is not delivered
+                    prefetch.clear(p);
+                    fetch_strategy.seek(&p.topic_name, p.partition_index, offset)
+                }
+                Err(e) => {
+                    error!("Seek of {} failed: {:#}", p, e);
+                    return;
+                }
+            }
+        }
+    };
+
+    if !applied {
+        warn!("Ignoring {:?} - partition is not assigned", command);
+    }
+}
 
-impl<'a, S: AsRef<str> + Clone> GroupProtocolMetadata<'a, S> {
-    pub fn new(topics: Cow<'a, [S]>) -> Self {
-        GroupProtocolMetadata {
-            topics,
-            _placeholder_head: 0,
-            _placeholder_tail: 0,
+async fn resolve_position(
+    cluster: &AsyncClusterClient,
+    assignment: &AssignmentContext,
+    config: &FetchConfig,
+    partition: &KafkaPartition,
+    position: StartPosition,
+) -> Result<i64> {
+    let committed = match (position, assignment.group.as_ref()) {
+        (StartPosition::Committed, Some(group)) => {
+            let mut partitions = HashMap::new();
+            partitions.insert(
+                partition.topic_name.clone(),
+                vec![partition.partition_index],
+            );
+            committed_offsets(cluster, group, &partitions).await?
+        }
+        (StartPosition::Committed, None) => {
+            return Err(RsKafkaError::IncompleteConfig("group_id").into())
         }
+        _ => HashMap::new(),
+    };
+
+    let offsets = position::resolve_offsets(
+        cluster,
+        &[(partition.clone(), position)],
+        &committed,
+        config.isolation_level,
+    )
+    .await?;
+    offsets
+        .get(&partition.topic_name, partition.partition_index)
+        .with_context(|| format!("no offset found for {}", partition))
+}
+
+/// Commits offsets delivered to user since last auto commit. Offsets which failed to commit are
+/// retried with the next commit.
+async fn commit_delivered(
+    cluster: &AsyncClusterClient,
+    group: Option<&GroupMembership>,
+    delivered: &DeliveredOffsets,
+    metrics: Option<&ConsumerMetrics>,
+) -> Result<()> {
+    let offsets = delivered.take();
+    if offsets.is_empty() {
+        return Ok(());
+    }
+    let group = group.context("missing group membership")?;
+    if let Err(e) = commit_offsets(cluster, group, offsets.clone()).await {
+        delivered.restore(offsets);
+        return Err(e);
     }
+    if let Some(metrics) = metrics {
+        metrics.record_committed(&offsets);
+    }
+    Ok(())
 }
 
-//todo: rename to sth like: Private/Custom
-#[derive(Debug, Clone, WireFormatWrite, WireFormatParse)]
-struct AssignmentMetadata {
-    _placeholder_head: i16,
-    pub topics: Vec<(String, Vec<i32>)>,
-    _placeholder_tail: i32,
+/// Commits offsets received from commit sink so far
+async fn commit_pending(
+    cluster: &AsyncClusterClient,
+    group: Option<&GroupMembership>,
+    receiver: &mut mpsc::Receiver<KafkaOffset<'static>>,
+    metrics: Option<&ConsumerMetrics>,
+) {
+    let mut pending = Vec::new();
+    while let Ok(offset) = receiver.try_recv() {
+        pending.push(offset);
+    }
+
+    if pending.is_empty() {
+        return;
+    }
+
+    match group {
+        Some(group) => match commit_offsets(cluster, group, pending.clone()).await {
+            Ok(()) => {
+                if let Some(metrics) = metrics {
+                    metrics.record_committed(&pending);
+                }
+            }
+            Err(e) => error!("Offset commit failed: {:#}", e),
+        },
+        None => warn!(
+            "Ignoring {} offset commits - no consumer group configured",
+            pending.len()
+        ),
+    }
 }
 
-impl AssignmentMetadata {
-    pub fn new(topics: Vec<(String, Vec<i32>)>) -> Self {
-        AssignmentMetadata {
-            topics,
-            _placeholder_head: 0,
-            _placeholder_tail: 0,
+async fn commit_offsets(
+    cluster: &AsyncClusterClient,
+    group: &GroupMembership,
+    offsets: Vec<KafkaOffset<'_>>,
+) -> Result<()> {
+    // Last committed offset wins
+    let mut per_topic: HashMap<String, HashMap<i32, i64>> = HashMap::new();
+    for o in offsets {
+        per_topic
+            .entry(o.topic.into_owned())
+            .or_default()
+            .insert(o.partition, o.offset);
+    }
+
+    let request = OffsetCommitRequestV2 {
+        group_id: Cow::Borrowed(&group.group_id),
+        generation_id: group.generation_id,
+        member_id: Cow::Borrowed(&group.member_id),
+        retention_time_ms: -1,
+        topics: per_topic
+            .iter()
+            .map(|(topic, partitions)| OffsetCommitTopic {
+                name: Cow::Borrowed(topic),
+                partitions: partitions
+                    .iter()
+                    .map(|(partition, offset)| OffsetCommitPartition {
+                        partition_index: *partition,
+                        committed_offset: *offset,
+                        committed_metadata: NullableString::with_null(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let response: OffsetCommitResponseV2 = cluster
+        .make_request(request, Some(group.coordinator))
+        .await?;
+
+    for t in response.topics.iter() {
+        for p in t.partitions.iter() {
+            match p.error_code {
+                ErrorCode::None => trace!(
+                    "Committed offset {}[{}]: {}",
+                    t.name,
+                    p.partition_index,
+                    per_topic[&t.name][&p.partition_index]
+                ),
+                error => {
+                    return Err(RsKafkaError::from(error))
+                        .with_context(|| format!("commit {}[{}]", t.name, p.partition_index))
+                }
+            }
         }
     }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum StopKind {
+    Shutdown,
+    RebalanceInProgress,
+    /// Topics matching subscribed pattern changed, member has to rejoin with new subscription
+    SubscriptionChanged,
+    /// Member was removed from the group, its partitions may already be assigned to others
+    MemberLost,
+    /// Coordinator moved or became unreachable, member has to find it and rejoin
+    CoordinatorLost,
 }
 
 pub struct ConsumerKillswitch {
@@ -587,3 +1289,126 @@ impl ConsumerKillswitch {
         let _ = self.join_handle.await;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::record_batch_bytes;
+    use rskafka_proto::apis::fetch::{FetchResponsePartition, FetchResponseTopic};
+
+    #[test]
+    fn coordinator_errors_are_found_in_error_chain() {
+        let moved = Error::from(RsKafkaError::from(ErrorCode::NotCoordinator)).context("join");
+        assert!(is_coordinator_error(&moved));
+
+        let unreachable = Error::from(RsKafkaError::from(std::io::Error::from(
+            std::io::ErrorKind::ConnectionRefused,
+        )));
+        assert!(is_coordinator_error(&unreachable));
+
+        let fatal = Error::from(RsKafkaError::from(ErrorCode::GroupAuthorizationFailed));
+        assert!(!is_coordinator_error(&fatal));
+    }
+
+    #[test]
+    fn stale_membership_rejoins_group() {
+        let join_error = |code| join_retry(&Error::from(RsKafkaError::from(code)).context("join"));
+
+        assert_eq!(
+            join_error(ErrorCode::CoordinatorNotAvailable),
+            JoinRetry::FindCoordinator
+        );
+        assert_eq!(
+            join_error(ErrorCode::RebalanceInProgress),
+            JoinRetry::Rejoin
+        );
+        assert_eq!(
+            join_error(ErrorCode::UnknownMemberId),
+            JoinRetry::RejoinAsNewMember
+        );
+        assert_eq!(
+            join_error(ErrorCode::IllegalGeneration),
+            JoinRetry::RejoinAsNewMember
+        );
+        assert_eq!(
+            join_error(ErrorCode::GroupAuthorizationFailed),
+            JoinRetry::Fail
+        );
+    }
+
+    #[test]
+    fn group_retries_are_limited() {
+        let config = ConsumerConfig::builder()
+            .topics(vec!["t1".into()])
+            .group_id("group".into())
+            .group_retries(2)
+            .build()
+            .unwrap();
+        let mut retries = GroupRetries::new(&config);
+
+        assert_eq!(retries.next_delay(), Some(config.retry_backoff));
+        assert_eq!(retries.next_delay(), Some(config.retry_backoff * 2));
+        assert_eq!(retries.next_delay(), None);
+
+        retries.reset();
+        assert_eq!(retries.next_delay(), Some(config.retry_backoff));
+    }
+
+    /// Fetched data of partition t-0 with a single record at given offset
+    fn fetched(offset: i64) -> FetchResponseTopic {
+        FetchResponseTopic {
+            name: "t".into(),
+            partitions: vec![FetchResponsePartition {
+                index: 0,
+                error_code: ErrorCode::None,
+                high_watermark: 10,
+                last_stable_offset: 10,
+                aborted_transactions: Vec::new(),
+                record_set: record_batch_bytes(offset),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn seek_drops_data_prefetched_from_old_position() {
+        let partition = KafkaPartition {
+            topic_name: "t".into(),
+            partition_index: 0,
+        };
+        let mut assigned_partitions = HashMap::new();
+        assigned_partitions.insert("t".to_string(), vec![0]);
+        let assignment = AssignmentContext {
+            assigned_partitions,
+            topic_metadata: HashMap::new(),
+            broker_racks: HashMap::new(),
+            group: None,
+        };
+        let config = FetchConfig::default();
+        let mut fetch_strategy =
+            SimpleFetchStrategy::new(&assignment, Offsets::new(), config.clone());
+        let (writer, reader) = prefetch::prefetch_buffer(&config);
+        writer.push(vec![fetched(1), fetched(2)]);
+
+        let seek = ControlCommand::Seek(partition.clone(), StartPosition::Offset(5));
+        let cluster = AsyncClusterClient::without_brokers();
+        apply_control(
+            &cluster,
+            &assignment,
+            &config,
+            &mut fetch_strategy,
+            &writer,
+            seek,
+        )
+        .await;
+        assert_eq!(fetch_strategy.position("t", 0), Some(5));
+
+        writer.push(vec![fetched(5)]);
+        drop(writer);
+        let (commit_sender, _commit_receiver) = mpsc::channel(1);
+        let mut streams =
+            partition_stream::partition_streams(&[partition], reader, commit_sender, None);
+        let messages: Vec<_> = streams.pop().unwrap().collect().await;
+        let offsets: Vec<_> = messages.iter().map(|m| m.offset).collect();
+        assert_eq!(offsets, vec![5]);
+    }
+}
@@ -0,0 +1,207 @@
+use super::{
+    ack_tracker::AckTracker, assignment_stream::CommitSink, auto_commit::DeliveredOffsets,
+    prefetch::PrefetchReader,
+};
+use crate::{KafkaMessage, KafkaOffset, KafkaPartition};
+use futures::prelude::*;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    vec,
+};
+use tokio::sync::mpsc;
+
+/// Messages of a single assigned partition in offset order.
+///
+/// Stream ends when partition is revoked from consumer (assignment ends).
+#[derive(Debug)]
+pub struct PartitionStream {
+    partition: KafkaPartition,
+    prefetch: PrefetchReader,
+    buffered: vec::IntoIter<KafkaMessage<'static>>,
+    commit_sender: mpsc::Sender<KafkaOffset<'static>>,
+    delivered: Option<DeliveredOffsets>,
+}
+
+impl PartitionStream {
+    pub fn partition(&self) -> &KafkaPartition {
+        &self.partition
+    }
+
+    /// Sink of offsets to commit, may be moved to a task processing this partition
+    pub fn commit_sink(&self) -> CommitSink {
+        CommitSink::new(self.commit_sender.clone())
+    }
+
+    /// Commits offsets of messages acked out of order, see `AckTracker`
+    pub fn ack_tracker(&self) -> AckTracker {
+        AckTracker::new(self.commit_sink())
+    }
+}
+
+impl Stream for PartitionStream {
+    type Item = KafkaMessage<'static>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(message) = self.buffered.next() {
+                if let Some(delivered) = self.delivered.as_ref() {
+                    delivered.record(&message.topic, message.partition, message.offset);
+                }
+                return Poll::Ready(Some(message));
+            }
+            match futures::ready!(self.prefetch.poll_partition(&self.partition, cx)) {
+                Some(fetch) => {
+                    self.buffered = fetch.into_messages_owned().collect::<Vec<_>>().into_iter()
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl Drop for PartitionStream {
+    /// Partition nobody reads is not fetched anymore and its buffered data no longer counts
+    /// against prefetch budget of other partitions
+    fn drop(&mut self) {
+        self.prefetch.abandon(&self.partition);
+    }
+}
+
+/// Creates streams of given partitions, each taking its own partition data from prefetch buffer
+pub(super) fn partition_streams(
+    partitions: &[KafkaPartition],
+    prefetch: PrefetchReader,
+    commit_sender: mpsc::Sender<KafkaOffset<'static>>,
+    delivered: Option<DeliveredOffsets>,
+) -> Vec<PartitionStream> {
+    partitions
+        .iter()
+        .map(|partition| PartitionStream {
+            partition: partition.clone(),
+            prefetch: prefetch.clone(),
+            buffered: Vec::new().into_iter(),
+            commit_sender: commit_sender.clone(),
+            delivered: delivered.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        consumer::{prefetch::prefetch_buffer, FetchConfig},
+        test_utils::record_batch_bytes,
+    };
+    use rskafka_proto::{
+        apis::fetch::{FetchResponsePartition, FetchResponseTopic},
+        ErrorCode,
+    };
+
+    fn partition(topic: &str, index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: topic.into(),
+            partition_index: index,
+        }
+    }
+
+    /// Fetched data of partition with a single record at given offset
+    fn fetched(topic: &str, partition: i32, offset: i64) -> FetchResponseTopic {
+        FetchResponseTopic {
+            name: topic.into(),
+            partitions: vec![FetchResponsePartition {
+                index: partition,
+                error_code: ErrorCode::None,
+                high_watermark: 10,
+                last_stable_offset: 10,
+                aborted_transactions: Vec::new(),
+                record_set: record_batch_bytes(offset),
+            }],
+        }
+    }
+
+    fn offsets(messages: &[KafkaMessage]) -> Vec<i64> {
+        messages.iter().map(|m| m.offset).collect()
+    }
+
+    #[tokio::test]
+    async fn messages_are_split_by_partition() {
+        let (writer, reader) = prefetch_buffer(&Default::default());
+        writer.push(vec![fetched("t1", 0, 1), fetched("t1", 1, 5)]);
+        writer.push(vec![
+            fetched("t1", 0, 2),
+            fetched("t1", 1, 6),
+            fetched("t2", 0, 1),
+        ]);
+        drop(writer);
+
+        let (commit_sender, _commit_receiver) = mpsc::channel(1);
+        let mut streams = partition_streams(
+            &[partition("t1", 0), partition("t1", 1)],
+            reader,
+            commit_sender,
+            None,
+        );
+
+        let second: Vec<_> = streams.pop().unwrap().collect().await;
+        let first: Vec<_> = streams.pop().unwrap().collect().await;
+        assert_eq!(offsets(&first), vec![1, 2]);
+        assert_eq!(offsets(&second), vec![5, 6]);
+    }
+
+    #[tokio::test]
+    async fn dropped_partition_stream_does_not_block_others() {
+        let (writer, reader) = prefetch_buffer(&Default::default());
+        for offset in 1..=3 {
+            writer.push(vec![fetched("t1", 0, offset), fetched("t1", 1, offset)]);
+        }
+        drop(writer);
+
+        let (commit_sender, _commit_receiver) = mpsc::channel(1);
+        let mut streams = partition_streams(
+            &[partition("t1", 0), partition("t1", 1)],
+            reader,
+            commit_sender,
+            None,
+        );
+
+        let second = streams.pop().unwrap();
+        drop(streams);
+        let messages: Vec<_> = second.collect().await;
+        assert_eq!(offsets(&messages), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn dropped_partition_stream_releases_prefetch_budget() {
+        let batch_bytes = record_batch_bytes(0).len();
+        let config = FetchConfig {
+            prefetch_partition_bytes: 2 * batch_bytes,
+            prefetch_total_bytes: 2 * batch_bytes,
+            ..FetchConfig::default()
+        };
+        let (writer, reader) = prefetch_buffer(&config);
+        let (commit_sender, _commit_receiver) = mpsc::channel(1);
+        let mut streams = partition_streams(
+            &[partition("t1", 0), partition("t1", 1)],
+            reader,
+            commit_sender,
+            None,
+        );
+        writer.push(vec![fetched("t1", 0, 1), fetched("t1", 0, 2)]);
+        assert!(
+            !writer.has_room("t1", 1),
+            "total budget used by partition 0"
+        );
+
+        drop(streams.remove(0));
+        assert!(writer.has_room("t1", 1));
+        assert!(!writer.has_room("t1", 0));
+
+        // Late data of dropped partition is discarded
+        writer.push(vec![fetched("t1", 0, 3), fetched("t1", 1, 5)]);
+        drop(writer);
+        let messages: Vec<_> = streams.pop().unwrap().collect().await;
+        assert_eq!(offsets(&messages), vec![5]);
+    }
+}
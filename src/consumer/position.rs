@@ -0,0 +1,220 @@
+use super::fetch_strategy::Offsets;
+use crate::{client::AsyncClusterClient, message::KafkaPartition, Error};
+use rskafka_proto::apis::{
+    fetch::IsolationLevel,
+    list_offsets::{EARLIEST_TIMESTAMP, LATEST_TIMESTAMP},
+};
+use std::collections::HashMap;
+
+/// Position in partition from which consumption starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartPosition {
+    /// Earliest offset still available in partition
+    Earliest,
+    /// Offset of the next message produced to partition
+    Latest,
+    /// Exact offset of the first message to consume
+    Offset(i64),
+    /// Resume after offset committed by consumer group (earliest if nothing was committed)
+    Committed,
+    /// First message with timestamp (milliseconds since Unix epoch) greater or equal to the given
+    /// one. Falls back to latest if there is no such message.
+    Timestamp(i64),
+}
+
+/// Position fetching continues from when fetch offset of partition is out of range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OffsetReset {
+    Earliest,
+    Latest,
+    /// Partition is stopped and error is reported in consumer's assignment stream. Fetching
+    /// continues after partition is seeked.
+    Fail,
+}
+
+impl OffsetReset {
+    pub fn start_position(&self) -> Option<StartPosition> {
+        match self {
+            OffsetReset::Earliest => Some(StartPosition::Earliest),
+            OffsetReset::Latest => Some(StartPosition::Latest),
+            OffsetReset::Fail => None,
+        }
+    }
+}
+
+/// Resolves start positions to offsets of the first messages to fetch.
+/// `committed` holds offsets committed by consumer group.
+pub(crate) async fn resolve_offsets(
+    cluster: &AsyncClusterClient,
+    positions: &[(KafkaPartition, StartPosition)],
+    committed: &HashMap<KafkaPartition, i64>,
+    isolation_level: IsolationLevel,
+) -> Result<Offsets, Error> {
+    let (mut offsets, queries) = plan_lookups(positions, committed)?;
+    if !queries.is_empty() {
+        let found = cluster.list_offsets(&queries, isolation_level).await?;
+        let not_found = add_found(&mut offsets, found);
+        if !not_found.is_empty() {
+            let latest = cluster.list_offsets(&not_found, isolation_level).await?;
+            add_found(&mut offsets, latest);
+        }
+    }
+
+    Ok(offsets)
+}
+
+/// Positions of partitions assigned to group member. Partitions with offset committed by the group
+/// resume from it, others start from `start_position`.
+pub(crate) fn group_positions(
+    partitions: &[KafkaPartition],
+    committed: &HashMap<KafkaPartition, i64>,
+    start_position: StartPosition,
+) -> Vec<(KafkaPartition, StartPosition)> {
+    partitions
+        .iter()
+        .map(|p| {
+            if committed.get(p).is_some_and(|offset| *offset >= 0) {
+                (p.clone(), StartPosition::Committed)
+            } else {
+                (p.clone(), start_position)
+            }
+        })
+        .collect()
+}
+
+/// Splits positions into offsets known up front and timestamps to look up in partition leaders
+fn plan_lookups(
+    positions: &[(KafkaPartition, StartPosition)],
+    committed: &HashMap<KafkaPartition, i64>,
+) -> Result<(Offsets, Vec<(KafkaPartition, i64)>), Error> {
+    let mut offsets = Offsets::new();
+    let mut queries = Vec::new();
+    for (partition, position) in positions {
+        let timestamp = match position {
+            StartPosition::Offset(offset) => {
+                offsets.insert(
+                    partition.topic_name.clone(),
+                    partition.partition_index,
+                    *offset,
+                );
+                continue;
+            }
+            StartPosition::Committed => match committed.get(partition) {
+                Some(offset) if *offset >= 0 => {
+                    offsets.insert(
+                        partition.topic_name.clone(),
+                        partition.partition_index,
+                        offset + 1,
+                    );
+                    continue;
+                }
+                _ => EARLIEST_TIMESTAMP,
+            },
+            StartPosition::Earliest => EARLIEST_TIMESTAMP,
+            StartPosition::Latest => LATEST_TIMESTAMP,
+            StartPosition::Timestamp(timestamp) if *timestamp < 0 => {
+                return Err(Error::ValueError(
+                    format!("invalid timestamp {} for {}", timestamp, partition).into(),
+                ))
+            }
+            StartPosition::Timestamp(timestamp) => *timestamp,
+        };
+        queries.push((partition.clone(), timestamp));
+    }
+
+    Ok((offsets, queries))
+}
+
+/// Adds offsets found by lookup. Timestamp lookup yields no offset if all messages are older,
+/// such partitions are returned to be looked up again at latest offset.
+fn add_found(
+    offsets: &mut Offsets,
+    found: HashMap<KafkaPartition, (i64, i64)>,
+) -> Vec<(KafkaPartition, i64)> {
+    let mut not_found = Vec::new();
+    for (partition, (_, offset)) in found {
+        if offset < 0 {
+            not_found.push((partition, LATEST_TIMESTAMP));
+        } else {
+            offsets.insert(partition.topic_name, partition.partition_index, offset);
+        }
+    }
+    not_found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn partition(index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: index,
+        }
+    }
+
+    #[test]
+    fn timestamps_are_looked_up() {
+        let positions = vec![
+            (partition(0), StartPosition::Timestamp(1_589_000_000_000)),
+            (partition(1), StartPosition::Offset(5)),
+            (partition(2), StartPosition::Committed),
+            (partition(3), StartPosition::Committed),
+        ];
+        let mut committed = HashMap::new();
+        committed.insert(partition(2), 9);
+
+        let (offsets, queries) = plan_lookups(&positions, &committed).unwrap();
+        assert_eq!(
+            queries,
+            vec![
+                (partition(0), 1_589_000_000_000),
+                (partition(3), EARLIEST_TIMESTAMP)
+            ]
+        );
+        assert_eq!(offsets.get("t1", 0), None);
+        assert_eq!(offsets.get("t1", 1), Some(5));
+        assert_eq!(offsets.get("t1", 2), Some(10));
+
+        let positions = vec![(partition(0), StartPosition::Timestamp(-5))];
+        assert!(matches!(
+            plan_lookups(&positions, &committed),
+            Err(Error::ValueError(_))
+        ));
+    }
+
+    #[test]
+    fn timestamp_after_last_message_falls_back_to_latest() {
+        let mut offsets = Offsets::new();
+        let mut found = HashMap::new();
+        found.insert(partition(0), (1_589_000_000_000, 42));
+        found.insert(partition(1), (-1, -1));
+
+        let not_found = add_found(&mut offsets, found);
+        assert_eq!(offsets.get("t1", 0), Some(42));
+        assert_eq!(offsets.get("t1", 1), None);
+        assert_eq!(not_found, vec![(partition(1), LATEST_TIMESTAMP)]);
+    }
+
+    #[test]
+    fn group_positions_resume_committed_partitions() {
+        let mut committed = HashMap::new();
+        committed.insert(partition(0), 10);
+        // Offset fetch reports -1 for partitions without committed offset
+        committed.insert(partition(1), -1);
+
+        let positions = group_positions(
+            &[partition(0), partition(1), partition(2)],
+            &committed,
+            StartPosition::Timestamp(1_589_000_000_000),
+        );
+        assert_eq!(
+            positions,
+            vec![
+                (partition(0), StartPosition::Committed),
+                (partition(1), StartPosition::Timestamp(1_589_000_000_000)),
+                (partition(2), StartPosition::Timestamp(1_589_000_000_000)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use super::{fetch_data::FetchResponse, FetchConfig};
+use crate::KafkaPartition;
+use rskafka_proto::apis::fetch::{FetchResponsePartition, FetchResponseTopic, IsolationLevel};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use tokio::sync::Notify;
+
+/// Creates buffer of fetched data waiting to be consumed by assignment streams.
+///
+/// Consumer task pushes fetched data without waiting for it to be consumed and stops fetching
+/// partitions which are over their byte budget (see `PrefetchWriter::has_room`), so memory use
+/// is bounded and a slow partition does not stall the others. Bytes are released when data is
+/// taken by assignment streams, when stream of a partition is dropped, or when position of a
+/// partition moves.
+pub(super) fn prefetch_buffer(config: &FetchConfig) -> (PrefetchWriter, PrefetchReader) {
+    let shared = Arc::new(Shared {
+        partition_bytes: config.prefetch_partition_bytes,
+        total_bytes: config.prefetch_total_bytes,
+        isolation_level: config.isolation_level,
+        state: Mutex::new(State::default()),
+        released: Notify::new(),
+    });
+    (
+        PrefetchWriter {
+            shared: Arc::clone(&shared),
+        },
+        PrefetchReader { shared },
+    )
+}
+
+struct Shared {
+    partition_bytes: usize,
+    total_bytes: usize,
+    isolation_level: IsolationLevel,
+    state: Mutex<State>,
+    /// Notified when data is taken from buffer
+    released: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    partitions: HashMap<KafkaPartition, PartitionQueue>,
+    total_bytes: usize,
+    /// Partitions whose streams were dropped, their data is discarded
+    abandoned: HashSet<KafkaPartition>,
+    /// Set when writer is dropped, readers end once buffer is drained
+    closed: bool,
+    /// Task reading data of all partitions
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct PartitionQueue {
+    chunks: VecDeque<FetchResponsePartition>,
+    bytes: usize,
+    /// Task reading data of this partition only
+    waker: Option<Waker>,
+}
+
+impl State {
+    fn wake_all(&mut self) {
+        for queue in self.partitions.values_mut() {
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Consumer task side of prefetch buffer. Closes buffer when dropped.
+pub(super) struct PrefetchWriter {
+    shared: Arc<Shared>,
+}
+
+impl PrefetchWriter {
+    /// Whether partition may be fetched without exceeding its budget or the total budget.
+    /// Partitions nobody reads anymore have no room.
+    pub fn has_room(&self, topic: &str, partition: i32) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        let abandoned = state
+            .abandoned
+            .iter()
+            .any(|p| p.partition_index == partition && p.topic_name == topic);
+        if abandoned {
+            return false;
+        }
+        let partition_bytes = state
+            .partitions
+            .iter()
+            .find(|(p, _)| p.partition_index == partition && p.topic_name == topic)
+            .map_or(0, |(_, queue)| queue.bytes);
+        partition_bytes < self.shared.partition_bytes && state.total_bytes < self.shared.total_bytes
+    }
+
+    /// Appends fetched partition data. Partitions without records are skipped.
+    pub fn push(&self, topics: Vec<FetchResponseTopic>) {
+        let mut state = self.shared.state.lock().unwrap();
+        for t in topics {
+            for p in t.partitions {
+                if p.record_set.is_empty() {
+                    continue;
+                }
+                let partition = KafkaPartition {
+                    topic_name: t.name.clone(),
+                    partition_index: p.index,
+                };
+                if state.abandoned.contains(&partition) {
+                    continue;
+                }
+                let bytes = p.record_set.len();
+                let queue = state.partitions.entry(partition).or_default();
+                queue.bytes += bytes;
+                queue.chunks.push_back(p);
+                if let Some(waker) = queue.waker.take() {
+                    waker.wake();
+                }
+                state.total_bytes += bytes;
+            }
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Drops buffered data of partition whose position moved (seek or reset after offset out of
+    /// range), so that its stream continues from the new position
+    pub fn clear(&self, partition: &KafkaPartition) {
+        let mut state = self.shared.state.lock().unwrap();
+        let released = match state.partitions.get_mut(partition) {
+            Some(queue) => {
+                queue.chunks.clear();
+                std::mem::take(&mut queue.bytes)
+            }
+            None => return,
+        };
+        state.total_bytes -= released;
+        self.shared.released.notify();
+    }
+
+    /// Completes when some data is taken from buffer
+    pub async fn released(&self) {
+        self.shared.released.notified().await
+    }
+}
+
+impl Drop for PrefetchWriter {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        state.wake_all();
+    }
+}
+
+/// Assignment side of prefetch buffer
+#[derive(Clone)]
+pub(super) struct PrefetchReader {
+    shared: Arc<Shared>,
+}
+
+impl PrefetchReader {
+    /// Takes all buffered data. Returns `None` once buffer is closed and drained.
+    pub fn poll_fetch(&self, cx: &mut Context<'_>) -> Poll<Option<FetchResponse>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let mut fetch = FetchResponse::new(self.shared.isolation_level);
+        let mut released = 0;
+        for (partition, queue) in state.partitions.iter_mut() {
+            if queue.chunks.is_empty() {
+                continue;
+            }
+            released += queue.bytes;
+            queue.bytes = 0;
+            fetch.topics.push(FetchResponseTopic {
+                name: partition.topic_name.clone(),
+                partitions: queue.chunks.drain(..).collect(),
+            });
+        }
+
+        if fetch.topics.is_empty() {
+            if state.closed {
+                return Poll::Ready(None);
+            }
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.total_bytes -= released;
+        self.shared.released.notify();
+        Poll::Ready(Some(fetch))
+    }
+
+    /// Takes the oldest buffered data of a single partition. Returns `None` once buffer is closed
+    /// and partition data is drained.
+    pub fn poll_partition(
+        &self,
+        partition: &KafkaPartition,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<FetchResponse>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let closed = state.closed;
+        let queue = state.partitions.entry(partition.clone()).or_default();
+        let chunk = match queue.chunks.pop_front() {
+            Some(chunk) => chunk,
+            None if closed => return Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        };
+        let bytes = chunk.record_set.len();
+        queue.bytes -= bytes;
+        state.total_bytes -= bytes;
+        self.shared.released.notify();
+
+        let mut fetch = FetchResponse::new(self.shared.isolation_level);
+        fetch.topics.push(FetchResponseTopic {
+            name: partition.topic_name.clone(),
+            partitions: vec![chunk],
+        });
+        Poll::Ready(Some(fetch))
+    }
+
+    /// Releases buffered data of partition whose stream was dropped. Data fetched for the
+    /// partition later is discarded.
+    pub fn abandon(&self, partition: &KafkaPartition) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(queue) = state.partitions.remove(partition) {
+            state.total_bytes -= queue.bytes;
+        }
+        state.abandoned.insert(partition.clone());
+        self.shared.released.notify();
+    }
+}
+
+impl std::fmt::Debug for PrefetchReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        f.debug_struct("PrefetchReader")
+            .field("total_bytes", &state.total_bytes)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{executor::block_on, future::poll_fn};
+    use rskafka_proto::ErrorCode;
+
+    fn config(partition_bytes: usize, total_bytes: usize) -> FetchConfig {
+        FetchConfig {
+            prefetch_partition_bytes: partition_bytes,
+            prefetch_total_bytes: total_bytes,
+            ..FetchConfig::default()
+        }
+    }
+
+    fn topic(name: &str, partition: i32, bytes: usize) -> FetchResponseTopic {
+        FetchResponseTopic {
+            name: name.into(),
+            partitions: vec![FetchResponsePartition {
+                index: partition,
+                error_code: ErrorCode::None,
+                high_watermark: 0,
+                last_stable_offset: 0,
+                aborted_transactions: Vec::new(),
+                record_set: vec![0; bytes],
+            }],
+        }
+    }
+
+    fn partition(topic: &str, index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: topic.into(),
+            partition_index: index,
+        }
+    }
+
+    #[test]
+    fn partitions_over_budget_have_no_room() {
+        let (writer, reader) = prefetch_buffer(&config(100, 250));
+        writer.push(vec![topic("t1", 0, 100), topic("t1", 1, 50)]);
+        assert!(!writer.has_room("t1", 0));
+        assert!(writer.has_room("t1", 1));
+
+        writer.push(vec![topic("t1", 1, 100)]);
+        assert!(!writer.has_room("t1", 2), "total budget exceeded");
+
+        let fetch = block_on(poll_fn(|cx| reader.poll_partition(&partition("t1", 0), cx)));
+        assert_eq!(fetch.unwrap().topics[0].partitions[0].index, 0);
+        assert!(writer.has_room("t1", 0));
+        assert!(!writer.has_room("t1", 1));
+    }
+
+    #[test]
+    fn cleared_partition_releases_its_bytes() {
+        let (writer, reader) = prefetch_buffer(&config(100, 150));
+        writer.push(vec![topic("t1", 0, 100), topic("t1", 1, 50)]);
+        assert!(!writer.has_room("t1", 2));
+
+        writer.clear(&partition("t1", 0));
+        assert!(writer.has_room("t1", 0));
+        assert!(writer.has_room("t1", 2));
+        drop(writer);
+        assert!(block_on(poll_fn(|cx| reader.poll_partition(&partition("t1", 0), cx))).is_none());
+    }
+
+    #[test]
+    fn readers_drain_buffer_after_close() {
+        let (writer, reader) = prefetch_buffer(&config(100, 1000));
+        writer.push(vec![topic("t1", 0, 10), topic("t1", 1, 10)]);
+        writer.push(vec![topic("t1", 0, 10)]);
+        drop(writer);
+
+        let fetch = block_on(poll_fn(|cx| reader.poll_fetch(cx))).unwrap();
+        let mut chunks: Vec<_> = fetch.partitions().map(|(_, p)| p.index).collect();
+        chunks.sort();
+        assert_eq!(chunks, vec![0, 0, 1]);
+        assert!(block_on(poll_fn(|cx| reader.poll_fetch(cx))).is_none());
+        assert!(block_on(poll_fn(|cx| reader.poll_partition(&partition("t1", 0), cx))).is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::KafkaPartition;
+use futures::future::{self, BoxFuture, FutureExt};
+
+/// Notified about changes of partitions assigned to group member.
+///
+/// Consumer awaits each callback before it continues, so callbacks can flush state or commit
+/// offsets (offsets sent to the old assignment's `CommitSink` in `on_partitions_revoked` are still
+/// committed). Consumer follows eager rebalance protocol: all partitions are revoked before
+/// rejoining the group and the complete new assignment is reported afterwards.
+pub trait RebalanceListener: Send + Sync {
+    /// Called when group starts rebalancing, before partitions are handed over to other members
+    fn on_partitions_revoked<'a>(&'a self, _partitions: &'a [KafkaPartition]) -> BoxFuture<'a, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// Called after joining the group, before fetching of assigned partitions starts
+    fn on_partitions_assigned<'a>(
+        &'a self,
+        _partitions: &'a [KafkaPartition],
+    ) -> BoxFuture<'a, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// Called when member was removed from the group (e.g. session timed out) and partitions may
+    /// be already owned by other members. Offsets can no longer be committed.
+    ///
+    /// Calls `on_partitions_revoked` by default.
+    fn on_partitions_lost<'a>(&'a self, partitions: &'a [KafkaPartition]) -> BoxFuture<'a, ()> {
+        self.on_partitions_revoked(partitions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingListener {
+        revoked: Mutex<Vec<KafkaPartition>>,
+    }
+
+    impl RebalanceListener for RecordingListener {
+        fn on_partitions_revoked<'a>(
+            &'a self,
+            partitions: &'a [KafkaPartition],
+        ) -> BoxFuture<'a, ()> {
+            async move {
+                self.revoked.lock().unwrap().extend_from_slice(partitions);
+            }
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn lost_partitions_are_revoked_by_default() {
+        let listener = RecordingListener::default();
+        let partitions = vec![KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: 0,
+        }];
+
+        listener.on_partitions_assigned(&partitions).await;
+        listener.on_partitions_lost(&partitions).await;
+
+        assert_eq!(*listener.revoked.lock().unwrap(), partitions);
+    }
+}
@@ -0,0 +1,207 @@
+use super::{
+    error::{PartitionError, PartitionErrorKind},
+    fetch_strategy::{AssignmentContext, FetchStrategy},
+    get_metadata,
+    prefetch::PrefetchWriter,
+    resolve_position, Assignment, ConsumerError, FetchConfig,
+};
+use crate::{backoff::Backoff, client::AsyncClusterClient, KafkaPartition};
+use log::{error, info, warn};
+use rskafka_proto::{apis::metadata::MetadataResponseV2, BrokerId, ErrorCode};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Partitions excluded from fetching by errors, waiting to be made fetchable again.
+///
+/// Recovery is retried with growing delay while some partitions keep failing. Errors partitions
+/// can't recover from are reported in consumer's assignment stream instead.
+pub(super) struct PartitionRecovery {
+    failed: Vec<PartitionError>,
+    due: Instant,
+    backoff: Backoff,
+    errors: Option<mpsc::Sender<Result<Assignment, ConsumerError>>>,
+}
+
+impl PartitionRecovery {
+    pub fn new(
+        errors: Option<mpsc::Sender<Result<Assignment, ConsumerError>>>,
+        backoff: Backoff,
+    ) -> Self {
+        PartitionRecovery {
+            failed: Vec::new(),
+            due: Instant::now(),
+            backoff,
+            errors,
+        }
+    }
+
+    /// Schedules recovery of partition excluded from fetching by `FetchStrategy::fail`
+    pub fn add(&mut self, error: PartitionError, config: &FetchConfig) {
+        let recoverable = match error.kind() {
+            PartitionErrorKind::OffsetOutOfRange => config.offset_reset.start_position().is_some(),
+            kind => kind.is_retriable(),
+        };
+        if !recoverable {
+            self.report(error);
+            return;
+        }
+
+        warn!("{}, retrying", error);
+        if self.failed.is_empty() {
+            self.due = Instant::now() + self.backoff.next_delay();
+        }
+        if !self.failed.contains(&error) {
+            self.failed.push(error);
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        !self.failed.is_empty() && Instant::now() >= self.due
+    }
+
+    /// Makes failed partitions fetchable again - leaders are looked up in cluster metadata and
+    /// out of range positions are reset. Partitions which still fail are retried later.
+    pub async fn recover<S: FetchStrategy>(
+        &mut self,
+        cluster: &AsyncClusterClient,
+        assignment: &AssignmentContext,
+        config: &FetchConfig,
+        fetch_strategy: &mut S,
+        prefetch: &PrefetchWriter,
+    ) {
+        let mut topics: Vec<&str> = self
+            .failed
+            .iter()
+            .filter(|e| e.kind() == PartitionErrorKind::LeaderChanged)
+            .map(|e| e.partition.topic_name.as_str())
+            .collect();
+        topics.sort();
+        topics.dedup();
+        let metadata = if topics.is_empty() {
+            None
+        } else {
+            match get_metadata(cluster, topics).await {
+                Ok(metadata) => Some(metadata),
+                Err(e) => {
+                    warn!("Looking up leaders of failed partitions failed: {:#}", e);
+                    None
+                }
+            }
+        };
+
+        let mut still_failed = Vec::new();
+        for error in std::mem::take(&mut self.failed) {
+            let p = &error.partition;
+            let recovered = match error.kind() {
+                PartitionErrorKind::LeaderChanged => {
+                    match metadata.as_ref().and_then(|m| leader(m, p)) {
+                        Some(leader) => {
+                            info!("Fetching {} from leader {}", p, leader);
+                            fetch_strategy.set_leader(&p.topic_name, p.partition_index, leader);
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                PartitionErrorKind::OffsetOutOfRange => {
+                    // Partitions without reset policy are never added
+                    let position = config.offset_reset.start_position().unwrap();
+                    match resolve_position(cluster, assignment, config, p, position).await {
+                        Ok(offset) => {
+                            info!("Resetting {} to offset {}", p, offset);
+                            prefetch.clear(p);
+                            fetch_strategy.seek(&p.topic_name, p.partition_index, offset);
+                            true
+                        }
+                        Err(e) => {
+                            warn!("Resetting position of {} failed: {:#}", p, e);
+                            false
+                        }
+                    }
+                }
+                PartitionErrorKind::Retriable | PartitionErrorKind::Fatal => {
+                    fetch_strategy.retry(&p.topic_name, p.partition_index);
+                    true
+                }
+            };
+            if !recovered {
+                still_failed.push(error);
+            }
+        }
+
+        self.failed = still_failed;
+        if self.failed.is_empty() {
+            self.backoff.reset();
+        } else {
+            self.due = Instant::now() + self.backoff.next_delay();
+        }
+    }
+
+    /// Passes error to the assignment stream. Error is only logged if user does not keep up with
+    /// reading the stream, so that consumer task never blocks on it.
+    fn report(&mut self, error: PartitionError) {
+        error!("{}, partition stopped", error);
+        if let Some(errors) = self.errors.as_mut() {
+            let _ = errors.try_send(Err(error.into()));
+        }
+    }
+}
+
+/// Leader of partition if metadata holds one
+fn leader(metadata: &MetadataResponseV2, partition: &KafkaPartition) -> Option<BrokerId> {
+    metadata
+        .topics
+        .iter()
+        .find(|t| t.name == partition.topic_name && t.error == ErrorCode::None)?
+        .partitions
+        .iter()
+        .find(|p| p.partition_index == partition.partition_index && p.error == ErrorCode::None)
+        .map(|p| p.leader)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::OffsetReset;
+    use std::time::Duration;
+
+    fn error(error_code: ErrorCode) -> PartitionError {
+        let partition = KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: 0,
+        };
+        PartitionError::new(partition, error_code)
+    }
+
+    #[test]
+    fn unrecoverable_errors_are_reported() {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        let mut recovery = PartitionRecovery::new(Some(sender), backoff);
+        let config = FetchConfig {
+            offset_reset: OffsetReset::Fail,
+            ..FetchConfig::default()
+        };
+
+        recovery.add(error(ErrorCode::NotLeaderForPartition), &config);
+        recovery.add(error(ErrorCode::NotLeaderForPartition), &config);
+        recovery.add(error(ErrorCode::TopicAuthorizationFailed), &config);
+        recovery.add(error(ErrorCode::OffsetOutOfRange), &config);
+        assert_eq!(
+            recovery.failed,
+            vec![error(ErrorCode::NotLeaderForPartition)]
+        );
+
+        let mut reported = Vec::new();
+        while let Ok(Err(e)) = receiver.try_recv() {
+            reported.push(e.partition_error().unwrap().error_code);
+        }
+        assert_eq!(
+            reported,
+            vec![
+                ErrorCode::TopicAuthorizationFailed,
+                ErrorCode::OffsetOutOfRange
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use super::{
+    broker_racks, committed_offsets, fetch_loop,
+    fetch_strategy::{AssignmentContext, GroupMembership, Offsets},
+    find_coordinator, get_metadata, position, Assignment, ConsumerError, ConsumerKillswitch,
+    FetchConfig, StartPosition,
+};
+use crate::{
+    backoff::Backoff, client::AsyncClusterClient, message::KafkaPartition, Error as RsKafkaError,
+};
+use anyhow::{bail, Context as AnyhowContext, Error, Result};
+use futures::prelude::*;
+use log::debug;
+use rskafka_proto::{apis::metadata::TopicMetadata, ErrorCode};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, Notify};
+
+pub struct StandaloneConsumerConfig {
+    pub client_id: String,
+    /// Partitions to consume along with positions consumption starts from
+    pub partitions: Vec<(KafkaPartition, StartPosition)>,
+    /// Group offsets are committed to. Commits are ignored if not set.
+    pub group_id: Option<String>,
+    pub fetch: FetchConfig,
+    /// Delay before retrying recovery of failed partitions, doubled with each consecutive
+    /// failure up to `retry_backoff_max`
+    pub retry_backoff: Duration,
+    pub retry_backoff_max: Duration,
+}
+
+/// Consumer reading explicitly listed partitions without joining a consumer group.
+///
+/// Produces exactly one `Assignment` covering all configured partitions.
+pub struct StandaloneConsumer {
+    receiver: mpsc::Receiver<Result<Assignment, ConsumerError>>,
+    killswitch: ConsumerKillswitch,
+}
+
+impl StandaloneConsumer {
+    pub async fn bootstrap<S: AsRef<str>>(
+        servers: S,
+        config: StandaloneConsumerConfig,
+    ) -> Result<Self, Error> {
+        let cluster = AsyncClusterClient::bootstrap(servers, config.client_id.clone()).await?;
+        Ok(Self::with_cluster_client(&Arc::new(cluster), config))
+    }
+
+    pub fn with_cluster_client(
+        client: &Arc<AsyncClusterClient>,
+        config: StandaloneConsumerConfig,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(1);
+        let shutdown = Arc::new(Notify::new());
+
+        let internals = StandaloneInternals {
+            cluster: Arc::clone(client),
+            config,
+        };
+
+        let join_handle = tokio::spawn({
+            let shutdown = Arc::clone(&shutdown);
+            internals.consumer_loop(sender, shutdown)
+        });
+
+        StandaloneConsumer {
+            receiver,
+            killswitch: ConsumerKillswitch {
+                shutdown,
+                join_handle,
+            },
+        }
+    }
+
+    pub fn split(
+        self,
+    ) -> (
+        ConsumerKillswitch,
+        impl Stream<Item = Result<Assignment, ConsumerError>>,
+    ) {
+        (self.killswitch, self.receiver)
+    }
+}
+
+struct StandaloneInternals {
+    cluster: Arc<AsyncClusterClient>,
+    config: StandaloneConsumerConfig,
+}
+
+impl StandaloneInternals {
+    async fn consumer_loop(
+        self,
+        mut assignment_sender: mpsc::Sender<Result<Assignment, ConsumerError>>,
+        shutdown: Arc<Notify>,
+    ) {
+        let result = self
+            .consumer_loop_inner(assignment_sender.clone(), shutdown)
+            .await;
+        if let Err(e) = result {
+            let _ = assignment_sender.send(Err(ConsumerError(e))).await;
+        }
+    }
+
+    async fn consumer_loop_inner(
+        self,
+        mut sender: mpsc::Sender<Result<Assignment, ConsumerError>>,
+        shutdown: Arc<Notify>,
+    ) -> Result<()> {
+        let context = self.build_context().await?;
+        let offsets = self.resolve_offsets(&context).await?;
+
+        let (assignment, mut feed) =
+            Assignment::new(context.partitions(), false, &self.config.fetch);
+        feed.errors = Some(sender.clone());
+
+        if sender.send(Ok(assignment)).await.is_err() {
+            debug!("shutting down - Assignment stream receiver deallocated");
+            return Ok(());
+        }
+
+        fetch_loop(
+            &self.cluster,
+            &context,
+            offsets,
+            &self.config.fetch,
+            Backoff::new(self.config.retry_backoff, self.config.retry_backoff_max),
+            None,
+            &shutdown,
+            &mut feed,
+        )
+        .await
+        .context("fetch loop failed")?;
+
+        debug!("Standalone consumer stopped");
+        Ok(())
+    }
+
+    async fn build_context(&self) -> Result<AssignmentContext> {
+        let mut assigned_partitions: HashMap<String, Vec<i32>> = HashMap::new();
+        for (p, _) in self.config.partitions.iter() {
+            assigned_partitions
+                .entry(p.topic_name.clone())
+                .or_default()
+                .push(p.partition_index);
+        }
+
+        let metadata = get_metadata(
+            &self.cluster,
+            assigned_partitions.keys().map(String::as_str),
+        )
+        .await?;
+        let broker_racks = broker_racks(&metadata);
+        let topic_metadata: HashMap<String, TopicMetadata> = metadata
+            .topics
+            .into_iter()
+            .map(|t| (t.name.clone(), t))
+            .collect();
+
+        for (topic, partitions) in assigned_partitions.iter() {
+            let t = topic_metadata
+                .get(topic)
+                .with_context(|| format!("missing metadata for {}", topic))?;
+            if t.error != ErrorCode::None {
+                return Err(RsKafkaError::from(t.error)).context(format!("topic {}", topic));
+            }
+            for p in partitions {
+                if !t.partitions.iter().any(|m| m.partition_index == *p) {
+                    bail!("partition {}[{}] does not exist", topic, p);
+                }
+            }
+        }
+
+        let group = match self.config.group_id.as_ref() {
+            Some(group_id) => {
+                let coordinator = find_coordinator(&self.cluster, group_id).await?;
+                Some(GroupMembership::standalone(group_id.clone(), coordinator))
+            }
+            None => None,
+        };
+
+        Ok(AssignmentContext {
+            assigned_partitions,
+            topic_metadata,
+            broker_racks,
+            group,
+        })
+    }
+
+    async fn resolve_offsets(&self, context: &AssignmentContext) -> Result<Offsets> {
+        let uses_committed = self
+            .config
+            .partitions
+            .iter()
+            .any(|(_, position)| *position == StartPosition::Committed);
+
+        let committed = if uses_committed {
+            let group = context
+                .group
+                .as_ref()
+                .ok_or(RsKafkaError::IncompleteConfig("group_id"))?;
+            committed_offsets(&self.cluster, group, &context.assigned_partitions).await?
+        } else {
+            HashMap::new()
+        };
+
+        position::resolve_offsets(
+            &self.cluster,
+            &self.config.partitions,
+            &committed,
+            self.config.fetch.isolation_level,
+        )
+        .await
+        .map_err(Into::into)
+    }
+}
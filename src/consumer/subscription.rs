@@ -0,0 +1,131 @@
+use crate::client::AsyncClusterClient;
+use log::{info, warn};
+use regex::Regex;
+use rskafka_proto::{
+    apis::metadata::{MetadataRequestV2, MetadataResponseV2},
+    ErrorCode,
+};
+use rskafka_wire_format::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Topics subscribed with a pattern, resolved against metadata of all cluster topics
+#[derive(Debug)]
+pub(super) struct PatternSubscription {
+    pattern: Regex,
+    refresh_interval: Duration,
+    due: Instant,
+    topics: Vec<String>,
+}
+
+impl PatternSubscription {
+    pub fn new(pattern: Regex, refresh_interval: Duration) -> Self {
+        PatternSubscription {
+            pattern,
+            refresh_interval,
+            due: Instant::now(),
+            topics: Vec::new(),
+        }
+    }
+
+    /// Topics matched by last refresh, sorted
+    pub fn topics(&self) -> &[String] {
+        &self.topics
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.due
+    }
+
+    /// Resolves pattern against current cluster metadata. Returns true if matching topics changed.
+    /// Failed metadata request is logged and retried at the next refresh, keeping current topics.
+    pub async fn refresh(&mut self, cluster: &AsyncClusterClient) -> bool {
+        self.due = Instant::now() + self.refresh_interval;
+        let request = MetadataRequestV2 {
+            topics: NullableArray::with_null(),
+        };
+        let metadata: MetadataResponseV2 = match cluster.make_request(request, None).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!(
+                    "Refreshing topics matching {} failed: {}, retrying in {:?}",
+                    self.pattern, e, self.refresh_interval
+                );
+                return false;
+            }
+        };
+
+        let topics = matching_topics(&self.pattern, &metadata);
+        if topics == self.topics {
+            return false;
+        }
+        info!(
+            "Topics matching {} changed: {:?} -> {:?}",
+            self.pattern, self.topics, topics
+        );
+        self.topics = topics;
+        true
+    }
+}
+
+/// Names of non-internal topics matching the pattern, sorted. Topics with metadata errors (e.g.
+/// just created ones without leaders yet) are skipped until next refresh.
+fn matching_topics(pattern: &Regex, metadata: &MetadataResponseV2) -> Vec<String> {
+    let mut topics: Vec<String> = metadata
+        .topics
+        .iter()
+        .filter(|t| !t.is_internal && t.error == ErrorCode::None && pattern.is_match(&t.name))
+        .map(|t| t.name.clone())
+        .collect();
+    topics.sort();
+    topics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::apis::metadata::TopicMetadata;
+
+    #[tokio::test]
+    async fn failed_refresh_is_retried_later() {
+        let cluster = AsyncClusterClient::without_brokers();
+        let pattern = Regex::new("^(?:t.*)$").unwrap();
+        let mut subscription = PatternSubscription::new(pattern, Duration::from_secs(60));
+        subscription.topics = vec!["t1".into()];
+        assert!(subscription.is_due());
+
+        assert!(!subscription.refresh(&cluster).await);
+        assert_eq!(subscription.topics(), ["t1".to_string()]);
+        assert!(!subscription.is_due());
+    }
+
+    fn topic(name: &str, is_internal: bool, error: ErrorCode) -> TopicMetadata {
+        TopicMetadata {
+            error,
+            name: name.into(),
+            is_internal,
+            partitions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn matching_topics_skips_internal_and_unavailable() {
+        let metadata = MetadataResponseV2 {
+            brokers: Vec::new(),
+            cluster_id: NullableString(None),
+            controller_id: 0,
+            topics: vec![
+                topic("cdc.db.users", false, ErrorCode::None),
+                topic("cdc.db.orders", false, ErrorCode::None),
+                topic("cdc.db.new", false, ErrorCode::LeaderNotAvailable),
+                topic("audit.cdc.db.users", false, ErrorCode::None),
+                topic("__consumer_offsets", true, ErrorCode::None),
+            ],
+        };
+        let pattern = Regex::new(r"^(?:cdc\.db\..*|__.*)$").unwrap();
+
+        assert_eq!(
+            matching_topics(&pattern, &metadata),
+            vec!["cdc.db.orders".to_string(), "cdc.db.users".to_string()]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    deserializer::Deserializer, Error, KafkaHeader, KafkaMessage, KafkaOffset, KafkaPartition,
+    TimestampType,
+};
+use futures::{future, prelude::*};
+use log::warn;
+use tokio::sync::mpsc;
+
+/// Message with decoded key and value
+#[derive(Debug)]
+pub struct TypedMessage<K, V> {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: i64,
+    pub timestamp_type: TimestampType,
+    pub leader_epoch: i32,
+    pub key: K,
+    pub value: V,
+    pub headers: Vec<KafkaHeader<'static>>,
+}
+
+impl<K, V> TypedMessage<K, V> {
+    pub fn partition(&self) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: self.topic.clone(),
+            partition_index: self.partition,
+        }
+    }
+
+    /// Offset to send to `CommitSink` once message is processed
+    pub fn to_offset(&self) -> KafkaOffset<'static> {
+        KafkaOffset {
+            topic: self.topic.clone().into(),
+            partition: self.partition,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Message which could not be decoded
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub message: KafkaMessage<'static>,
+    pub error: Error,
+}
+
+/// What typed stream does with messages which cannot be decoded
+#[derive(Debug, Clone, Default)]
+pub enum DeserializeErrorPolicy {
+    /// Log and drop the message
+    Skip,
+    /// Yield the error and end the stream
+    #[default]
+    Fail,
+    /// Pass undecoded message to the channel and continue
+    DeadLetter(mpsc::UnboundedSender<DeadLetter>),
+}
+
+/// Decodes messages of the stream. See `Assignment::into_typed_stream`.
+pub fn typed_stream<S, KD, VD>(
+    messages: S,
+    key_deserializer: KD,
+    value_deserializer: VD,
+    policy: DeserializeErrorPolicy,
+) -> impl Stream<Item = Result<TypedMessage<KD::Item, VD::Item>, Error>>
+where
+    S: Stream<Item = KafkaMessage<'static>>,
+    KD: Deserializer,
+    VD: Deserializer,
+{
+    messages
+        .scan(false, move |failed, message| {
+            if *failed {
+                return future::ready(None);
+            }
+            let decoded = match decode(&key_deserializer, &value_deserializer, &message) {
+                Ok(typed) => Some(Ok(typed)),
+                Err(error) => match &policy {
+                    DeserializeErrorPolicy::Skip => {
+                        warn!(
+                            "Skipping message {}[{}]@{}: {}",
+                            message.topic, message.partition, message.offset, error
+                        );
+                        None
+                    }
+                    DeserializeErrorPolicy::Fail => {
+                        *failed = true;
+                        Some(Err(error))
+                    }
+                    DeserializeErrorPolicy::DeadLetter(sender) => {
+                        if let Err(mpsc::error::SendError(letter)) =
+                            sender.send(DeadLetter { message, error })
+                        {
+                            warn!(
+                                "Dead letter channel closed, dropping message {}[{}]@{}: {}",
+                                letter.message.topic,
+                                letter.message.partition,
+                                letter.message.offset,
+                                letter.error
+                            );
+                        }
+                        None
+                    }
+                },
+            };
+            future::ready(Some(decoded))
+        })
+        .filter_map(future::ready)
+}
+
+fn decode<KD: Deserializer, VD: Deserializer>(
+    key_deserializer: &KD,
+    value_deserializer: &VD,
+    message: &KafkaMessage,
+) -> Result<TypedMessage<KD::Item, VD::Item>, Error> {
+    Ok(TypedMessage {
+        topic: message.topic.to_string(),
+        partition: message.partition,
+        offset: message.offset,
+        timestamp: message.timestamp,
+        timestamp_type: message.timestamp_type,
+        leader_epoch: message.leader_epoch,
+        key: key_deserializer.deserialize(message.key.as_deref())?,
+        value: value_deserializer.deserialize(message.value.as_deref())?,
+        headers: message.headers.iter().map(KafkaHeader::detached).collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::deserializer::{BigEndianDeserializer, Nullable, StringDeserializer};
+    use futures::{executor::block_on, stream};
+
+    fn message(offset: i64, value: &[u8]) -> KafkaMessage<'static> {
+        KafkaMessage {
+            topic: "t1".into(),
+            partition: 0,
+            offset,
+            timestamp: 0,
+            timestamp_type: TimestampType::CreateTime,
+            leader_epoch: 0,
+            key: None,
+            value: Some(value.to_vec().into()),
+            headers: Vec::new().into(),
+        }
+    }
+
+    fn messages() -> impl Stream<Item = KafkaMessage<'static>> {
+        stream::iter(vec![
+            message(0, b"ok"),
+            message(1, &[0xff]),
+            message(2, b"ok"),
+        ])
+    }
+
+    fn offsets<K, V>(results: &[Result<TypedMessage<K, V>, Error>]) -> Vec<Option<i64>> {
+        results
+            .iter()
+            .map(|r| r.as_ref().ok().map(|m| m.offset))
+            .collect()
+    }
+
+    #[test]
+    fn skip_policy_drops_invalid_messages() {
+        let results: Vec<_> = block_on(
+            typed_stream(
+                messages(),
+                Nullable(StringDeserializer),
+                StringDeserializer,
+                DeserializeErrorPolicy::Skip,
+            )
+            .collect(),
+        );
+
+        assert_eq!(offsets(&results), vec![Some(0), Some(2)]);
+        assert_eq!(results[0].as_ref().unwrap().key, None);
+        assert_eq!(results[0].as_ref().unwrap().value, "ok");
+    }
+
+    #[test]
+    fn fail_policy_ends_stream() {
+        let results: Vec<_> = block_on(
+            typed_stream(
+                messages(),
+                Nullable(StringDeserializer),
+                StringDeserializer,
+                DeserializeErrorPolicy::Fail,
+            )
+            .collect(),
+        );
+
+        assert_eq!(offsets(&results), vec![Some(0), None]);
+        assert!(matches!(results[1], Err(Error::DeserializationFailed(_))));
+    }
+
+    #[test]
+    fn dead_letter_policy_forwards_invalid_messages() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let results: Vec<_> = block_on(
+            typed_stream(
+                messages(),
+                Nullable(BigEndianDeserializer::<i32>::default()),
+                StringDeserializer,
+                DeserializeErrorPolicy::DeadLetter(sender),
+            )
+            .collect(),
+        );
+
+        assert_eq!(offsets(&results), vec![Some(0), Some(2)]);
+        let letter = receiver.try_recv().unwrap();
+        assert_eq!(letter.message.offset, 1);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::Error;
+use std::{convert::TryInto, marker::PhantomData};
+
+/// Decodes message key or value.
+///
+/// `data` is `None` for null keys and values. Built-in deserializers reject nulls, wrap them in
+/// `Nullable` to accept them.
+pub trait Deserializer: Send + Sync {
+    type Item;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error>;
+}
+
+impl<D: Deserializer + ?Sized> Deserializer for Box<D> {
+    type Item = D::Item;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        (**self).deserialize(data)
+    }
+}
+
+/// Raw bytes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytesDeserializer;
+
+impl Deserializer for BytesDeserializer {
+    type Item = Vec<u8>;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        Ok(non_null(data)?.to_vec())
+    }
+}
+
+/// UTF-8 string
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StringDeserializer;
+
+impl Deserializer for StringDeserializer {
+    type Item = String;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        String::from_utf8(non_null(data)?.to_vec())
+            .map_err(|e| Error::DeserializationFailed(e.to_string().into()))
+    }
+}
+
+/// Integer types encoded in big-endian byte order
+pub trait BigEndianInt: Sized {
+    fn from_be_slice(data: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_big_endian_int {
+    ($($ty:ty),*) => {
+        $(
+            impl BigEndianInt for $ty {
+                fn from_be_slice(data: &[u8]) -> Option<Self> {
+                    data.try_into().ok().map(<$ty>::from_be_bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_big_endian_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+/// Integer encoded in big-endian byte order (as written by Java client integer serializers)
+#[derive(Debug, Clone, Copy)]
+pub struct BigEndianDeserializer<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for BigEndianDeserializer<T> {
+    fn default() -> Self {
+        BigEndianDeserializer(PhantomData)
+    }
+}
+
+impl<T: BigEndianInt> Deserializer for BigEndianDeserializer<T> {
+    type Item = T;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        let data = non_null(data)?;
+        T::from_be_slice(data).ok_or_else(|| {
+            Error::DeserializationFailed(
+                format!(
+                    "expected {} bytes, got {}",
+                    std::mem::size_of::<T>(),
+                    data.len()
+                )
+                .into(),
+            )
+        })
+    }
+}
+
+/// JSON document decoded with serde
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy)]
+pub struct JsonDeserializer<T>(PhantomData<fn() -> T>);
+
+#[cfg(feature = "json")]
+impl<T> Default for JsonDeserializer<T> {
+    fn default() -> Self {
+        JsonDeserializer(PhantomData)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Deserializer for JsonDeserializer<T> {
+    type Item = T;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        serde_json::from_slice(non_null(data)?)
+            .map_err(|e| Error::DeserializationFailed(e.to_string().into()))
+    }
+}
+
+/// Accepts null data, passes everything else to inner deserializer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nullable<D>(pub D);
+
+impl<D: Deserializer> Deserializer for Nullable<D> {
+    type Item = Option<D::Item>;
+
+    fn deserialize(&self, data: Option<&[u8]>) -> Result<Self::Item, Error> {
+        match data {
+            Some(data) => self.0.deserialize(Some(data)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn non_null(data: Option<&[u8]>) -> Result<&[u8], Error> {
+    data.ok_or_else(|| Error::DeserializationFailed("unexpected null".into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn string_deserializer() {
+        assert_eq!(StringDeserializer.deserialize(Some(b"abc")).unwrap(), "abc");
+        assert!(StringDeserializer.deserialize(Some(&[0xff])).is_err());
+        assert!(StringDeserializer.deserialize(None).is_err());
+    }
+
+    #[test]
+    fn big_endian_deserializer() {
+        let deserializer = BigEndianDeserializer::<i32>::default();
+        assert_eq!(deserializer.deserialize(Some(&[0, 0, 1, 2])).unwrap(), 258);
+        assert_eq!(
+            deserializer
+                .deserialize(Some(&[0xff, 0xff, 0xff, 0xfe]))
+                .unwrap(),
+            -2
+        );
+        assert!(deserializer.deserialize(Some(&[0, 1])).is_err());
+    }
+
+    #[test]
+    fn nullable_deserializer() {
+        let deserializer = Nullable(StringDeserializer);
+        assert_eq!(deserializer.deserialize(None).unwrap(), None);
+        assert_eq!(
+            deserializer.deserialize(Some(b"abc")).unwrap(),
+            Some("abc".to_string())
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_deserializer() {
+        let deserializer = JsonDeserializer::<Vec<i32>>::default();
+        assert_eq!(
+            deserializer.deserialize(Some(b"[1,2]")).unwrap(),
+            vec![1, 2]
+        );
+        assert!(deserializer.deserialize(Some(b"{")).is_err());
+    }
+}
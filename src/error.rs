@@ -9,6 +9,9 @@ pub enum Error {
     #[error("missing {0} in config")]
     IncompleteConfig(&'static str),
 
+    #[error("invalid {0} in config: {1}")]
+    InvalidConfig(&'static str, Cow<'static, str>),
+
     #[error("io error")]
     Io(#[from] std::io::Error),
 
@@ -29,6 +32,30 @@ pub enum Error {
 
     #[error("cluster error: {0}")]
     ClusterError(String),
+
+    #[error("assignment is no longer active")]
+    AssignmentClosed,
+
+    #[error("partition assignment failed: {0}")]
+    AssignmentFailed(Cow<'static, str>),
+
+    #[error("deserialization failed: {0}")]
+    DeserializationFailed(Cow<'static, str>),
+
+    #[error("producer was fenced by another instance with the same transactional id")]
+    ProducerFenced,
+
+    #[error("invalid transaction state: {0}")]
+    InvalidTransactionState(Cow<'static, str>),
+
+    #[error("transaction was aborted")]
+    TransactionAborted,
+
+    #[error("producer is closed")]
+    ProducerClosed,
+
+    #[error("record was not delivered within delivery timeout")]
+    DeliveryTimeout,
 }
 
 impl From<(ErrorCode, Option<String>)> for Error {
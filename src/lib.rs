@@ -1,15 +1,26 @@
 #[macro_use]
 extern crate rskafka_wire_format_derive;
 
+mod backoff;
 pub mod batch;
 pub mod client;
 pub mod consumer;
+pub mod deserializer;
 mod error;
 mod message;
+pub mod producer;
 
-pub use consumer::{Consumer, ConsumerConfig};
+pub use consumer::{
+    Consumer, ConsumerConfig, ConsumerConfigBuilder, ConsumerGroupMetadata, ConsumerMetrics,
+    DeserializeErrorPolicy, FetchConfig, FetchConfigBuilder, OffsetReset, PartitionError,
+    StandaloneConsumer, StandaloneConsumerConfig, StartPosition, TypedMessage,
+};
 pub use error::Error;
-pub use message::{KafkaMessage, KafkaOffset};
+pub use message::{KafkaHeader, KafkaMessage, KafkaOffset, KafkaPartition, TimestampType};
+pub use producer::{
+    Acks, Producer, ProducerConfig, ProducerConfigBuilder, ProducerRecord, ProducerSink,
+    RecordMetadata,
+};
 
 #[cfg(test)]
 mod test_utils {
@@ -20,4 +31,30 @@ mod test_utils {
             .try_init()
             .ok();
     }
+
+    pub fn hex_bytes(hex_str: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for i in 0..hex_str.len() / 2 {
+            let hex_byte = &hex_str[i * 2..=i * 2 + 1];
+            let byte = u8::from_str_radix(hex_byte, 16).unwrap();
+            buf.push(byte);
+        }
+
+        buf
+    }
+
+    /// Record batch with a single record at given offset
+    pub fn record_batch_bytes(offset: i64) -> Vec<u8> {
+        let mut batch = hex_bytes(
+            &[
+                "0000000000000001000000650000000002a25f84b100000000000000000171eb",
+                "dfc70500000171ebdfc705ffffffffffffffffffffffffffff00000001660000",
+                "0010647570612d6b657918647570612d7061796c6f6164040c68616465723108",
+                "313233340e686561646572320861626364",
+            ]
+            .concat(),
+        );
+        batch[..8].copy_from_slice(&offset.to_be_bytes());
+        batch
+    }
 }
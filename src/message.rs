@@ -5,8 +5,14 @@ pub struct KafkaMessage<'a> {
     pub topic: Cow<'a, str>,
     pub partition: i32,
     pub offset: i64,
+    /// Milliseconds since epoch, meaning depends on `timestamp_type`
+    pub timestamp: i64,
+    pub timestamp_type: TimestampType,
+    /// Epoch of partition leader which appended the message
+    pub leader_epoch: i32,
     pub key: Option<Cow<'a, [u8]>>,
     pub value: Option<Cow<'a, [u8]>>,
+    pub headers: Cow<'a, [KafkaHeader<'a>]>,
 }
 
 impl<'a> KafkaMessage<'a> {
@@ -23,15 +29,52 @@ impl<'a> KafkaMessage<'a> {
             topic: self.topic.as_ref().to_owned().into(),
             partition: self.partition,
             offset: self.offset,
+            timestamp: self.timestamp,
+            timestamp_type: self.timestamp_type,
+            leader_epoch: self.leader_epoch,
             key: self.key.as_ref().map(|k| Cow::Owned(k.as_ref().to_owned())),
             value: self
                 .value
                 .as_ref()
                 .map(|k| Cow::Owned(k.as_ref().to_owned())),
+            headers: self.headers.iter().map(KafkaHeader::detached).collect(),
+        }
+    }
+
+    /// Value of the first header with given key
+    pub fn header(&self, key: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|h| h.key == key)
+            .map(|h| h.value.as_ref())
+    }
+}
+
+/// Source of message timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampType {
+    /// Set by producer when message was created
+    CreateTime,
+    /// Set by broker when message was appended to the log
+    LogAppendTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaHeader<'a> {
+    pub key: Cow<'a, str>,
+    pub value: Cow<'a, [u8]>,
+}
+
+impl KafkaHeader<'_> {
+    pub fn detached(&self) -> KafkaHeader<'static> {
+        KafkaHeader {
+            key: self.key.as_ref().to_owned().into(),
+            value: self.value.as_ref().to_owned().into(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KafkaOffset<'a> {
     pub topic: Cow<'a, str>,
     pub partition: i32,
@@ -0,0 +1,438 @@
+use super::record::{ProducerRecord, RecordMetadata};
+use crate::{Error, KafkaPartition};
+use rskafka_proto::{Header, Record, RecordBatch};
+use rskafka_wire_format::{prelude::*, VarInt};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// Records waiting to be sent, grouped into per partition batches.
+///
+/// A batch is ready when it is full, its linger time passed or producer is flushed. Up to
+/// `max_in_flight` batches of a partition are sent at a time. Retried batches are queued ahead
+/// of later batches in sequence order and sent one at a time, so that records of a partition are
+/// appended to the log in the order they were sent. Batches not sent within delivery timeout
+/// expire.
+pub(super) struct Accumulator {
+    linger: Duration,
+    batch_size: usize,
+    max_in_flight: usize,
+    delivery_timeout: Duration,
+    batches: HashMap<KafkaPartition, VecDeque<ProducerBatch>>,
+    in_flight: HashMap<KafkaPartition, usize>,
+}
+
+impl Accumulator {
+    pub fn new(
+        linger: Duration,
+        batch_size: usize,
+        max_in_flight: usize,
+        delivery_timeout: Duration,
+    ) -> Self {
+        Accumulator {
+            linger,
+            batch_size,
+            max_in_flight,
+            delivery_timeout,
+            batches: HashMap::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+
+    pub fn append(&mut self, partition: KafkaPartition, record: PendingRecord, now: Instant) {
+        let batch_size = self.batch_size;
+        let queue = self.batches.entry(partition.clone()).or_default();
+        match queue.back_mut() {
+            Some(batch) if batch.size + record.record.size() <= batch_size => batch.push(record),
+            _ => {
+                let mut batch = ProducerBatch::new(partition, now);
+                batch.push(record);
+                queue.push_back(batch);
+            }
+        }
+    }
+
+    /// Record of given size would not fit into the last batch of partition
+    pub fn starts_new_batch(&self, partition: &KafkaPartition, record_size: usize) -> bool {
+        match self.batches.get(partition).and_then(|queue| queue.back()) {
+            Some(batch) => batch.size + record_size > self.batch_size,
+            None => true,
+        }
+    }
+
+    /// Takes at most one ready batch of every partition which may have another batch in flight.
+    /// Returned batches are in flight until `complete` (or `retry`) is called for them.
+    pub fn drain(&mut self, now: Instant, flush: bool) -> Vec<ProducerBatch> {
+        let mut ready = Vec::new();
+        for (partition, queue) in self.batches.iter_mut() {
+            let in_flight = self.in_flight.get(partition).copied().unwrap_or(0);
+            let is_ready = match queue.front() {
+                Some(batch) => match batch.retry_at {
+                    Some(retry_at) => in_flight == 0 && now >= retry_at,
+                    None => {
+                        in_flight < self.max_in_flight
+                            && (flush
+                                || queue.len() > 1
+                                || batch.size >= self.batch_size
+                                || now >= batch.created + self.linger)
+                    }
+                },
+                None => false,
+            };
+            if is_ready {
+                *self.in_flight.entry(partition.clone()).or_default() += 1;
+                ready.extend(queue.pop_front());
+            }
+        }
+        self.batches.retain(|_, queue| !queue.is_empty());
+        ready
+    }
+
+    /// Queues in flight batch to be sent again, ahead of batches with higher sequence numbers
+    pub fn retry(&mut self, mut batch: ProducerBatch, retry_at: Instant) {
+        self.complete(&batch.partition);
+        batch.attempts += 1;
+        batch.retry_at = Some(retry_at);
+        let queue = self.batches.entry(batch.partition.clone()).or_default();
+        let position = queue
+            .iter()
+            .position(|b| b.retry_at.is_none() || b.base_sequence > batch.base_sequence)
+            .unwrap_or(queue.len());
+        queue.insert(position, batch);
+    }
+
+    /// Takes batches waiting to be sent for longer than delivery timeout
+    pub fn expire(&mut self, now: Instant) -> Vec<ProducerBatch> {
+        let mut expired = Vec::new();
+        for queue in self.batches.values_mut() {
+            while let Some(batch) = queue.front() {
+                if now < batch.created + self.delivery_timeout {
+                    break;
+                }
+                expired.extend(queue.pop_front());
+            }
+        }
+        self.batches.retain(|_, queue| !queue.is_empty());
+        expired
+    }
+
+    /// Takes all batches not sent yet
+    pub fn drain_all(&mut self) -> Vec<ProducerBatch> {
+        self.batches
+            .drain()
+            .flat_map(|(_, queue)| queue.into_iter())
+            .collect()
+    }
+
+    /// Partition's in flight batch was acknowledged or failed
+    pub fn complete(&mut self, partition: &KafkaPartition) {
+        if let Some(in_flight) = self.in_flight.get_mut(partition) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                self.in_flight.remove(partition);
+            }
+        }
+    }
+
+    /// When the next batch of a partition which is not waiting for its in flight batches
+    /// becomes ready, or the next batch expires
+    pub fn next_due(&self) -> Option<Instant> {
+        self.batches
+            .iter()
+            .filter_map(|(partition, queue)| {
+                let batch = queue.front()?;
+                let in_flight = self.in_flight.get(partition).copied().unwrap_or(0);
+                let expires = batch.created + self.delivery_timeout;
+                let ready = match batch.retry_at {
+                    Some(retry_at) if in_flight == 0 => retry_at,
+                    None if in_flight < self.max_in_flight => batch.created + self.linger,
+                    _ => expires,
+                };
+                Some(ready.min(expires))
+            })
+            .min()
+    }
+
+    /// No batches are waiting to be sent
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+}
+
+pub(super) struct PendingRecord {
+    pub record: ProducerRecord,
+    /// Record timestamp, milliseconds since epoch
+    pub timestamp: i64,
+    pub delivery: oneshot::Sender<Result<RecordMetadata, Error>>,
+}
+
+/// Records of a single partition sent in one record batch
+pub(super) struct ProducerBatch {
+    pub partition: KafkaPartition,
+    records: Vec<PendingRecord>,
+    size: usize,
+    created: Instant,
+    /// Producer id and epoch, -1 unless producer is idempotent or transactional
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    pub transactional: bool,
+    attempts: u32,
+    /// Set when batch failed and waits to be sent again
+    retry_at: Option<Instant>,
+}
+
+impl ProducerBatch {
+    fn new(partition: KafkaPartition, created: Instant) -> Self {
+        ProducerBatch {
+            partition,
+            records: Vec::new(),
+            size: 0,
+            created,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            transactional: false,
+            attempts: 0,
+            retry_at: None,
+        }
+    }
+
+    fn push(&mut self, record: PendingRecord) {
+        self.size += record.record.size();
+        self.records.push(record);
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Bytes of records held by batch, counted against buffer memory
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of times batch was retried
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// When the first record was appended, delivery timeout counts from then
+    pub fn created(&self) -> Instant {
+        self.created
+    }
+
+    /// Record batch in wire format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let first_timestamp = self.records.first().map_or(0, |r| r.timestamp);
+        let max_timestamp = self
+            .records
+            .iter()
+            .map(|r| r.timestamp)
+            .max()
+            .unwrap_or(first_timestamp);
+        let records: Vec<Record> = self
+            .records
+            .iter()
+            .enumerate()
+            .map(|(i, r)| Record {
+                length: VarInt(0),
+                attributes: 0,
+                timestamp_delta: VarInt((r.timestamp - first_timestamp) as i32),
+                offset_delta: VarInt(i as i32),
+                key: r.record.key.as_deref().map(Cow::Borrowed),
+                value: r.record.value.as_deref().map(Cow::Borrowed),
+                headers: r
+                    .record
+                    .headers
+                    .iter()
+                    .map(|h| Header {
+                        key: Cow::Borrowed(h.key.as_ref()),
+                        value: Cow::Borrowed(h.value.as_ref()),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut batch = RecordBatch {
+            base_offset: 0,
+            batch_length: 0,
+            partition_leader_epoch: -1,
+            magic: 2,
+            crc: 0,
+            attributes: 0,
+            last_offset_delta: records.len() as i32 - 1,
+            first_timestamp,
+            max_timestamp,
+            producer_id: self.producer_id,
+            producer_epoch: self.producer_epoch,
+            base_sequence: self.base_sequence,
+            records: records.into(),
+        };
+        batch.set_transactional(self.transactional);
+        batch.to_wire_bytes()
+    }
+
+    /// Completes delivery of records appended at given offset. Log append time is -1 if
+    /// records keep their create time.
+    pub fn complete(self, base_offset: i64, log_append_time: i64) {
+        for (i, r) in self.records.into_iter().enumerate() {
+            let metadata = RecordMetadata {
+                topic: self.partition.topic_name.clone(),
+                partition: self.partition.partition_index,
+                offset: base_offset + i as i64,
+                timestamp: if log_append_time >= 0 {
+                    log_append_time
+                } else {
+                    r.timestamp
+                },
+            };
+            let _ = r.delivery.send(Ok(metadata));
+        }
+    }
+
+    pub fn fail(self, error: &Error) {
+        for r in self.records {
+            let _ = r.delivery.send(Err(copy_error(error)));
+        }
+    }
+}
+
+/// Copy of error reported to every record of a failed batch. Errors which can't be cloned
+/// (I/O errors) are reported by their description.
+pub(super) fn copy_error(error: &Error) -> Error {
+    match error {
+        Error::ErrorResponse(code, message) => Error::ErrorResponse(*code, message.clone()),
+        Error::ProducerFenced => Error::ProducerFenced,
+        Error::ProducerClosed => Error::ProducerClosed,
+        Error::TransactionAborted => Error::TransactionAborted,
+        Error::InvalidTransactionState(message) => Error::InvalidTransactionState(message.clone()),
+        Error::ValueError(message) => Error::ValueError(message.clone()),
+        Error::DeliveryTimeout => Error::DeliveryTimeout,
+        e => Error::ClusterError(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rskafka_proto::RecordBatch;
+
+    fn partition(index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: index,
+        }
+    }
+
+    fn record(value: &str) -> PendingRecord {
+        PendingRecord {
+            record: ProducerRecord::new("t1").value(value),
+            timestamp: 1000,
+            delivery: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn batches_are_drained_one_per_partition_when_ready() {
+        let linger = Duration::from_millis(10);
+        let mut accumulator = Accumulator::new(linger, 40, 1, Duration::from_secs(60));
+        let now = Instant::now();
+        accumulator.append(partition(0), record("aaaaaaaaaa"), now);
+        accumulator.append(partition(0), record("bbbbbbbbbb"), now);
+        accumulator.append(partition(1), record("c"), now);
+        assert_eq!(accumulator.next_due(), Some(now + linger));
+
+        // Second record of partition 0 did not fit into the first batch
+        let drained = accumulator.drain(now, false);
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].partition, partition(0));
+        assert!(accumulator.drain(now + linger, false)[0].partition == partition(1));
+        assert!(accumulator.drain(now + linger, true).is_empty());
+
+        accumulator.complete(&partition(0));
+        let drained = accumulator.drain(now, true);
+        assert_eq!(drained[0].record_count(), 1);
+        assert!(accumulator.is_empty());
+    }
+
+    #[test]
+    fn retried_batches_are_sent_in_sequence_order() {
+        let mut accumulator =
+            Accumulator::new(Duration::from_millis(0), 1, 2, Duration::from_secs(60));
+        let now = Instant::now();
+        for value in &["a", "b", "c"] {
+            accumulator.append(partition(0), record(value), now);
+        }
+        let mut in_flight = Vec::new();
+        for sequence in 0..2 {
+            let mut drained = accumulator.drain(now, false);
+            assert_eq!(drained.len(), 1);
+            drained[0].base_sequence = sequence;
+            in_flight.extend(drained);
+        }
+        assert!(
+            accumulator.drain(now, false).is_empty(),
+            "2 batches in flight"
+        );
+
+        // Second batch failed before the first one
+        let first = in_flight.remove(0);
+        accumulator.retry(in_flight.remove(0), now);
+        accumulator.retry(first, now);
+        assert_eq!(accumulator.next_due(), Some(now));
+        let sequences: Vec<_> = (0..3)
+            .map(|_| {
+                let drained = accumulator.drain(now, false);
+                let sequence = drained[0].base_sequence;
+                accumulator.complete(&drained[0].partition);
+                sequence
+            })
+            .collect();
+        assert_eq!(sequences, vec![0, 1, -1]);
+    }
+
+    #[test]
+    fn batches_expire_after_delivery_timeout() {
+        let timeout = Duration::from_secs(1);
+        let mut accumulator = Accumulator::new(Duration::from_secs(5), 1, 1, timeout);
+        let now = Instant::now();
+        accumulator.append(partition(0), record("a"), now);
+        accumulator.append(partition(0), record("b"), now + timeout / 2);
+        accumulator.append(partition(1), record("c"), now + timeout / 2);
+        assert_eq!(accumulator.next_due(), Some(now + timeout));
+
+        let expired = accumulator.expire(now + timeout);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].partition, partition(0));
+        assert_eq!(accumulator.next_due(), Some(now + timeout / 2 + timeout));
+        assert_eq!(accumulator.expire(now + timeout * 2).len(), 2);
+        assert!(accumulator.is_empty());
+    }
+
+    #[test]
+    fn batch_is_written_as_record_batch() {
+        let mut batch = ProducerBatch::new(partition(0), Instant::now());
+        batch.push(record("v1"));
+        batch.push(PendingRecord {
+            timestamp: 1005,
+            ..record("v2")
+        });
+        batch.producer_id = 7;
+        batch.producer_epoch = 1;
+        batch.base_sequence = 3;
+        batch.transactional = true;
+
+        let bytes = batch.to_bytes();
+        let parsed = RecordBatch::over_wire_bytes(&bytes).unwrap();
+        assert_eq!(parsed.last_offset_delta, 1);
+        assert_eq!(parsed.max_timestamp, 1005);
+        assert_eq!(parsed.base_sequence, 3);
+        assert!(parsed.is_transactional());
+        assert_eq!(parsed.records[1].timestamp_delta, VarInt(5));
+        assert_eq!(parsed.records[1].value.as_deref(), Some(&b"v2"[..]));
+    }
+}
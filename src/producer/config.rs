@@ -0,0 +1,334 @@
+use super::partitioner::{DefaultPartitioner, Partitioner};
+use crate::Error;
+use std::{convert::TryFrom, sync::Arc, time::Duration};
+
+/// Replicas which have to write records before partition leader acknowledges them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Acks {
+    /// Broker does not respond. Records are completed once they are sent, with offset -1.
+    None,
+    /// Partition leader only, records are lost if leader fails before followers copy them
+    Leader,
+    /// All in-sync replicas
+    All,
+}
+
+impl Acks {
+    pub(crate) fn value(self) -> i16 {
+        match self {
+            Acks::None => 0,
+            Acks::Leader => 1,
+            Acks::All => -1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProducerConfig {
+    pub(crate) client_id: String,
+    /// Chooses partition of records sent without partition
+    pub(crate) partitioner: Arc<dyn Partitioner>,
+    /// Time a record may wait in its batch for more records before the batch is sent
+    pub(crate) linger: Duration,
+    /// Size of record data in a batch after which the batch is sent without waiting for linger
+    pub(crate) batch_size: usize,
+    /// Bytes of records waiting to be sent or acknowledged
+    pub(crate) buffer_memory: usize,
+    pub(crate) acks: Acks,
+    pub(crate) request_timeout_ms: i32,
+    /// Time since record is appended after which it fails if not acknowledged yet
+    pub(crate) delivery_timeout: Duration,
+    /// Batches are stamped with producer id and sequence numbers, so that retries don't write
+    /// duplicates. Always enabled for transactional producer.
+    pub(crate) idempotence: bool,
+    /// Number of batches of a partition sent without waiting for acknowledgement
+    pub(crate) max_in_flight: usize,
+    /// Enables transactions, see `Producer::init_transactions`
+    pub(crate) transactional_id: Option<String>,
+    pub(crate) transaction_timeout_ms: i32,
+    pub(crate) retry_backoff: Duration,
+    pub(crate) retry_backoff_max: Duration,
+}
+
+impl ProducerConfig {
+    pub fn builder() -> ProducerConfigBuilder {
+        ProducerConfigBuilder::default()
+    }
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        ProducerConfigBuilder::default().build().unwrap()
+    }
+}
+
+pub struct ProducerConfigBuilder {
+    client_id: String,
+    partitioner: Arc<dyn Partitioner>,
+    linger: Duration,
+    batch_size: usize,
+    buffer_memory: usize,
+    acks: Acks,
+    request_timeout: Duration,
+    delivery_timeout: Duration,
+    idempotence: bool,
+    max_in_flight: usize,
+    transactional_id: Option<String>,
+    transaction_timeout: Duration,
+    retry_backoff: Duration,
+    retry_backoff_max: Duration,
+}
+
+/// Broker tracks sequences of only this many batches per partition
+const MAX_IDEMPOTENT_IN_FLIGHT: usize = 5;
+
+impl Default for ProducerConfigBuilder {
+    fn default() -> Self {
+        ProducerConfigBuilder {
+            client_id: "rskafka".to_string(),
+            partitioner: Arc::new(DefaultPartitioner::default()),
+            linger: Duration::from_millis(5),
+            batch_size: 16 * 1024,
+            buffer_memory: 32 * 1024 * 1024,
+            acks: Acks::All,
+            request_timeout: Duration::from_secs(30),
+            delivery_timeout: Duration::from_secs(120),
+            idempotence: false,
+            max_in_flight: MAX_IDEMPOTENT_IN_FLIGHT,
+            transactional_id: None,
+            transaction_timeout: Duration::from_secs(60),
+            retry_backoff: Duration::from_millis(100),
+            retry_backoff_max: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ProducerConfigBuilder {
+    pub fn client_id(mut self, val: String) -> Self {
+        self.client_id = val;
+        self
+    }
+
+    /// Partitioner of records without partition, `DefaultPartitioner` by default
+    pub fn partitioner(mut self, val: Arc<dyn Partitioner>) -> Self {
+        self.partitioner = val;
+        self
+    }
+
+    /// Time a record may wait for more records to be batched with, zero sends records as soon
+    /// as possible
+    pub fn linger(mut self, val: Duration) -> Self {
+        self.linger = val;
+        self
+    }
+
+    /// Bytes of record data per partition batch
+    pub fn batch_size(mut self, val: usize) -> Self {
+        self.batch_size = val;
+        self
+    }
+
+    /// Bytes of records waiting to be sent or acknowledged, `send` waits while buffer is full
+    pub fn buffer_memory(mut self, val: usize) -> Self {
+        self.buffer_memory = val;
+        self
+    }
+
+    /// Acknowledgement required for records, `Acks::All` by default
+    pub fn acks(mut self, val: Acks) -> Self {
+        self.acks = val;
+        self
+    }
+
+    /// Time broker may take to respond to produce request. Request which is not responded in
+    /// time is retried.
+    pub fn request_timeout(mut self, val: Duration) -> Self {
+        self.request_timeout = val;
+        self
+    }
+
+    /// Upper bound of time between `send` and record being acknowledged or failed, including
+    /// linger and retries. Must not be shorter than linger plus request timeout.
+    pub fn delivery_timeout(mut self, val: Duration) -> Self {
+        self.delivery_timeout = val;
+        self
+    }
+
+    /// Prevents duplicates and reordering of records when batches are retried
+    pub fn idempotence(mut self, val: bool) -> Self {
+        self.idempotence = val;
+        self
+    }
+
+    /// Batches of a partition in flight at a time, at most 5 with idempotence. Without
+    /// idempotence retried batches may be written out of order unless this is 1.
+    pub fn max_in_flight(mut self, val: usize) -> Self {
+        self.max_in_flight = val;
+        self
+    }
+
+    /// Identifies producer across restarts. Enables transactions and fences older producer
+    /// instances using the same id.
+    pub fn transactional_id(mut self, val: String) -> Self {
+        self.transactional_id = Some(val);
+        self
+    }
+
+    /// Time after which coordinator aborts transaction not completed by producer
+    pub fn transaction_timeout(mut self, val: Duration) -> Self {
+        self.transaction_timeout = val;
+        self
+    }
+
+    /// Initial delay before retrying a failed request, doubled with every attempt
+    pub fn retry_backoff(mut self, val: Duration) -> Self {
+        self.retry_backoff = val;
+        self
+    }
+
+    pub fn retry_backoff_max(mut self, val: Duration) -> Self {
+        self.retry_backoff_max = val;
+        self
+    }
+
+    pub fn build(self) -> Result<ProducerConfig, Error> {
+        if self.batch_size == 0 {
+            return Err(Error::InvalidConfig(
+                "batch_size",
+                "must be positive".into(),
+            ));
+        }
+        if self.buffer_memory < self.batch_size {
+            return Err(Error::InvalidConfig(
+                "buffer_memory",
+                "must not be smaller than batch_size".into(),
+            ));
+        }
+        let idempotence = self.idempotence || self.transactional_id.is_some();
+        if self.max_in_flight == 0 {
+            return Err(Error::InvalidConfig(
+                "max_in_flight",
+                "must be positive".into(),
+            ));
+        }
+        if idempotence && self.max_in_flight > MAX_IDEMPOTENT_IN_FLIGHT {
+            return Err(Error::InvalidConfig(
+                "max_in_flight",
+                "must not be greater than 5 with idempotence".into(),
+            ));
+        }
+        if idempotence && self.acks != Acks::All {
+            return Err(Error::InvalidConfig(
+                "acks",
+                "must be All with idempotence".into(),
+            ));
+        }
+        if self.delivery_timeout < self.linger + self.request_timeout {
+            return Err(Error::InvalidConfig(
+                "delivery_timeout",
+                "must not be shorter than linger plus request_timeout".into(),
+            ));
+        }
+        if self.transactional_id.as_deref() == Some("") {
+            return Err(Error::InvalidConfig(
+                "transactional_id",
+                "must not be empty".into(),
+            ));
+        }
+        if self.retry_backoff_max < self.retry_backoff {
+            return Err(Error::InvalidConfig(
+                "retry_backoff_max",
+                "must not be shorter than retry_backoff".into(),
+            ));
+        }
+
+        Ok(ProducerConfig {
+            client_id: self.client_id,
+            partitioner: self.partitioner,
+            linger: self.linger,
+            batch_size: self.batch_size,
+            buffer_memory: self.buffer_memory,
+            acks: self.acks,
+            request_timeout_ms: positive_millis("request_timeout", self.request_timeout)?,
+            delivery_timeout: self.delivery_timeout,
+            idempotence,
+            max_in_flight: self.max_in_flight,
+            transactional_id: self.transactional_id,
+            transaction_timeout_ms: positive_millis(
+                "transaction_timeout",
+                self.transaction_timeout,
+            )?,
+            retry_backoff: self.retry_backoff,
+            retry_backoff_max: self.retry_backoff_max,
+        })
+    }
+}
+
+fn positive_millis(name: &'static str, val: Duration) -> Result<i32, Error> {
+    match i32::try_from(val.as_millis()) {
+        Ok(0) => Err(Error::InvalidConfig(name, "must be positive".into())),
+        Ok(ms) => Ok(ms),
+        Err(_) => Err(Error::InvalidConfig(name, "too long".into())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn producer_config_is_validated() {
+        let config = ProducerConfig::builder()
+            .transactional_id("txn-1".into())
+            .build()
+            .unwrap();
+        assert_eq!(config.transactional_id.as_deref(), Some("txn-1"));
+        assert_eq!(config.transaction_timeout_ms, 60_000);
+        assert!(config.idempotence);
+
+        let invalid = ProducerConfig::builder().batch_size(0).build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("batch_size", _))
+        ));
+        let invalid = ProducerConfig::builder().buffer_memory(1024).build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("buffer_memory", _))
+        ));
+        let invalid = ProducerConfig::builder()
+            .transactional_id(String::new())
+            .build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("transactional_id", _))
+        ));
+        let invalid = ProducerConfig::builder()
+            .idempotence(true)
+            .max_in_flight(6)
+            .build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("max_in_flight", _))
+        ));
+        let invalid = ProducerConfig::builder()
+            .transactional_id("txn-1".into())
+            .acks(Acks::Leader)
+            .build();
+        assert!(matches!(invalid, Err(Error::InvalidConfig("acks", _))));
+        let invalid = ProducerConfig::builder()
+            .delivery_timeout(Duration::from_secs(10))
+            .build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("delivery_timeout", _))
+        ));
+        let invalid = ProducerConfig::builder()
+            .request_timeout(Duration::from_secs(0))
+            .build();
+        assert!(matches!(
+            invalid,
+            Err(Error::InvalidConfig("request_timeout", _))
+        ));
+    }
+}
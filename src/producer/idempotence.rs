@@ -0,0 +1,175 @@
+use super::accumulator::ProducerBatch;
+use crate::{client::AsyncClusterClient, Error, KafkaPartition};
+use rskafka_proto::{apis::init_producer_id::InitProducerIdRequestV1, ErrorCode};
+use rskafka_wire_format::prelude::*;
+use std::{collections::HashMap, time::Duration};
+
+/// Producer id and epoch assigned by broker, with sequence numbers of every partition.
+///
+/// Broker appends batch only if its base sequence follows the last batch it appended from the
+/// producer, so batches which are retried (or were in flight with a failed one) are neither
+/// duplicated nor reordered.
+pub(super) struct ProducerId {
+    pub id: i64,
+    pub epoch: i16,
+    /// Base sequence of the next batch
+    sequences: HashMap<KafkaPartition, i32>,
+    /// Sequence following the last acknowledged batch
+    acknowledged: HashMap<KafkaPartition, i32>,
+}
+
+impl ProducerId {
+    pub fn new(id: i64, epoch: i16) -> Self {
+        ProducerId {
+            id,
+            epoch,
+            sequences: HashMap::new(),
+            acknowledged: HashMap::new(),
+        }
+    }
+
+    /// Base sequence of batch with given number of records
+    pub fn next_sequence(&mut self, partition: &KafkaPartition, records: usize) -> i32 {
+        let sequence = self.sequences.entry(partition.clone()).or_insert(0);
+        let base = *sequence;
+        *sequence = add_sequence(base, records);
+        base
+    }
+
+    /// Batch was stamped with this producer id, and not with an older one
+    pub fn owns(&self, batch: &ProducerBatch) -> bool {
+        batch.producer_id == self.id && batch.producer_epoch == self.epoch
+    }
+
+    pub fn acknowledge(&mut self, batch: &ProducerBatch) {
+        self.acknowledged.insert(
+            batch.partition.clone(),
+            add_sequence(batch.base_sequence, batch.record_count()),
+        );
+    }
+
+    /// Batch directly follows the last acknowledged batch of its partition
+    pub fn is_next_expected(&self, batch: &ProducerBatch) -> bool {
+        self.acknowledged
+            .get(&batch.partition)
+            .map_or(0, |sequence| *sequence)
+            == batch.base_sequence
+    }
+}
+
+/// Sequences wrap to 0 after `i32::MAX`
+fn add_sequence(base: i32, records: usize) -> i32 {
+    let records = records as i32;
+    if base > i32::MAX - records {
+        records - (i32::MAX - base) - 1
+    } else {
+        base + records
+    }
+}
+
+/// What happens to failed batch of idempotent producer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Recovery {
+    /// Batch is sent again with the same sequence
+    Retry,
+    Fail,
+    /// Broker lost producer's sequences (e.g. producer state expired), batch fails and new
+    /// producer id is obtained for the following batches
+    ResetProducerId,
+}
+
+impl Recovery {
+    /// `next_expected` tells whether all batches preceding the failed one were acknowledged
+    pub fn after(error: &Error, next_expected: bool) -> Self {
+        match error {
+            // Earlier batch failed or is still in flight, batch will fit after it is written
+            Error::ErrorResponse(ErrorCode::OutOfOrderSequenceNumber, _) if !next_expected => {
+                Recovery::Retry
+            }
+            Error::ErrorResponse(ErrorCode::OutOfOrderSequenceNumber, _)
+            | Error::ErrorResponse(ErrorCode::UnknownProducerId, _) => Recovery::ResetProducerId,
+            e if is_retriable(e) => Recovery::Retry,
+            _ => Recovery::Fail,
+        }
+    }
+}
+
+/// Batch may not have been written, e.g. because partition leader moved or broker was not
+/// reachable
+pub(super) fn is_retriable(error: &Error) -> bool {
+    match error {
+        Error::Io(_) | Error::ClusterError(_) => true,
+        Error::ErrorResponse(code, _) => matches!(
+            code,
+            ErrorCode::NotLeaderForPartition
+                | ErrorCode::LeaderNotAvailable
+                | ErrorCode::UnknownTopicOrPartition
+                | ErrorCode::NetworkException
+                | ErrorCode::RequestTimedOut
+                | ErrorCode::NotEnoughReplicas
+                | ErrorCode::NotEnoughReplicasAfterAppend
+                | ErrorCode::KafkaStorageError
+        ),
+        _ => false,
+    }
+}
+
+/// Obtains producer id of idempotent producer without transactional id
+pub(super) async fn init_producer_id(
+    cluster: &AsyncClusterClient,
+    transaction_timeout_ms: i32,
+    request_timeout: Duration,
+) -> Result<ProducerId, Error> {
+    let request = InitProducerIdRequestV1 {
+        transactional_id: NullableString::with_null(),
+        transaction_timeout_ms,
+    };
+    let response = cluster
+        .make_request_timeout(request, None, request_timeout)
+        .await?;
+    match response.error_code {
+        ErrorCode::None => Ok(ProducerId::new(
+            response.producer_id,
+            response.producer_epoch,
+        )),
+        code => Err(code.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn partition(index: i32) -> KafkaPartition {
+        KafkaPartition {
+            topic_name: "t1".into(),
+            partition_index: index,
+        }
+    }
+
+    #[test]
+    fn sequences_are_counted_per_partition_and_wrap() {
+        let mut producer_id = ProducerId::new(1, 0);
+        assert_eq!(producer_id.next_sequence(&partition(0), 3), 0);
+        assert_eq!(producer_id.next_sequence(&partition(0), 2), 3);
+        assert_eq!(producer_id.next_sequence(&partition(1), 1), 0);
+
+        producer_id.sequences.insert(partition(0), i32::MAX - 1);
+        assert_eq!(producer_id.next_sequence(&partition(0), 3), i32::MAX - 1);
+        assert_eq!(producer_id.sequences[&partition(0)], 1);
+    }
+
+    #[test]
+    fn out_of_order_batch_is_retried_behind_earlier_batches() {
+        let out_of_order = Error::from(ErrorCode::OutOfOrderSequenceNumber);
+        assert_eq!(Recovery::after(&out_of_order, false), Recovery::Retry);
+        assert_eq!(
+            Recovery::after(&out_of_order, true),
+            Recovery::ResetProducerId
+        );
+        let not_leader = Error::from(ErrorCode::NotLeaderForPartition);
+        assert_eq!(Recovery::after(&not_leader, true), Recovery::Retry);
+        let too_large = Error::from(ErrorCode::MessageTooLarge);
+        assert_eq!(Recovery::after(&too_large, false), Recovery::Fail);
+    }
+}
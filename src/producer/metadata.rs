@@ -0,0 +1,91 @@
+use crate::{client::AsyncClusterClient, Error, KafkaPartition};
+use rskafka_proto::{BrokerId, ErrorCode};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+/// Partition leaders of topics records are sent to. Topic is looked up in cluster metadata when
+/// it is first used and again after its leaders moved.
+pub(super) struct ProducerMetadata {
+    topics: Mutex<HashMap<String, HashMap<i32, BrokerId>>>,
+    request_timeout: Duration,
+}
+
+impl ProducerMetadata {
+    pub fn new(request_timeout: Duration) -> Self {
+        ProducerMetadata {
+            topics: Mutex::new(HashMap::new()),
+            request_timeout,
+        }
+    }
+
+    pub async fn partition_count(
+        &self,
+        cluster: &AsyncClusterClient,
+        topic: &str,
+    ) -> Result<i32, Error> {
+        if let Some(partitions) = self.topics.lock().unwrap().get(topic) {
+            return Ok(partitions.len() as i32);
+        }
+        self.refresh(cluster, topic).await
+    }
+
+    pub async fn leader(
+        &self,
+        cluster: &AsyncClusterClient,
+        partition: &KafkaPartition,
+    ) -> Result<BrokerId, Error> {
+        if let Some(leader) = self.cached_leader(partition) {
+            return Ok(leader);
+        }
+        self.refresh(cluster, &partition.topic_name).await?;
+        self.cached_leader(partition).ok_or_else(|| {
+            (
+                ErrorCode::UnknownTopicOrPartition,
+                Some(format!("{} not found", partition)),
+            )
+                .into()
+        })
+    }
+
+    /// Forgets leaders of topic, e.g. after broker responded it is not a leader anymore
+    pub fn invalidate(&self, topic: &str) {
+        self.topics.lock().unwrap().remove(topic);
+    }
+
+    fn cached_leader(&self, partition: &KafkaPartition) -> Option<BrokerId> {
+        self.topics
+            .lock()
+            .unwrap()
+            .get(&partition.topic_name)?
+            .get(&partition.partition_index)
+            .copied()
+    }
+
+    /// Caches topic with leaders of all partitions on broker 1
+    #[cfg(test)]
+    pub fn add_topic(&self, topic: &str, partition_count: i32) {
+        let partitions = (0..partition_count)
+            .map(|p| (p, BrokerId::from(1)))
+            .collect();
+        self.topics
+            .lock()
+            .unwrap()
+            .insert(topic.to_owned(), partitions);
+    }
+
+    /// Looks up topic leaders, returns number of topic partitions
+    async fn refresh(&self, cluster: &AsyncClusterClient, topic: &str) -> Result<i32, Error> {
+        let leaders = cluster
+            .partition_leaders_timeout(std::iter::once(topic), self.request_timeout)
+            .await?;
+        let partitions: HashMap<i32, BrokerId> = leaders
+            .into_iter()
+            .map(|(p, leader)| (p.partition_index, leader))
+            .collect();
+        let count = partitions.len() as i32;
+        self.topics
+            .lock()
+            .unwrap()
+            .insert(topic.to_owned(), partitions);
+        Ok(count)
+    }
+}
@@ -0,0 +1,491 @@
+use crate::{
+    client::AsyncClusterClient, consumer::ConsumerGroupMetadata, Error, KafkaOffset, KafkaPartition,
+};
+use accumulator::{Accumulator, PendingRecord, ProducerBatch};
+use idempotence::ProducerId;
+use log::{debug, info};
+use metadata::ProducerMetadata;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{watch, Notify},
+    task::JoinHandle,
+};
+use transaction::{TransactionManager, TransactionState};
+
+mod accumulator;
+mod config;
+mod idempotence;
+mod metadata;
+pub mod partitioner;
+mod record;
+mod sender;
+mod sink;
+mod transaction;
+
+pub use config::{Acks, ProducerConfig, ProducerConfigBuilder};
+pub use record::{DeliveryFuture, ProducerRecord, RecordMetadata};
+pub use sink::ProducerSink;
+
+/// Asynchronous producer sending records in per partition batches.
+///
+/// Records are appended to batches by `send` and sent by a background task once batch is full or
+/// its linger time passes. Records may also be forwarded to the producer's `sink`. Producer with
+/// `transactional_id` sends records in transactions, see `init_transactions`.
+pub struct Producer {
+    inner: Arc<ProducerInner>,
+    sender_task: Option<JoinHandle<()>>,
+}
+
+impl Producer {
+    pub async fn bootstrap<S: AsRef<str>>(
+        servers: S,
+        config: ProducerConfig,
+    ) -> Result<Self, Error> {
+        let cluster = AsyncClusterClient::bootstrap(servers, config.client_id.clone()).await?;
+        Ok(Self::with_cluster_client(&Arc::new(cluster), config))
+    }
+
+    pub fn with_cluster_client(client: &Arc<AsyncClusterClient>, config: ProducerConfig) -> Self {
+        let inner = Arc::new(ProducerInner::new(Arc::clone(client), config));
+        let sender_task = tokio::spawn(sender::run(Arc::clone(&inner)));
+
+        Producer {
+            inner,
+            sender_task: Some(sender_task),
+        }
+    }
+
+    /// Appends record to its partition batch, waiting while buffer memory is full. Returned
+    /// future completes once record is acknowledged by partition leader, or fails if it is not
+    /// acknowledged within delivery timeout. With `Acks::None` it completes once record is sent.
+    ///
+    /// Transactional producer can send records only after `begin_transaction`.
+    pub async fn send(&self, record: ProducerRecord) -> Result<DeliveryFuture, Error> {
+        self.inner.send(record).await
+    }
+
+    /// Sink sending records with this producer, see `ProducerSink`
+    pub fn sink(&self) -> ProducerSink {
+        ProducerSink::new(Arc::clone(&self.inner))
+    }
+
+    /// Sends all appended records without waiting for linger and waits until they are
+    /// acknowledged or fail
+    pub async fn flush(&self) {
+        self.inner.flush().await
+    }
+
+    /// Flushes records and stops background task
+    pub async fn close(mut self) {
+        self.inner.close();
+        if let Some(task) = self.sender_task.take() {
+            let _ = task.await;
+        }
+    }
+
+    /// Registers producer's transactional id with transaction coordinator. Older producer
+    /// instances using the same id are fenced and their open transaction is completed.
+    ///
+    /// Has to be called once before the first transaction is started.
+    pub async fn init_transactions(&self) -> Result<(), Error> {
+        let mut transaction = self.inner.transaction()?.lock().await;
+        if transaction.state() != TransactionState::Uninitialized {
+            return Err(Error::InvalidTransactionState(
+                "transactions already initialized".into(),
+            ));
+        }
+        let (producer_id, producer_epoch) = transaction.init().await?;
+        self.inner.set_producer_id(producer_id, producer_epoch);
+        Ok(())
+    }
+
+    pub async fn begin_transaction(&self) -> Result<(), Error> {
+        self.inner.transaction()?.lock().await.begin()
+    }
+
+    /// Commits offsets of consumed messages with the transaction, so that messages are marked
+    /// consumed only if records produced from them are committed. Offsets are those of the last
+    /// processed messages, as passed to `CommitSink`.
+    ///
+    /// Offsets are committed with group generation and member id, so offsets of a member which
+    /// was removed from the group are rejected and transaction has to be aborted.
+    pub async fn send_offsets_to_transaction(
+        &self,
+        offsets: &[KafkaOffset<'_>],
+        group: &ConsumerGroupMetadata,
+    ) -> Result<(), Error> {
+        self.inner
+            .transaction()?
+            .lock()
+            .await
+            .send_offsets(offsets, group)
+            .await
+    }
+
+    /// Flushes records sent in transaction and commits it. If some records failed the error is
+    /// returned and transaction has to be aborted.
+    pub async fn commit_transaction(&self) -> Result<(), Error> {
+        let transaction = self.inner.transaction()?;
+        transaction.lock().await.start_commit()?;
+        self.inner.flush().await;
+
+        let mut transaction = transaction.lock().await;
+        match transaction.state() {
+            TransactionState::AbortableError => {
+                let error = self.inner.state.lock().unwrap().transaction_error.clone();
+                return Err(Error::InvalidTransactionState(
+                    format!(
+                        "transaction has failed records: {}",
+                        error.unwrap_or_default()
+                    )
+                    .into(),
+                ));
+            }
+            TransactionState::Fenced => return Err(Error::ProducerFenced),
+            _ => (),
+        }
+        transaction.end(true).await?;
+        debug!("Transaction committed");
+        Ok(())
+    }
+
+    /// Aborts transaction. Records not sent yet fail with `Error::TransactionAborted`, records
+    /// already sent are aborted by coordinator.
+    pub async fn abort_transaction(&self) -> Result<(), Error> {
+        let transaction = self.inner.transaction()?;
+        transaction.lock().await.start_abort()?;
+
+        let unsent = self.inner.state.lock().unwrap().accumulator.drain_all();
+        let count = unsent.iter().map(|b| b.record_count()).sum();
+        let size = unsent.iter().map(|b| b.size()).sum();
+        for batch in unsent {
+            batch.fail(&Error::TransactionAborted);
+        }
+        self.inner.record_completed(count, size);
+        self.inner.flush().await;
+
+        let mut transaction = transaction.lock().await;
+        transaction.end(false).await?;
+        let failed = self.inner.state.lock().unwrap().transaction_error.take();
+        if failed.is_some() {
+            // Sequence numbers of failed batches were lost, new epoch starts them over
+            let (producer_id, producer_epoch) = transaction.init().await?;
+            self.inner.set_producer_id(producer_id, producer_epoch);
+        }
+        info!("Transaction aborted");
+        Ok(())
+    }
+}
+
+impl Drop for Producer {
+    /// Background task sends remaining records and stops
+    fn drop(&mut self) {
+        self.inner.close();
+    }
+}
+
+/// Producer state shared with background task
+struct ProducerInner {
+    cluster: Arc<AsyncClusterClient>,
+    config: ProducerConfig,
+    metadata: ProducerMetadata,
+    state: Mutex<ProducerState>,
+    /// Notified when records are appended or completed, flush is requested or producer closed
+    wakeup: Notify,
+    pending_sender: watch::Sender<usize>,
+    /// Number of records appended and not completed yet
+    pending: watch::Receiver<usize>,
+    transaction: Option<tokio::sync::Mutex<TransactionManager>>,
+}
+
+struct ProducerState {
+    accumulator: Accumulator,
+    /// Set once producer id of idempotent (or transactional) producer is obtained from broker
+    producer_id: Option<ProducerId>,
+    pending: usize,
+    /// Bytes of pending records
+    buffered: usize,
+    /// Batches are sent without waiting for linger until accumulator is empty
+    flushing: bool,
+    closed: bool,
+    /// Error of records which failed in the ongoing transaction
+    transaction_error: Option<String>,
+}
+
+impl ProducerInner {
+    fn new(cluster: Arc<AsyncClusterClient>, config: ProducerConfig) -> Self {
+        let transaction = config.transactional_id.clone().map(|id| {
+            tokio::sync::Mutex::new(TransactionManager::new(Arc::clone(&cluster), id, &config))
+        });
+        let (pending_sender, pending) = watch::channel(0);
+        ProducerInner {
+            cluster,
+            metadata: ProducerMetadata::new(Duration::from_millis(
+                config.request_timeout_ms as u64,
+            )),
+            state: Mutex::new(ProducerState {
+                accumulator: Accumulator::new(
+                    config.linger,
+                    config.batch_size,
+                    config.max_in_flight,
+                    config.delivery_timeout,
+                ),
+                producer_id: None,
+                pending: 0,
+                buffered: 0,
+                flushing: false,
+                closed: false,
+                transaction_error: None,
+            }),
+            wakeup: Notify::new(),
+            pending_sender,
+            pending,
+            transaction,
+            config,
+        }
+    }
+
+    fn transaction(&self) -> Result<&tokio::sync::Mutex<TransactionManager>, Error> {
+        self.transaction.as_ref().ok_or_else(|| {
+            Error::InvalidTransactionState("producer has no transactional id".into())
+        })
+    }
+
+    async fn send(&self, record: ProducerRecord) -> Result<DeliveryFuture, Error> {
+        if let Some(transaction) = self.transaction.as_ref() {
+            transaction.lock().await.ensure_in_transaction()?;
+        }
+        let size = record.size();
+        if size > self.config.buffer_memory {
+            return Err(Error::ValueError(
+                format!("record of {} bytes does not fit into buffer memory", size).into(),
+            ));
+        }
+        let partition = self.partition(&record).await?;
+        let timestamp = record.timestamp.unwrap_or_else(now_millis);
+        let (delivery, future) = DeliveryFuture::new();
+        let pending = PendingRecord {
+            record,
+            timestamp,
+            delivery,
+        };
+
+        // Buffer memory is released when records complete, which is broadcast as change of
+        // pending records
+        let mut completed = self.pending.clone();
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return Err(Error::ProducerClosed);
+                }
+                if state.buffered + size <= self.config.buffer_memory {
+                    state.accumulator.append(partition, pending, Instant::now());
+                    state.pending += 1;
+                    state.buffered += size;
+                    let _ = self.pending_sender.broadcast(state.pending);
+                    break;
+                }
+            }
+            if completed.recv().await.is_none() {
+                return Err(Error::ProducerClosed);
+            }
+        }
+        self.wakeup.notify();
+
+        Ok(future)
+    }
+
+    fn set_producer_id(&self, id: i64, epoch: i16) {
+        self.state.lock().unwrap().producer_id = Some(ProducerId::new(id, epoch));
+    }
+
+    /// Partition of record without partition is chosen by partitioner
+    async fn partition(&self, record: &ProducerRecord) -> Result<KafkaPartition, Error> {
+        let count = self
+            .metadata
+            .partition_count(&self.cluster, &record.topic)
+            .await?;
+        let partition_index = match record.partition {
+            Some(p) => p,
+            None => self.partitioner_partition(record, count),
+        };
+        if partition_index < 0 || partition_index >= count {
+            return Err(Error::ValueError(
+                format!(
+                    "topic {} has no partition {}",
+                    record.topic, partition_index
+                )
+                .into(),
+            ));
+        }
+        Ok(KafkaPartition {
+            topic_name: record.topic.clone(),
+            partition_index,
+        })
+    }
+
+    fn partitioner_partition(&self, record: &ProducerRecord, count: i32) -> i32 {
+        let partitioner = &self.config.partitioner;
+        let key = record.key.as_deref();
+        let partition = partitioner.partition(&record.topic, key, count);
+        if key.is_some() {
+            return partition;
+        }
+
+        // Sticky partitioner moves records without key to another partition once batch of the
+        // current one is completed
+        let starts_new_batch = self.state.lock().unwrap().accumulator.starts_new_batch(
+            &KafkaPartition {
+                topic_name: record.topic.clone(),
+                partition_index: partition,
+            },
+            record.size(),
+        );
+        if starts_new_batch {
+            partitioner
+                .on_new_batch(&record.topic, partition, count)
+                .unwrap_or(partition)
+        } else {
+            partition
+        }
+    }
+
+    async fn flush(&self) {
+        let mut pending = self.pending.clone();
+        self.request_flush();
+        while *pending.borrow() > 0 {
+            if pending.recv().await.is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Batches are sent without waiting for linger until accumulator is empty
+    fn request_flush(&self) {
+        self.state.lock().unwrap().flushing = true;
+        self.wakeup.notify();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.wakeup.notify();
+    }
+
+    /// Completes records of acknowledged batch
+    fn complete_batch(&self, batch: ProducerBatch, base_offset: i64, log_append_time: i64) {
+        if let Some(producer_id) = self.state.lock().unwrap().producer_id.as_mut() {
+            if producer_id.owns(&batch) {
+                producer_id.acknowledge(&batch);
+            }
+        }
+        let partition = batch.partition.clone();
+        let (count, size) = (batch.record_count(), batch.size());
+        batch.complete(base_offset, log_append_time);
+        self.batch_completed(&partition, count, size);
+    }
+
+    /// Batch can't be retried anymore
+    fn delivery_expired(&self, batch: &ProducerBatch) -> bool {
+        Instant::now() >= batch.created() + self.config.delivery_timeout
+    }
+
+    /// Time to wait for produce response, the same broker may wait for replication
+    fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.request_timeout_ms as u64)
+    }
+
+    /// Queues failed batch to be sent again after backoff, ahead of partition's later batches
+    fn retry_batch(&self, batch: ProducerBatch) {
+        let delay = self.config.retry_backoff * 2u32.pow(batch.attempts().min(16));
+        let retry_at = Instant::now() + delay.min(self.config.retry_backoff_max);
+        self.state
+            .lock()
+            .unwrap()
+            .accumulator
+            .retry(batch, retry_at);
+        self.wakeup.notify();
+    }
+
+    /// Idempotent producer without transactions needs producer id before sending batches
+    fn needs_producer_id(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        self.config.idempotence
+            && self.transaction.is_none()
+            && state.producer_id.is_none()
+            && !state.accumulator.is_empty()
+    }
+
+    /// Batch sent to partition was acknowledged or failed, next batch of partition may be sent
+    fn batch_completed(&self, partition: &KafkaPartition, count: usize, size: usize) {
+        self.state.lock().unwrap().accumulator.complete(partition);
+        self.record_completed(count, size);
+        self.wakeup.notify();
+    }
+
+    /// Releases buffer memory of completed records
+    fn record_completed(&self, count: usize, size: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.pending -= count;
+        state.buffered -= size;
+        let _ = self.pending_sender.broadcast(state.pending);
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use partitioner::{RoundRobinPartitioner, StickyPartitioner};
+
+    fn producer_inner(config: ProducerConfig) -> ProducerInner {
+        ProducerInner::new(Arc::new(AsyncClusterClient::without_brokers()), config)
+    }
+
+    #[test]
+    fn keyless_records_cycle_through_partitions_with_round_robin() {
+        let config = ProducerConfig::builder()
+            .partitioner(Arc::new(RoundRobinPartitioner::default()))
+            .build()
+            .unwrap();
+        let inner = producer_inner(config);
+        let record = ProducerRecord::new("t").value("v");
+
+        let partitions: Vec<_> = (0..6)
+            .map(|_| inner.partitioner_partition(&record, 3))
+            .collect();
+        assert_eq!(partitions, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn sticky_partition_changes_when_batch_is_sent() {
+        let config = ProducerConfig::builder()
+            .partitioner(Arc::new(StickyPartitioner::default()))
+            .linger(Duration::from_millis(0))
+            .build()
+            .unwrap();
+        let inner = producer_inner(config);
+        inner.metadata.add_topic("t", 3);
+        let record = || ProducerRecord::new("t").value("v");
+
+        let _delivery = inner.send(record()).await.unwrap();
+        let first = inner.partitioner_partition(&record(), 3);
+        assert_eq!(inner.partitioner_partition(&record(), 3), first);
+
+        inner
+            .state
+            .lock()
+            .unwrap()
+            .accumulator
+            .drain(Instant::now(), true);
+        assert_ne!(inner.partitioner_partition(&record(), 3), first);
+    }
+}
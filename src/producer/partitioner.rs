@@ -0,0 +1,197 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+};
+
+/// Chooses partition of records sent without explicit partition.
+pub trait Partitioner: Send + Sync {
+    /// Partition of record with given key in topic with `partition_count` partitions (always
+    /// positive). Returned partition must be lower than `partition_count`.
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32;
+
+    /// Called when record without key chosen to go to `partition` would start a new batch of
+    /// the partition (previous batch is full or was already sent). Returns partition the record
+    /// goes to instead, if partitioner moves records to another partition.
+    fn on_new_batch(&self, _topic: &str, _partition: i32, _partition_count: i32) -> Option<i32> {
+        None
+    }
+}
+
+/// Java client compatible partitioner. Records with key go to partition chosen by murmur2 hash
+/// of the key, so the same keys land in the same partitions as records produced by Java
+/// services. Records without key are spread by `StickyPartitioner`.
+#[derive(Debug, Default)]
+pub struct DefaultPartitioner {
+    sticky: StickyPartitioner,
+}
+
+impl Partitioner for DefaultPartitioner {
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        match key {
+            Some(key) => to_positive(murmur2(key)) % partition_count,
+            None => self.sticky.partition(topic, None, partition_count),
+        }
+    }
+
+    fn on_new_batch(&self, topic: &str, partition: i32, partition_count: i32) -> Option<i32> {
+        self.sticky.on_new_batch(topic, partition, partition_count)
+    }
+}
+
+/// Sends records of a topic to all its partitions in turns, ignoring keys
+#[derive(Debug, Default)]
+pub struct RoundRobinPartitioner {
+    counters: Mutex<HashMap<String, u32>>,
+}
+
+impl Partitioner for RoundRobinPartitioner {
+    fn partition(&self, topic: &str, _key: Option<&[u8]>, partition_count: i32) -> i32 {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(topic.to_owned()).or_insert(0);
+        let partition = (*counter % partition_count as u32) as i32;
+        *counter = counter.wrapping_add(1);
+        partition
+    }
+}
+
+/// Sticky partitioner (KIP-480), ignores keys. Records of a topic go to the same randomly chosen
+/// partition until its batch is full or sent, then another partition is chosen. Records are
+/// spread evenly over time while filling bigger batches than with round robin.
+#[derive(Debug, Default)]
+pub struct StickyPartitioner {
+    partitions: Mutex<HashMap<String, i32>>,
+}
+
+impl Partitioner for StickyPartitioner {
+    fn partition(&self, topic: &str, _key: Option<&[u8]>, partition_count: i32) -> i32 {
+        let mut partitions = self.partitions.lock().unwrap();
+        match partitions.get(topic) {
+            Some(partition) if *partition < partition_count => *partition,
+            _ => {
+                let partition = random(partition_count);
+                partitions.insert(topic.to_owned(), partition);
+                partition
+            }
+        }
+    }
+
+    fn on_new_batch(&self, topic: &str, partition: i32, partition_count: i32) -> Option<i32> {
+        let mut partitions = self.partitions.lock().unwrap();
+        match partitions.get(topic) {
+            // Another record already moved topic to a new partition
+            Some(current) if *current != partition => return Some(*current),
+            _ if partition_count < 2 => return None,
+            _ => (),
+        }
+        let next = random(partition_count - 1);
+        let next = if next >= partition { next + 1 } else { next };
+        partitions.insert(topic.to_owned(), next);
+        Some(next)
+    }
+}
+
+/// Random number lower than `bound`, drawn from randomly keyed hasher
+fn random(bound: i32) -> i32 {
+    let hash = RandomState::new().build_hasher().finish();
+    (hash % bound as u64) as i32
+}
+
+/// Murmur2 hash as implemented by Java client (`Utils.murmur2`)
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let tail = chunks.remainder();
+    if tail.len() >= 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// Clears sign bit, as Java client does before taking hash modulo partition count
+fn to_positive(n: i32) -> i32 {
+    n & 0x7fff_ffff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn murmur2_matches_java_client() {
+        // Test vectors of Java client's UtilsTest.testMurmur2
+        let cases: &[(&[u8], i32)] = &[
+            (b"21", -973932308),
+            (b"foobar", -790332482),
+            (b"a-little-bit-long-string", -985981536),
+            (b"a-little-bit-longer-string", -1486304829),
+            (
+                b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8",
+                -58897971,
+            ),
+            (b"abc", 479470107),
+        ];
+        for (data, hash) in cases {
+            assert_eq!(murmur2(data), *hash, "{}", String::from_utf8_lossy(data));
+        }
+    }
+
+    #[test]
+    fn keyed_records_are_partitioned_by_key_hash() {
+        let partitioner = DefaultPartitioner::default();
+        assert_eq!(
+            partitioner.partition("t1", Some(b"foobar"), 10),
+            to_positive(-790332482) % 10
+        );
+        assert_eq!(partitioner.partition("t1", Some(b"21"), 1), 0);
+    }
+
+    #[test]
+    fn round_robin_partitions_by_topic() {
+        let partitioner = RoundRobinPartitioner::default();
+        let partitions: Vec<_> = (0..4)
+            .map(|_| partitioner.partition("t1", Some(b"k"), 3))
+            .collect();
+        assert_eq!(partitions, vec![0, 1, 2, 0]);
+        assert_eq!(partitioner.partition("t2", None, 3), 0);
+    }
+
+    #[test]
+    fn sticky_partition_changes_on_new_batch() {
+        let partitioner = StickyPartitioner::default();
+        let first = partitioner.partition("t1", None, 5);
+        assert_eq!(partitioner.partition("t1", Some(b"k"), 5), first);
+
+        let second = partitioner.on_new_batch("t1", first, 5).unwrap();
+        assert_eq!(partitioner.partition("t1", None, 5), second);
+        assert_ne!(second, first);
+        assert!(second < 5);
+        // Stale notification about the previous partition is ignored
+        assert_eq!(partitioner.on_new_batch("t1", first, 5), Some(second));
+        assert_eq!(partitioner.partition("t1", None, 5), second);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{Error, KafkaHeader};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::oneshot;
+
+/// Record to be sent to a topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProducerRecord {
+    pub topic: String,
+    /// Partition chosen by producer's partitioner if not set
+    pub partition: Option<i32>,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<KafkaHeader<'static>>,
+    /// Milliseconds since epoch, time of sending if not set
+    pub timestamp: Option<i64>,
+}
+
+impl ProducerRecord {
+    pub fn new<T: Into<String>>(topic: T) -> Self {
+        ProducerRecord {
+            topic: topic.into(),
+            partition: None,
+            key: None,
+            value: None,
+            headers: Vec::new(),
+            timestamp: None,
+        }
+    }
+
+    pub fn partition(mut self, partition: i32) -> Self {
+        self.partition = Some(partition);
+        self
+    }
+
+    pub fn key<K: Into<Vec<u8>>>(mut self, key: K) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    pub fn value<V: Into<Vec<u8>>>(mut self, value: V) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    pub fn header<K: Into<String>, V: Into<Vec<u8>>>(mut self, key: K, value: V) -> Self {
+        self.headers.push(KafkaHeader {
+            key: key.into().into(),
+            value: value.into().into(),
+        });
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Approximate size of record in a batch
+    pub(super) fn size(&self) -> usize {
+        const RECORD_OVERHEAD: usize = 16;
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|h| h.key.len() + h.value.len() + 4)
+            .sum();
+        RECORD_OVERHEAD
+            + self.key.as_ref().map_or(0, Vec::len)
+            + self.value.as_ref().map_or(0, Vec::len)
+            + headers
+    }
+}
+
+/// Position of record acknowledged by partition leader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordMetadata {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since epoch, create time or log append time depending on topic config
+    pub timestamp: i64,
+}
+
+/// Completes when record is acknowledged or fails to be sent
+#[derive(Debug)]
+pub struct DeliveryFuture {
+    receiver: oneshot::Receiver<Result<RecordMetadata, Error>>,
+}
+
+impl DeliveryFuture {
+    pub(super) fn new() -> (oneshot::Sender<Result<RecordMetadata, Error>>, Self) {
+        let (sender, receiver) = oneshot::channel();
+        (sender, DeliveryFuture { receiver })
+    }
+}
+
+impl Future for DeliveryFuture {
+    type Output = Result<RecordMetadata, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match futures::ready!(Pin::new(&mut self.receiver).poll(cx)) {
+            Ok(result) => Poll::Ready(result),
+            // Record was dropped with producer task
+            Err(_) => Poll::Ready(Err(Error::ProducerClosed)),
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use super::{
+    accumulator::ProducerBatch,
+    idempotence::{self, Recovery},
+    Acks, ProducerInner,
+};
+use crate::{backoff::Backoff, Error};
+use futures::{future, prelude::*, stream::FuturesUnordered};
+use log::{debug, warn};
+use rskafka_proto::{
+    apis::produce::{ProducePartition, ProduceRequestV3, ProduceTopic},
+    BrokerId, ErrorCode,
+};
+use rskafka_wire_format::prelude::*;
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Instant};
+use tokio::time;
+
+/// Background task sending ready batches. Batches for different brokers (and batches which
+/// became ready while a request is in flight) are sent concurrently.
+pub(super) async fn run(inner: Arc<ProducerInner>) {
+    let mut requests = FuturesUnordered::new();
+    let mut backoff = Backoff::new(inner.config.retry_backoff, inner.config.retry_backoff_max);
+    loop {
+        let expired = inner
+            .state
+            .lock()
+            .unwrap()
+            .accumulator
+            .expire(Instant::now());
+        for batch in expired {
+            let (count, size) = (batch.record_count(), batch.size());
+            discard(&inner, batch, &Error::DeliveryTimeout).await;
+            inner.record_completed(count, size);
+        }
+
+        if inner.needs_producer_id() {
+            let timeout_ms = inner.config.transaction_timeout_ms;
+            let result =
+                idempotence::init_producer_id(&inner.cluster, timeout_ms, inner.request_timeout())
+                    .await;
+            match result {
+                Ok(producer_id) => {
+                    debug!(
+                        "Initialized idempotent producer: id {}, epoch {}",
+                        producer_id.id, producer_id.epoch
+                    );
+                    inner.state.lock().unwrap().producer_id = Some(producer_id);
+                    backoff.reset();
+                }
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "Obtaining producer id failed: {}, retrying in {:?}",
+                        e, delay
+                    );
+                    time::delay_for(delay).await;
+                    continue;
+                }
+            }
+        }
+
+        let (batches, due, closed) = ready_batches(&inner);
+        if !batches.is_empty() {
+            requests.push(send_batches(Arc::clone(&inner), batches));
+            continue;
+        }
+        if closed && requests.is_empty() {
+            break;
+        }
+
+        let linger = async {
+            match due {
+                Some(due) => time::delay_until(due.into()).await,
+                None => future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = inner.wakeup.notified() => (),
+            _ = requests.next(), if !requests.is_empty() => (),
+            _ = linger => (),
+        }
+    }
+    debug!("Producer stopped");
+}
+
+/// Takes ready batches stamped with producer id and sequence numbers (retried batches keep
+/// theirs unless producer id changed). Also returns when the next batch becomes ready and
+/// whether producer is closed with no batches left.
+///
+/// Batches of idempotent producer are not taken without producer id, which may have been reset
+/// by a failed batch since it was last checked.
+fn ready_batches(inner: &ProducerInner) -> (Vec<ProducerBatch>, Option<Instant>, bool) {
+    let mut state = inner.state.lock().unwrap();
+    if inner.config.idempotence && state.producer_id.is_none() {
+        let closed = state.closed && state.accumulator.is_empty();
+        return (Vec::new(), None, closed);
+    }
+    let flush = state.flushing || state.closed;
+    let mut batches = state.accumulator.drain(Instant::now(), flush);
+    if state.accumulator.is_empty() {
+        state.flushing = false;
+    }
+    if let Some(producer_id) = state.producer_id.as_mut() {
+        for batch in batches.iter_mut() {
+            if producer_id.owns(batch) {
+                continue;
+            }
+            batch.producer_id = producer_id.id;
+            batch.producer_epoch = producer_id.epoch;
+            batch.base_sequence = producer_id.next_sequence(&batch.partition, batch.record_count());
+            batch.transactional = inner.transaction.is_some();
+        }
+    }
+    let closed = state.closed && state.accumulator.is_empty();
+    (batches, state.accumulator.next_due(), closed)
+}
+
+async fn send_batches(inner: Arc<ProducerInner>, batches: Vec<ProducerBatch>) {
+    // Partitions have to be part of transaction before records are produced to them
+    if let Some(transaction) = inner.transaction.as_ref() {
+        let partitions: Vec<_> = batches.iter().map(|b| b.partition.clone()).collect();
+        let result = transaction.lock().await.add_partitions(&partitions).await;
+        if let Err(e) = result {
+            for batch in batches {
+                fail(&inner, batch, &e).await;
+            }
+            return;
+        }
+    }
+
+    let mut brokers: HashMap<BrokerId, Vec<ProducerBatch>> = HashMap::new();
+    for batch in batches {
+        match inner
+            .metadata
+            .leader(&inner.cluster, &batch.partition)
+            .await
+        {
+            Ok(leader) => brokers.entry(leader).or_default().push(batch),
+            Err(e) => fail(&inner, batch, &e).await,
+        }
+    }
+    let requests = brokers
+        .into_iter()
+        .map(|(broker, batches)| produce(&inner, broker, batches));
+    future::join_all(requests).await;
+}
+
+async fn produce(inner: &ProducerInner, broker: BrokerId, batches: Vec<ProducerBatch>) {
+    let request = build_request(inner, &batches);
+    let timeout = inner.request_timeout();
+    if inner.config.acks == Acks::None {
+        // Broker does not respond, records are completed once they are written to connection
+        let result = inner
+            .cluster
+            .send_request(request, Some(broker), timeout)
+            .await;
+        for batch in batches {
+            match &result {
+                Ok(()) => inner.complete_batch(batch, -1, -1),
+                Err(e) => {
+                    inner.metadata.invalidate(&batch.partition.topic_name);
+                    recover(inner, batch, e).await;
+                }
+            }
+        }
+        return;
+    }
+
+    let response = match inner
+        .cluster
+        .make_request_timeout(request, Some(broker), timeout)
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            for batch in batches {
+                // Leader may be down, it is looked up again for next batches
+                inner.metadata.invalidate(&batch.partition.topic_name);
+                recover(inner, batch, &e).await;
+            }
+            return;
+        }
+    };
+
+    for batch in batches {
+        let result = response
+            .topics
+            .iter()
+            .find(|t| t.name == batch.partition.topic_name)
+            .and_then(|t| {
+                t.partitions
+                    .iter()
+                    .find(|p| p.index == batch.partition.partition_index)
+            });
+        match result {
+            // Duplicate was already written by an earlier attempt
+            Some(p)
+                if p.error_code == ErrorCode::None
+                    || p.error_code == ErrorCode::DuplicateSequenceNumber =>
+            {
+                inner.complete_batch(batch, p.base_offset, p.log_append_time_ms)
+            }
+            Some(p) => {
+                inner.metadata.invalidate(&batch.partition.topic_name);
+                recover(inner, batch, &p.error_code.into()).await;
+            }
+            None => {
+                let e = Error::ProtocolError("partition missing in produce response".into());
+                fail(inner, batch, &e).await;
+            }
+        }
+    }
+}
+
+fn build_request<'a>(
+    inner: &'a ProducerInner,
+    batches: &'a [ProducerBatch],
+) -> ProduceRequestV3<'a> {
+    let mut topics: Vec<ProduceTopic> = Vec::new();
+    for batch in batches {
+        let partition = ProducePartition {
+            index: batch.partition.partition_index,
+            records: NullableBytes::from(Some(batch.to_bytes())),
+        };
+        match topics
+            .iter_mut()
+            .find(|t| t.name == batch.partition.topic_name)
+        {
+            Some(topic) => topic.partitions.push(partition),
+            None => topics.push(ProduceTopic {
+                name: Cow::Borrowed(&batch.partition.topic_name),
+                partitions: vec![partition],
+            }),
+        }
+    }
+
+    ProduceRequestV3 {
+        transactional_id: inner.config.transactional_id.as_deref().into(),
+        acks: inner.config.acks.value(),
+        timeout_ms: inner.config.request_timeout_ms,
+        topics,
+    }
+}
+
+/// Retries batch which failed with retriable error until its delivery timeout passes, otherwise
+/// fails it. Batch of idempotent producer is retried only if it can't be duplicated.
+async fn recover(inner: &ProducerInner, batch: ProducerBatch, error: &Error) {
+    let recovery = match inner.state.lock().unwrap().producer_id.as_ref() {
+        Some(producer_id) if producer_id.owns(&batch) => {
+            Recovery::after(error, producer_id.is_next_expected(&batch))
+        }
+        _ if idempotence::is_retriable(error) => Recovery::Retry,
+        _ => Recovery::Fail,
+    };
+    match recovery {
+        Recovery::Retry if inner.delivery_expired(&batch) => {
+            warn!(
+                "Sending {} records to {} failed: {}, not retrying after delivery timeout",
+                batch.record_count(),
+                batch.partition,
+                error
+            );
+            fail(inner, batch, &Error::DeliveryTimeout).await;
+        }
+        Recovery::Retry => {
+            debug!(
+                "Sending {} records to {} failed: {}, retrying",
+                batch.record_count(),
+                batch.partition,
+                error
+            );
+            inner.retry_batch(batch);
+        }
+        Recovery::ResetProducerId => {
+            warn!("Broker lost sequence of {}", batch.partition);
+            fail(inner, batch, error).await;
+        }
+        Recovery::Fail => fail(inner, batch, error).await,
+    }
+}
+
+/// Fails records of batch which was in flight
+async fn fail(inner: &ProducerInner, batch: ProducerBatch, error: &Error) {
+    let partition = batch.partition.clone();
+    let (count, size) = (batch.record_count(), batch.size());
+    discard(inner, batch, error).await;
+    inner.batch_completed(&partition, count, size);
+}
+
+/// Fails records of batch. Failed batch can't be committed in transaction.
+///
+/// Broker would reject later batches of idempotent producer as out of order once sequence
+/// numbers of a batch are lost, so producer gets new producer id. Transactional producer gets
+/// new epoch when the failed transaction is aborted.
+async fn discard(inner: &ProducerInner, batch: ProducerBatch, error: &Error) {
+    warn!(
+        "Sending {} records to {} failed: {}",
+        batch.record_count(),
+        batch.partition,
+        error
+    );
+    if let Some(transaction) = inner.transaction.as_ref() {
+        transaction.lock().await.fail(error);
+        inner
+            .state
+            .lock()
+            .unwrap()
+            .transaction_error
+            .get_or_insert_with(|| error.to_string());
+    } else {
+        let mut state = inner.state.lock().unwrap();
+        if matches!(state.producer_id.as_ref(), Some(p) if p.owns(&batch)) {
+            debug!(
+                "Resetting producer id after sequence of {} was lost",
+                batch.partition
+            );
+            state.producer_id = None;
+        }
+    }
+    batch.fail(error);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{client::AsyncClusterClient, ProducerConfig, ProducerRecord};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn idempotent_batches_wait_for_producer_id() {
+        let config = ProducerConfig::builder()
+            .idempotence(true)
+            .linger(Duration::from_millis(0))
+            .build()
+            .unwrap();
+        let inner = ProducerInner::new(Arc::new(AsyncClusterClient::without_brokers()), config);
+        inner.metadata.add_topic("t", 1);
+        let _delivery = inner
+            .send(ProducerRecord::new("t").partition(0).value("v"))
+            .await
+            .unwrap();
+
+        // Producer id was not obtained yet
+        let (batches, _, closed) = ready_batches(&inner);
+        assert!(batches.is_empty());
+        assert!(!closed);
+
+        inner.set_producer_id(7, 0);
+        let (batches, _, _) = ready_batches(&inner);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].producer_id, 7);
+        assert_eq!(batches[0].base_sequence, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_batch_resets_producer_id() {
+        let config = ProducerConfig::builder()
+            .idempotence(true)
+            .linger(Duration::from_millis(0))
+            .build()
+            .unwrap();
+        let inner = ProducerInner::new(Arc::new(AsyncClusterClient::without_brokers()), config);
+        inner.metadata.add_topic("t", 1);
+        inner.set_producer_id(7, 0);
+        let delivery = inner
+            .send(ProducerRecord::new("t").partition(0).value("v"))
+            .await
+            .unwrap();
+        let (mut batches, _, _) = ready_batches(&inner);
+
+        fail(
+            &inner,
+            batches.remove(0),
+            &ErrorCode::MessageTooLarge.into(),
+        )
+        .await;
+        assert!(delivery.await.is_err());
+        assert!(inner.state.lock().unwrap().producer_id.is_none());
+    }
+}
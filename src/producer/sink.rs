@@ -0,0 +1,204 @@
+use super::{DeliveryFuture, ProducerInner, ProducerRecord};
+use crate::Error;
+use futures::{prelude::*, stream::FuturesUnordered};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+type SendFuture = Pin<Box<dyn Future<Output = Result<DeliveryFuture, Error>> + Send>>;
+
+/// Sink of records sent with `Producer`, obtained from `Producer::sink`.
+///
+/// Sink is ready for the next record once the previous one is appended to its batch, which waits
+/// while producer's buffer memory is full, so `Stream::forward` is slowed down to the pace
+/// records are acknowledged. Flush sends batches without waiting for linger and completes once
+/// all records sent through the sink are acknowledged.
+///
+/// Sink fails with error of the first record which failed to be delivered.
+pub struct ProducerSink {
+    inner: Arc<ProducerInner>,
+    /// Record being appended to its batch
+    sending: Option<SendFuture>,
+    deliveries: FuturesUnordered<DeliveryFuture>,
+}
+
+impl ProducerSink {
+    pub(super) fn new(inner: Arc<ProducerInner>) -> Self {
+        ProducerSink {
+            inner,
+            sending: None,
+            deliveries: FuturesUnordered::new(),
+        }
+    }
+
+    fn poll_sending(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        if let Some(sending) = self.sending.as_mut() {
+            let result = futures::ready!(sending.as_mut().poll(cx));
+            self.sending = None;
+            self.deliveries.push(result?);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Takes deliveries completed so far
+    fn poll_delivered(&mut self, cx: &mut Context) -> Result<(), Error> {
+        while let Poll::Ready(Some(result)) = self.deliveries.poll_next_unpin(cx) {
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink<ProducerRecord> for ProducerSink {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        futures::ready!(self.poll_sending(cx))?;
+        Poll::Ready(self.poll_delivered(cx))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: ProducerRecord) -> Result<(), Self::Error> {
+        let inner = Arc::clone(&self.inner);
+        self.sending = Some(Box::pin(async move { inner.send(item).await }));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        futures::ready!(self.poll_sending(cx))?;
+        self.poll_delivered(cx)?;
+        if self.deliveries.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        self.inner.request_flush();
+        while let Some(result) = futures::ready!(self.deliveries.poll_next_unpin(cx)) {
+            result?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{client::AsyncClusterClient, ProducerConfig};
+    use futures::task::noop_waker;
+    use rskafka_proto::ErrorCode;
+    use std::time::Instant;
+
+    fn record(partition: i32) -> ProducerRecord {
+        ProducerRecord::new("t").partition(partition).value("v")
+    }
+
+    /// Producer without sender task, with buffer memory for two records
+    fn producer_inner() -> Arc<ProducerInner> {
+        let size = record(0).size();
+        let config = ProducerConfig::builder()
+            .batch_size(size)
+            .buffer_memory(2 * size)
+            .build()
+            .unwrap();
+        let inner = ProducerInner::new(Arc::new(AsyncClusterClient::without_brokers()), config);
+        inner.metadata.add_topic("t", 2);
+        Arc::new(inner)
+    }
+
+    /// Completes sent batches as the sender task does after they are acknowledged
+    fn acknowledge(inner: &ProducerInner, partition: i32) {
+        let batches = inner
+            .state
+            .lock()
+            .unwrap()
+            .accumulator
+            .drain(Instant::now(), true);
+        for batch in batches {
+            if batch.partition.partition_index == partition {
+                inner.complete_batch(batch, 0, -1);
+            } else {
+                inner.retry_batch(batch);
+            }
+        }
+    }
+
+    fn send(sink: &mut ProducerSink, cx: &mut Context, record: ProducerRecord) {
+        assert!(matches!(
+            Pin::new(&mut *sink).poll_ready(cx),
+            Poll::Ready(Ok(()))
+        ));
+        Pin::new(&mut *sink).start_send(record).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sink_is_not_ready_while_buffer_memory_is_full() {
+        let inner = producer_inner();
+        let mut sink = ProducerSink::new(Arc::clone(&inner));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        send(&mut sink, &mut cx, record(0));
+        send(&mut sink, &mut cx, record(0));
+        send(&mut sink, &mut cx, record(0));
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_pending());
+
+        // First record released its memory, the third one is appended
+        acknowledge(&inner, 0);
+        assert!(matches!(
+            Pin::new(&mut sink).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(inner.state.lock().unwrap().pending, 2);
+    }
+
+    #[tokio::test]
+    async fn flush_waits_for_records_sent_through_sink() {
+        let inner = producer_inner();
+        let mut sink = ProducerSink::new(Arc::clone(&inner));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        // Sent with producer, not through the sink
+        let _other = inner.send(record(1)).await.unwrap();
+
+        send(&mut sink, &mut cx, record(0));
+        assert!(Pin::new(&mut sink).poll_flush(&mut cx).is_pending());
+        assert!(inner.state.lock().unwrap().flushing);
+
+        acknowledge(&inner, 0);
+        assert!(matches!(
+            Pin::new(&mut sink).poll_flush(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(inner.state.lock().unwrap().pending, 1);
+    }
+
+    #[tokio::test]
+    async fn sink_fails_with_first_delivery_error() {
+        let inner = producer_inner();
+        let mut sink = ProducerSink::new(Arc::clone(&inner));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        send(&mut sink, &mut cx, record(0));
+        send(&mut sink, &mut cx, record(1));
+        let batches = inner
+            .state
+            .lock()
+            .unwrap()
+            .accumulator
+            .drain(Instant::now(), true);
+        for batch in batches {
+            let (count, size) = (batch.record_count(), batch.size());
+            batch.fail(&ErrorCode::MessageTooLarge.into());
+            inner.record_completed(count, size);
+        }
+
+        assert!(matches!(
+            Pin::new(&mut sink).poll_ready(&mut cx),
+            Poll::Ready(Err(Error::ErrorResponse(ErrorCode::MessageTooLarge, _)))
+        ));
+    }
+}
@@ -0,0 +1,598 @@
+use super::ProducerConfig;
+use crate::{
+    backoff::Backoff, client::AsyncClusterClient, consumer::ConsumerGroupMetadata, Error,
+    KafkaOffset, KafkaPartition,
+};
+use log::{debug, info, warn};
+use rskafka_proto::{
+    apis::{
+        add_offsets_to_txn::AddOffsetsToTxnRequestV1,
+        add_partitions_to_txn::{AddPartitionsToTxnRequestV1, AddPartitionsToTxnTopic},
+        end_txn::EndTxnRequestV1,
+        find_coordinator::{FindCoordinatorRequestV2, KeyType},
+        init_producer_id::InitProducerIdRequestV1,
+        txn_offset_commit::{
+            TxnOffsetCommitPartition, TxnOffsetCommitRequestV3, TxnOffsetCommitTopic,
+        },
+    },
+    BrokerId, ErrorCode, KafkaRequest,
+};
+use rskafka_wire_format::{prelude::*, CompactArray, CompactNullableString, TaggedFields};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TransactionState {
+    /// Producer id was not obtained yet, see `Producer::init_transactions`
+    Uninitialized,
+    Ready,
+    InTransaction,
+    Committing,
+    Aborting,
+    /// Some records of transaction failed to be sent, transaction can only be aborted
+    AbortableError,
+    /// Another producer with the same transactional id was initialized
+    Fenced,
+}
+
+/// Transaction of producer with transactional id, coordinated by the transaction coordinator
+/// broker.
+///
+/// Requests to coordinator are retried while it is unavailable or moving (and while a previous
+/// transaction is being completed) until transaction timeout passes. Fencing errors put producer
+/// into `Fenced` state in which every operation fails with `Error::ProducerFenced`.
+pub(super) struct TransactionManager {
+    cluster: Arc<AsyncClusterClient>,
+    transactional_id: String,
+    transaction_timeout: Duration,
+    request_timeout: Duration,
+    retry_backoff: (Duration, Duration),
+    coordinator: Coordinator,
+    producer_id: i64,
+    producer_epoch: i16,
+    state: TransactionState,
+    /// Partitions added to the ongoing transaction
+    partitions: HashSet<KafkaPartition>,
+    offsets_added: bool,
+}
+
+impl TransactionManager {
+    pub fn new(
+        cluster: Arc<AsyncClusterClient>,
+        transactional_id: String,
+        config: &ProducerConfig,
+    ) -> Self {
+        TransactionManager {
+            cluster,
+            coordinator: Coordinator::new(transactional_id.clone(), KeyType::Transaction),
+            transactional_id,
+            transaction_timeout: Duration::from_millis(config.transaction_timeout_ms as u64),
+            request_timeout: Duration::from_millis(config.request_timeout_ms as u64),
+            retry_backoff: (config.retry_backoff, config.retry_backoff_max),
+            producer_id: -1,
+            producer_epoch: -1,
+            state: TransactionState::Uninitialized,
+            partitions: HashSet::new(),
+            offsets_added: false,
+        }
+    }
+
+    pub fn state(&self) -> TransactionState {
+        self.state
+    }
+
+    /// Obtains producer id and epoch, fencing older producers with the same transactional id.
+    /// Transaction left open by them is completed by coordinator.
+    pub async fn init(&mut self) -> Result<(i64, i16), Error> {
+        let request = InitProducerIdRequestV1 {
+            transactional_id: NullableString::with_owned(self.transactional_id.clone()),
+            transaction_timeout_ms: self.transaction_timeout.as_millis() as i32,
+        };
+        let response = self.send(request, |r| r.error_code).await?;
+        info!(
+            "Initialized transactional producer {}: id {}, epoch {}",
+            self.transactional_id, response.producer_id, response.producer_epoch
+        );
+        self.producer_id = response.producer_id;
+        self.producer_epoch = response.producer_epoch;
+        self.state = TransactionState::Ready;
+        Ok((self.producer_id, self.producer_epoch))
+    }
+
+    pub fn begin(&mut self) -> Result<(), Error> {
+        match self.state {
+            TransactionState::Ready => {
+                self.state = TransactionState::InTransaction;
+                self.partitions.clear();
+                self.offsets_added = false;
+                Ok(())
+            }
+            TransactionState::InTransaction => Err(invalid_state("transaction already started")),
+            _ => self.ensure_in_transaction(),
+        }
+    }
+
+    /// Fails unless records may be sent as part of a transaction
+    pub fn ensure_in_transaction(&self) -> Result<(), Error> {
+        match self.state {
+            TransactionState::InTransaction => Ok(()),
+            TransactionState::Uninitialized => {
+                Err(invalid_state("transactions were not initialized"))
+            }
+            TransactionState::Ready => Err(invalid_state("no transaction in progress")),
+            TransactionState::Committing | TransactionState::Aborting => {
+                Err(invalid_state("transaction is being completed"))
+            }
+            TransactionState::AbortableError => {
+                Err(invalid_state("transaction failed and has to be aborted"))
+            }
+            TransactionState::Fenced => Err(Error::ProducerFenced),
+        }
+    }
+
+    /// Adds partitions to transaction before records are produced to them
+    pub async fn add_partitions(&mut self, partitions: &[KafkaPartition]) -> Result<(), Error> {
+        match self.state {
+            TransactionState::InTransaction | TransactionState::Committing => (),
+            _ => return self.ensure_in_transaction(),
+        }
+        let mut topics: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+        for p in partitions.iter().filter(|p| !self.partitions.contains(*p)) {
+            topics
+                .entry(p.topic_name.as_str())
+                .or_default()
+                .push(p.partition_index);
+        }
+        if topics.is_empty() {
+            return Ok(());
+        }
+
+        let request = AddPartitionsToTxnRequestV1 {
+            transactional_id: Cow::Owned(self.transactional_id.clone()),
+            producer_id: self.producer_id,
+            producer_epoch: self.producer_epoch,
+            topics: topics
+                .into_iter()
+                .map(|(name, partitions)| AddPartitionsToTxnTopic {
+                    name: Cow::Owned(name.to_owned()),
+                    partitions,
+                })
+                .collect(),
+        };
+        self.send(request, |r| {
+            first_error(
+                r.topics
+                    .iter()
+                    .flat_map(|t| t.partitions.iter().map(|p| p.error_code)),
+            )
+        })
+        .await?;
+        debug!("Added {:?} to transaction", partitions);
+        self.partitions.extend(partitions.iter().cloned());
+        Ok(())
+    }
+
+    /// Commits consumed offsets as part of transaction, see `Producer::send_offsets_to_transaction`
+    pub async fn send_offsets(
+        &mut self,
+        offsets: &[KafkaOffset<'_>],
+        group: &ConsumerGroupMetadata,
+    ) -> Result<(), Error> {
+        self.ensure_in_transaction()?;
+        let request = AddOffsetsToTxnRequestV1 {
+            transactional_id: Cow::Owned(self.transactional_id.clone()),
+            producer_id: self.producer_id,
+            producer_epoch: self.producer_epoch,
+            group_id: Cow::Owned(group.group_id.clone()),
+        };
+        self.send(request, |r| r.error_code).await?;
+        self.offsets_added = true;
+
+        let mut topics: BTreeMap<&str, Vec<TxnOffsetCommitPartition>> = BTreeMap::new();
+        for o in offsets {
+            topics
+                .entry(o.topic.as_ref())
+                .or_default()
+                .push(TxnOffsetCommitPartition {
+                    partition_index: o.partition,
+                    committed_offset: o.offset,
+                    committed_leader_epoch: -1,
+                    committed_metadata: CompactNullableString::with_null(),
+                    tagged_fields: TaggedFields,
+                });
+        }
+        let request = TxnOffsetCommitRequestV3 {
+            transactional_id: self.transactional_id.as_str().into(),
+            group_id: group.group_id.as_str().into(),
+            producer_id: self.producer_id,
+            producer_epoch: self.producer_epoch,
+            generation_id: group.generation_id,
+            member_id: group.member_id.as_str().into(),
+            group_instance_id: group.group_instance_id.as_deref().into(),
+            topics: CompactArray(
+                topics
+                    .into_iter()
+                    .map(|(name, partitions)| TxnOffsetCommitTopic {
+                        name: name.into(),
+                        partitions: CompactArray(partitions),
+                        tagged_fields: TaggedFields,
+                    })
+                    .collect(),
+            ),
+            tagged_fields: TaggedFields,
+        };
+        let mut group_coordinator = Coordinator::new(group.group_id.clone(), KeyType::Group);
+        let result = coordinator_request(
+            &self.cluster,
+            &mut group_coordinator,
+            self.backoff(),
+            Instant::now() + self.transaction_timeout,
+            self.request_timeout,
+            request,
+            |r| {
+                first_error(
+                    r.topics
+                        .0
+                        .iter()
+                        .flat_map(|t| t.partitions.0.iter().map(|p| p.error_code)),
+                )
+            },
+        )
+        .await;
+        let result = self.check_fenced(result);
+        self.check_group_member(result, group)?;
+        Ok(())
+    }
+
+    pub fn start_commit(&mut self) -> Result<(), Error> {
+        if self.state != TransactionState::Committing {
+            self.ensure_in_transaction()?;
+        }
+        self.state = TransactionState::Committing;
+        Ok(())
+    }
+
+    pub fn start_abort(&mut self) -> Result<(), Error> {
+        match self.state {
+            TransactionState::InTransaction
+            | TransactionState::Committing
+            | TransactionState::Aborting
+            | TransactionState::AbortableError => {
+                self.state = TransactionState::Aborting;
+                Ok(())
+            }
+            _ => self.ensure_in_transaction(),
+        }
+    }
+
+    /// Records of transaction failed to be sent, transaction can't be committed anymore
+    pub fn fail(&mut self, error: &Error) {
+        match (self.state, error) {
+            (_, Error::ErrorResponse(code, _)) if code.is_producer_fenced() => {
+                self.state = TransactionState::Fenced
+            }
+            (TransactionState::InTransaction, _) | (TransactionState::Committing, _) => {
+                self.state = TransactionState::AbortableError
+            }
+            _ => (),
+        }
+    }
+
+    /// Commits or aborts transaction started by `start_commit` or `start_abort`
+    pub async fn end(&mut self, commit: bool) -> Result<(), Error> {
+        if !self.partitions.is_empty() || self.offsets_added {
+            let request = EndTxnRequestV1 {
+                transactional_id: Cow::Owned(self.transactional_id.clone()),
+                producer_id: self.producer_id,
+                producer_epoch: self.producer_epoch,
+                committed: commit,
+            };
+            self.send(request, |r| r.error_code).await?;
+        }
+        self.state = TransactionState::Ready;
+        self.partitions.clear();
+        self.offsets_added = false;
+        Ok(())
+    }
+
+    async fn send<R>(
+        &mut self,
+        request: R,
+        error_code: fn(&R::Response) -> ErrorCode,
+    ) -> Result<R::Response, Error>
+    where
+        R: KafkaRequest + Clone,
+    {
+        let backoff = self.backoff();
+        let result = coordinator_request(
+            &self.cluster,
+            &mut self.coordinator,
+            backoff,
+            Instant::now() + self.transaction_timeout,
+            self.request_timeout,
+            request,
+            error_code,
+        )
+        .await;
+        self.check_fenced(result)
+    }
+
+    fn check_fenced<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        match result {
+            Err(Error::ErrorResponse(code, _)) if code.is_producer_fenced() => {
+                warn!(
+                    "Transactional producer {} was fenced: {}",
+                    self.transactional_id, code
+                );
+                self.state = TransactionState::Fenced;
+                Err(Error::ProducerFenced)
+            }
+            result => result,
+        }
+    }
+
+    /// Offsets of consumer which is no longer member of the group generation are rejected, the
+    /// transaction can only be aborted
+    fn check_group_member<T>(
+        &mut self,
+        result: Result<T, Error>,
+        group: &ConsumerGroupMetadata,
+    ) -> Result<T, Error> {
+        match result {
+            Err(Error::ErrorResponse(code, _))
+                if code == ErrorCode::IllegalGeneration
+                    || code == ErrorCode::UnknownMemberId
+                    || code == ErrorCode::FencedInstanceId =>
+            {
+                warn!(
+                    "Offsets of member {} of group {} generation {} rejected: {}",
+                    group.member_id, group.group_id, group.generation_id, code
+                );
+                self.state = TransactionState::AbortableError;
+                let message = format!(
+                    "consumer is no longer member of group {} generation {}",
+                    group.group_id, group.generation_id
+                );
+                Err((code, Some(message)).into())
+            }
+            result => result,
+        }
+    }
+
+    fn backoff(&self) -> Backoff {
+        Backoff::new(self.retry_backoff.0, self.retry_backoff.1)
+    }
+}
+
+/// Coordinator of transaction or consumer group
+struct Coordinator {
+    key: String,
+    key_type: KeyType,
+    broker: Option<BrokerId>,
+}
+
+impl Coordinator {
+    fn new(key: String, key_type: KeyType) -> Self {
+        Coordinator {
+            key,
+            key_type,
+            broker: None,
+        }
+    }
+
+    async fn find(
+        &mut self,
+        cluster: &AsyncClusterClient,
+        request_timeout: Duration,
+    ) -> Result<BrokerId, Error> {
+        if let Some(broker) = self.broker {
+            return Ok(broker);
+        }
+        let request = FindCoordinatorRequestV2 {
+            key: self.key.clone(),
+            key_type: self.key_type,
+        };
+        let response = cluster
+            .make_request_timeout(request, None, request_timeout)
+            .await?;
+        match response.error_code {
+            ErrorCode::None => {
+                debug!(
+                    "Found coordinator of {:?} {}: {}",
+                    self.key_type, self.key, response.node_id
+                );
+                self.broker = Some(response.node_id);
+                Ok(response.node_id)
+            }
+            code => Err((code, response.error_message.into_owned_option()).into()),
+        }
+    }
+}
+
+/// Sends request to coordinator, finding it again after it moved. Requests failing with
+/// retriable errors (including requests timed out after `request_timeout`) are retried until
+/// deadline passes.
+async fn coordinator_request<R>(
+    cluster: &AsyncClusterClient,
+    coordinator: &mut Coordinator,
+    mut backoff: Backoff,
+    deadline: Instant,
+    request_timeout: Duration,
+    request: R,
+    error_code: fn(&R::Response) -> ErrorCode,
+) -> Result<R::Response, Error>
+where
+    R: KafkaRequest + Clone,
+{
+    loop {
+        let result = match coordinator.find(cluster, request_timeout).await {
+            Ok(broker) => {
+                cluster
+                    .make_request_timeout(request.clone(), Some(broker), request_timeout)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let error = match result {
+            Ok(response) => match error_code(&response) {
+                ErrorCode::None => return Ok(response),
+                code => Error::from(code),
+            },
+            Err(e) => e,
+        };
+
+        let retriable = match &error {
+            Error::ErrorResponse(code, _) if code.is_coordinator_error() => {
+                coordinator.broker = None;
+                true
+            }
+            Error::Io(_) => {
+                coordinator.broker = None;
+                true
+            }
+            Error::ErrorResponse(ErrorCode::ConcurrentTransactions, _) => true,
+            _ => false,
+        };
+        let delay = backoff.next_delay();
+        if !retriable || Instant::now() + delay > deadline {
+            return Err(error);
+        }
+        warn!(
+            "Request to coordinator of {} failed: {}, retrying in {:?}",
+            coordinator.key, error, delay
+        );
+        time::delay_for(delay).await;
+    }
+}
+
+fn first_error<I: IntoIterator<Item = ErrorCode>>(codes: I) -> ErrorCode {
+    codes
+        .into_iter()
+        .find(|code| *code != ErrorCode::None)
+        .unwrap_or(ErrorCode::None)
+}
+
+fn invalid_state(message: &'static str) -> Error {
+    Error::InvalidTransactionState(message.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transaction_manager() -> TransactionManager {
+        let mut transaction = TransactionManager::new(
+            Arc::new(AsyncClusterClient::without_brokers()),
+            "tx".into(),
+            &ProducerConfig::default(),
+        );
+        // As after successful `init`
+        transaction.state = TransactionState::Ready;
+        transaction
+    }
+
+    fn group() -> ConsumerGroupMetadata {
+        ConsumerGroupMetadata {
+            group_id: "group".into(),
+            generation_id: 3,
+            member_id: "member".into(),
+            group_instance_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn transaction_is_committed_or_aborted() {
+        let mut transaction = transaction_manager();
+        transaction.begin().unwrap();
+        assert!(transaction.ensure_in_transaction().is_ok());
+        assert!(matches!(
+            transaction.begin(),
+            Err(Error::InvalidTransactionState(_))
+        ));
+
+        transaction.start_commit().unwrap();
+        assert_eq!(transaction.state(), TransactionState::Committing);
+        assert!(transaction.ensure_in_transaction().is_err());
+        transaction.end(true).await.unwrap();
+        assert_eq!(transaction.state(), TransactionState::Ready);
+
+        transaction.begin().unwrap();
+        transaction.start_abort().unwrap();
+        assert_eq!(transaction.state(), TransactionState::Aborting);
+        transaction.end(false).await.unwrap();
+        assert_eq!(transaction.state(), TransactionState::Ready);
+    }
+
+    #[test]
+    fn transaction_requires_init() {
+        let mut transaction = transaction_manager();
+        transaction.state = TransactionState::Uninitialized;
+        assert!(matches!(
+            transaction.begin(),
+            Err(Error::InvalidTransactionState(_))
+        ));
+    }
+
+    #[test]
+    fn failed_transaction_can_only_be_aborted() {
+        let mut transaction = transaction_manager();
+        transaction.begin().unwrap();
+        transaction.fail(&Error::from(ErrorCode::NotEnoughReplicas));
+        assert_eq!(transaction.state(), TransactionState::AbortableError);
+
+        assert!(matches!(
+            transaction.ensure_in_transaction(),
+            Err(Error::InvalidTransactionState(_))
+        ));
+        assert!(transaction.start_commit().is_err());
+        transaction.start_abort().unwrap();
+        assert_eq!(transaction.state(), TransactionState::Aborting);
+    }
+
+    #[test]
+    fn fenced_producer_fails_every_operation() {
+        let mut transaction = transaction_manager();
+        transaction.begin().unwrap();
+        transaction.fail(&Error::from(ErrorCode::ProducerFenced));
+        assert_eq!(transaction.state(), TransactionState::Fenced);
+
+        assert!(matches!(
+            transaction.ensure_in_transaction(),
+            Err(Error::ProducerFenced)
+        ));
+        assert!(matches!(transaction.begin(), Err(Error::ProducerFenced)));
+        assert!(matches!(
+            transaction.start_abort(),
+            Err(Error::ProducerFenced)
+        ));
+    }
+
+    #[test]
+    fn coordinator_errors_fence_producer() {
+        let mut transaction = transaction_manager();
+        transaction.begin().unwrap();
+        let result: Result<(), Error> =
+            transaction.check_fenced(Err(ErrorCode::InvalidProducerEpoch.into()));
+        assert!(matches!(result, Err(Error::ProducerFenced)));
+        assert_eq!(transaction.state(), TransactionState::Fenced);
+    }
+
+    #[test]
+    fn offsets_of_removed_group_member_abort_transaction() {
+        let mut transaction = transaction_manager();
+        transaction.begin().unwrap();
+        let result: Result<(), Error> =
+            transaction.check_group_member(Err(ErrorCode::IllegalGeneration.into()), &group());
+        assert!(matches!(
+            result,
+            Err(Error::ErrorResponse(ErrorCode::IllegalGeneration, _))
+        ));
+        assert_eq!(transaction.state(), TransactionState::AbortableError);
+        transaction.start_abort().unwrap();
+    }
+}